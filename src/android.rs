@@ -0,0 +1,79 @@
+//! A sink that forwards records to Android's logcat. Requires the `android` feature and an
+//! Android target.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use logosaurus::android::AndroidLogger;
+//!
+//! log::set_boxed_logger(Box::new(AndroidLogger::new("myapp"))).unwrap();
+//! log::set_max_level(log::LevelFilter::Debug);
+//! ```
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+
+#[link(name = "log")]
+extern "C" {
+    fn __android_log_write(prio: c_int, tag: *const c_char, text: *const c_char) -> c_int;
+}
+
+/// Forwards records to logcat through `__android_log_write`. See the
+/// [module documentation](index.html).
+///
+/// Log levels are mapped to the corresponding logcat priorities, with `Trace` mapped to
+/// `VERBOSE`.
+pub struct AndroidLogger {
+    tag: CString,
+    level: log::LevelFilter,
+}
+
+impl AndroidLogger {
+    /// Returns an `AndroidLogger` that logs with the given tag. This is typically the prefix
+    /// the program would use with a `Logger`.
+    pub fn new(tag: &str) -> AndroidLogger {
+        AndroidLogger {
+            tag: c_string(tag),
+            level: log::LevelFilter::Trace,
+        }
+    }
+
+    /// Set the allowed log level.
+    pub fn set_level(mut self, level: log::LevelFilter) -> AndroidLogger {
+        self.level = level;
+        self
+    }
+}
+
+impl log::Log for AndroidLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let msg = c_string(&record.args().to_string());
+        // SAFETY: both pointers are valid NUL-terminated strings that outlive the call.
+        unsafe {
+            __android_log_write(priority(record.level()), self.tag.as_ptr(), msg.as_ptr());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn priority(level: log::Level) -> c_int {
+    match level {
+        log::Level::Error => 6,
+        log::Level::Warn => 5,
+        log::Level::Info => 4,
+        log::Level::Debug => 3,
+        log::Level::Trace => 2,
+    }
+}
+
+// Converts `s` to a C string, dropping any interior NUL bytes.
+fn c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).unwrap_or_default()
+}
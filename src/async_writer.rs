@@ -0,0 +1,483 @@
+use crate::status::Health;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread;
+
+enum Msg {
+    Data(Vec<u8>),
+    Flush(mpsc::Sender<()>),
+}
+
+/// What an [`AsyncWriter`] does with a write when its queue is full.
+///
+/// [`AsyncWriter`]: struct.AsyncWriter.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Block the writing thread until there is room in the queue. This is the default.
+    Block,
+    /// Discard the incoming write.
+    DropNewest,
+    /// Discard the oldest queued write to make room for the incoming write.
+    DropOldest,
+}
+
+struct State {
+    queue: VecDeque<Msg>,
+    closed: bool,
+    // Set while the process prepares to fork. The background thread sets `idle` once it has
+    // stopped processing the queue.
+    paused: bool,
+    idle: bool,
+    // Set when the background thread has exited or panicked.
+    dead: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    idle: Condvar,
+    dropped: AtomicU64,
+    // The errors and successful writes of the inner writer, for `Logger::status`.
+    health: Health,
+    // The most recent error of the inner writer, returned by the next `write` or `flush`.
+    error: Mutex<Option<io::Error>>,
+    // The writer is kept here rather than on the background thread, so that the thread can be
+    // restarted in a forked child.
+    writer: Mutex<Box<dyn Write + Send>>,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+// All live writers, so that their background threads can be paused and restarted around a fork.
+static WRITERS: Mutex<Vec<Weak<Shared>>> = Mutex::new(Vec::new());
+
+/// A writer that performs the actual I/O on a dedicated background thread.
+///
+/// Writes are copied into a bounded queue and return immediately, so a slow destination (a
+/// congested pipe, a slow disk) does not block the logging call site, nor does it hold up other
+/// threads waiting on the [`Logger`]'s lock. A `Logger` hands each formatted record to its
+/// writer in a single call, so every queued entry is a whole record.
+///
+/// When the queue is full, writes block until the background thread catches up. Use
+/// `set_backpressure` to drop records instead; the number of dropped writes is available
+/// through an [`AsyncHandle`].
+///
+/// Calling `flush` waits until all previously queued data has been written and the underlying
+/// writer has been flushed, and returns the error of the flush, if any. An error of a queued
+/// write is returned by the next call to `write` or `flush`, whose data is then not queued, so
+/// that a `Logger` counts and reports it. Writes fail once the background thread has exited,
+/// for example because the inner writer panicked. Dropping an `AsyncWriter` writes out any
+/// queued data and stops the background thread.
+///
+/// # Example
+///
+/// ```
+/// use logosaurus::{AsyncWriter, Backpressure, Logger};
+/// use std::io;
+///
+/// let w = AsyncWriter::new(io::stderr(), 1024).set_backpressure(Backpressure::DropOldest);
+/// let handle = w.handle();
+/// let logger = Logger::builder().set_out(w).build();
+///
+/// // ...
+/// assert_eq!(handle.dropped(), 0);
+/// ```
+///
+/// [`Logger`]: struct.Logger.html
+/// [`AsyncHandle`]: struct.AsyncHandle.html
+pub struct AsyncWriter {
+    shared: Arc<Shared>,
+    capacity: usize,
+    backpressure: Backpressure,
+}
+
+/// A handle for observing an [`AsyncWriter`] after it has been moved into a `Logger`.
+///
+/// [`AsyncWriter`]: struct.AsyncWriter.html
+#[derive(Clone)]
+pub struct AsyncHandle {
+    shared: Arc<Shared>,
+}
+
+impl AsyncHandle {
+    /// Returns the number of writes dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of times writing to or flushing the inner writer has failed.
+    pub fn errors(&self) -> u64 {
+        self.shared.health.errors()
+    }
+
+    pub(crate) fn health(&self) -> &Health {
+        &self.shared.health
+    }
+
+    /// Returns the number of writes currently waiting in the queue.
+    pub fn queue_len(&self) -> usize {
+        lock(&self.shared.state).queue.len()
+    }
+}
+
+impl AsyncWriter {
+    /// Returns an `AsyncWriter` that writes to `w` on a background thread. At most `capacity`
+    /// writes are queued at a time.
+    pub fn new<W: Write + Send + 'static>(w: W, capacity: usize) -> AsyncWriter {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                closed: false,
+                paused: false,
+                idle: false,
+                dead: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            idle: Condvar::new(),
+            dropped: AtomicU64::new(0),
+            health: Health::default(),
+            error: Mutex::new(None),
+            writer: Mutex::new(Box::new(w)),
+            thread: Mutex::new(None),
+        });
+        *lock(&shared.thread) = Some(spawn(Arc::clone(&shared)));
+        let mut writers = lock(&WRITERS);
+        writers.retain(|w| w.strong_count() > 0);
+        writers.push(Arc::downgrade(&shared));
+        AsyncWriter {
+            shared,
+            capacity: capacity.max(1),
+            backpressure: Backpressure::Block,
+        }
+    }
+
+    /// Set the behavior when the queue is full.
+    pub fn set_backpressure(mut self, backpressure: Backpressure) -> AsyncWriter {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Returns a handle that can be used to observe this writer.
+    pub fn handle(&self) -> AsyncHandle {
+        AsyncHandle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    fn push(&self, buf: &[u8]) -> io::Result<()> {
+        let mut state = lock(&self.shared.state);
+        while state.queue.len() >= self.capacity {
+            if state.dead {
+                return Err(disconnected());
+            }
+            match self.backpressure {
+                Backpressure::Block => {
+                    state = self
+                        .shared
+                        .not_full
+                        .wait(state)
+                        .unwrap_or_else(|e| e.into_inner());
+                }
+                Backpressure::DropNewest => {
+                    self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                Backpressure::DropOldest => {
+                    let oldest = state
+                        .queue
+                        .iter()
+                        .position(|msg| matches!(msg, Msg::Data(_)));
+                    match oldest {
+                        Some(i) => {
+                            state.queue.remove(i);
+                            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+        if state.dead {
+            return Err(disconnected());
+        }
+        state.queue.push_back(Msg::Data(buf.to_vec()));
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    // Returns the error of the inner writer that has not been returned yet, if any.
+    fn take_error(&self) -> io::Result<()> {
+        match lock(&self.shared.error).take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+fn lock<T: ?Sized>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn spawn(shared: Arc<Shared>) -> thread::JoinHandle<()> {
+    thread::Builder::new()
+        .name(String::from("logosaurus-writer"))
+        .spawn(move || {
+            let _exit = Exit(&shared);
+            run(&shared)
+        })
+        .expect("failed to spawn writer thread")
+}
+
+// Marks the background thread as dead when it exits, including by panicking, so that writers
+// waiting for room in the queue or for a flush do not wait forever.
+struct Exit<'a>(&'a Shared);
+
+impl Drop for Exit<'_> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.0.state);
+        state.dead = true;
+        // Dropping the pending flush requests wakes up their callers.
+        state.queue.retain(|msg| matches!(msg, Msg::Data(_)));
+        self.0.not_full.notify_all();
+        self.0.idle.notify_all();
+    }
+}
+
+// Records a failed write or flush of the inner writer.
+fn failed(shared: &Shared, e: io::Error) {
+    shared.health.failed(&e);
+    *lock(&shared.error) = Some(e);
+}
+
+fn run(shared: &Shared) {
+    loop {
+        let msg = {
+            let mut state = lock(&shared.state);
+            loop {
+                if state.paused && !state.closed {
+                    state.idle = true;
+                    shared.idle.notify_all();
+                } else if let Some(msg) = state.queue.pop_front() {
+                    break msg;
+                } else if state.closed {
+                    if let Err(e) = lock(&shared.writer).flush() {
+                        failed(shared, e);
+                    }
+                    return;
+                }
+                state = shared
+                    .not_empty
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+        };
+        shared.not_full.notify_one();
+        let mut w = lock(&shared.writer);
+        match msg {
+            Msg::Data(buf) => match w.write_all(&buf) {
+                Ok(()) => shared.health.wrote(buf.len()),
+                Err(e) => failed(shared, e),
+            },
+            Msg::Flush(done) => {
+                if let Err(e) = w.flush() {
+                    failed(shared, e);
+                }
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+fn writers() -> Vec<Arc<Shared>> {
+    lock(&WRITERS).iter().filter_map(Weak::upgrade).collect()
+}
+
+// Pauses the background thread of every writer and waits until none of them is writing.
+pub(crate) fn pause_all() {
+    for shared in writers() {
+        let mut state = lock(&shared.state);
+        if state.closed || state.dead {
+            continue;
+        }
+        state.paused = true;
+        shared.not_empty.notify_one();
+        while !state.idle && !state.dead {
+            state = shared.idle.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+// Resumes the background threads paused by `pause_all`. In a forked child the threads do not
+// exist, so `restart` starts new ones.
+pub(crate) fn resume_all(restart: bool) {
+    for shared in writers() {
+        let mut state = lock(&shared.state);
+        if restart && !state.closed {
+            // The old thread cannot be joined in the child, so its handle is leaked.
+            let old = lock(&shared.thread).replace(spawn(Arc::clone(&shared)));
+            std::mem::forget(old);
+            state.dead = false;
+        }
+        state.paused = false;
+        state.idle = false;
+        shared.not_empty.notify_one();
+    }
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "writer thread is not running")
+}
+
+impl Write for AsyncWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.take_error()?;
+        self.push(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let (done, wait) = mpsc::channel();
+        {
+            // Flush requests are never dropped and do not count against the capacity.
+            let mut state = lock(&self.shared.state);
+            if state.dead {
+                return Err(disconnected());
+            }
+            state.queue.push_back(Msg::Flush(done));
+            self.shared.not_empty.notify_one();
+        }
+        wait.recv().map_err(|_| disconnected())?;
+        self.take_error()
+    }
+}
+
+impl Drop for AsyncWriter {
+    fn drop(&mut self) {
+        // Closing the queue lets the thread drain it and exit.
+        lock(&self.shared.state).closed = true;
+        self.shared.not_empty.notify_one();
+        let handle = lock(&self.shared.thread).take();
+        if let Some(handle) = handle {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Buffer;
+
+    #[test]
+    fn test_async_writer() {
+        let v = Buffer::new();
+        let mut w = AsyncWriter::new(v.clone(), 2);
+
+        for i in 0..10 {
+            writeln!(w, "line {}", i).unwrap();
+        }
+        w.flush().unwrap();
+        assert_eq!(10, v.bytes().split(|b| *b == b'\n').count() - 1);
+
+        writeln!(w, "last").unwrap();
+        drop(w);
+        assert!(v.bytes().ends_with(b"line 9\nlast\n"));
+    }
+
+    #[test]
+    fn test_backpressure() {
+        // Writes wait for `gate`, which is held locked so that the background thread stalls and
+        // the queue fills up.
+        struct Stalled(Arc<Mutex<()>>, Buffer);
+
+        impl Write for Stalled {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let _gate = self.0.lock().unwrap();
+                self.1.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let v = Buffer::new();
+        let gate = Arc::new(Mutex::new(()));
+        let guard = gate.lock().unwrap();
+        let mut w = AsyncWriter::new(Stalled(Arc::clone(&gate), v.clone()), 2)
+            .set_backpressure(Backpressure::DropOldest);
+        let handle = w.handle();
+
+        w.write_all(b"a").unwrap();
+        while handle.queue_len() > 0 {
+            thread::yield_now(); // wait for the thread to pick up "a" and stall on the lock
+        }
+        for b in [b"b", b"c", b"d", b"e"].iter() {
+            w.write_all(*b).unwrap();
+        }
+        assert_eq!(2, handle.dropped());
+        assert_eq!(2, handle.queue_len());
+
+        drop(guard);
+        w.flush().unwrap();
+        assert_eq!(b"ade", v.bytes().as_slice());
+    }
+
+    #[test]
+    fn test_errors() {
+        struct Full;
+
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("no space left on device"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut w = AsyncWriter::new(Full, 4);
+        let handle = w.handle();
+        w.write_all(b"a").unwrap();
+        let err = w.flush().unwrap_err();
+        assert_eq!("no space left on device", err.to_string());
+        assert_eq!(1, handle.errors());
+
+        w.flush().unwrap(); // the error is returned once
+
+        // An error is also returned by the next write.
+        w.write_all(b"b").unwrap();
+        while handle.errors() < 2 {
+            thread::yield_now();
+        }
+        w.write_all(b"c").unwrap_err();
+        w.flush().unwrap();
+    }
+
+    #[test]
+    fn test_dead_thread() {
+        struct Panics;
+
+        impl Write for Panics {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                panic!("writer panicked");
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut w = AsyncWriter::new(Panics, 1);
+        w.write_all(b"a").unwrap();
+        // Once the thread is gone, writes fail rather than waiting for room in the queue.
+        let err = (0..3).find_map(|_| w.write_all(b"b").err()).unwrap();
+        assert_eq!(io::ErrorKind::BrokenPipe, err.kind());
+        assert_eq!(io::ErrorKind::BrokenPipe, w.flush().unwrap_err().kind());
+    }
+}
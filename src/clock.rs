@@ -0,0 +1,74 @@
+use chrono::{DateTime, FixedOffset, Offset, TimeZone, Utc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+#[cfg(test)]
+use std::{cell::Cell, time::Duration};
+
+// The local time zone's offset from UTC in seconds (low 32 bits), and the UTC second it was
+// determined for (high 32 bits, truncated). Zero until the first call to `now`.
+static OFFSET: AtomicU64 = AtomicU64::new(0);
+
+// Returns the current local time.
+//
+// Determining the local offset can read the system's time zone database, so the offset is
+// computed at most once per second and reused in between. A change of the offset, such as the
+// start of daylight saving time, therefore takes effect within a second.
+pub(crate) fn now() -> DateTime<FixedOffset> {
+    let utc = Utc::now();
+    let second = utc.timestamp() as u32;
+    let cached = OFFSET.load(Ordering::Relaxed);
+    let seconds = if cached != 0 && (cached >> 32) as u32 == second {
+        cached as u32 as i32
+    } else {
+        let seconds = chrono::Local
+            .offset_from_utc_datetime(&utc.naive_utc())
+            .fix()
+            .local_minus_utc();
+        OFFSET.store(
+            (u64::from(second) << 32) | u64::from(seconds as u32),
+            Ordering::Relaxed,
+        );
+        seconds
+    };
+    // Offsets from the time zone database are always within a day.
+    let offset = FixedOffset::east_opt(seconds).unwrap_or_else(|| Utc.fix());
+    utc.with_timezone(&offset)
+}
+
+#[cfg(test)]
+thread_local! {
+    // How far `instant` has been moved ahead with `advance` on the current thread.
+    static ADVANCED: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+// Returns the current monotonic time, for rate limits and other intervals.
+//
+// In unit tests, the time can be moved ahead on the current thread with `advance`, so that
+// tests of intervals do not have to sleep.
+pub(crate) fn instant() -> Instant {
+    let now = Instant::now();
+    #[cfg(test)]
+    let now = now + ADVANCED.with(Cell::get);
+    now
+}
+
+// Moves the time returned by `instant` on the current thread ahead by `d`.
+#[cfg(test)]
+pub(crate) fn advance(d: Duration) {
+    ADVANCED.with(|advanced| advanced.set(advanced.get() + d));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_now() {
+        for _ in 0..2 {
+            let now = now();
+            let local = chrono::Local::now();
+            assert_eq!(local.offset().fix(), *now.offset());
+            assert!((local.fixed_offset() - now).num_seconds().abs() < 5);
+        }
+    }
+}
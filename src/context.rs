@@ -0,0 +1,278 @@
+//! Per-thread logging context.
+//!
+//! The context holds fields that are included in every record logged on the thread, similar to
+//! a mapped diagnostic context (MDC). Use [`field`] to add a field for the duration of a scope.
+//!
+//! A scope, entered using [`scope`], marks the start of a unit of work such as handling a
+//! request. While a scope is active, records include an `elapsed` field with the time elapsed
+//! since the innermost scope was entered, so per-request latency is visible on every line
+//! without manual bookkeeping.
+//!
+//! The context is thread-local. To carry it over to another thread, for example when handing
+//! work to a thread pool, capture it with [`Context::current`] and re-enter it on the other
+//! thread with [`Context::scope`].
+//!
+//! # Example
+//!
+//! ```
+//! use log::info;
+//! use logosaurus::context::{self, Context};
+//! use std::thread;
+//!
+//! fn handle_request(id: u64) {
+//!     let _scope = context::scope();
+//!     let _field = context::field("request_id", &id.to_string());
+//!     info!("loaded user"); // ... loaded user request_id=7 elapsed=1.234ms
+//!
+//!     let ctx = Context::current();
+//!     thread::spawn(move || {
+//!         ctx.scope(|| info!("sent email")); // ... sent email request_id=7 elapsed=2.345ms
+//!     });
+//! }
+//! ```
+//!
+//! # Async tasks
+//!
+//! Under an async executor, a task may move between threads at every `.await`, and one thread
+//! interleaves many tasks, so the thread-local context alone is wrong. Wrap the task's future
+//! with [`Context::wrap`] so that the context follows the task instead: the context is entered
+//! whenever the future is polled. This works with any executor.
+//!
+//! Guards returned by [`field`] and [`scope`] cannot be held across an `.await` in a task that
+//! must be `Send`; use [`Context::with_field`] to build the task's context up front instead.
+//!
+//! ```
+//! use log::info;
+//! use logosaurus::context::Context;
+//!
+//! async fn handle_request(id: u64) {
+//!     let ctx = Context::current().with_field("request_id", &id.to_string());
+//!     ctx.wrap(async {
+//!         info!("loading user"); // ... loading user request_id=7
+//!         // some_io().await;
+//!         info!("loaded user"); // ... loaded user request_id=7
+//!     })
+//!     .await
+//! }
+//! ```
+//!
+//! [`field`]: fn.field.html
+//! [`Context::wrap`]: struct.Context.html#method.wrap
+//! [`Context::with_field`]: struct.Context.html#method.with_field
+//! [`scope`]: fn.scope.html
+//! [`Context::current`]: struct.Context.html#method.current
+//! [`Context::scope`]: struct.Context.html#method.scope
+use std::cell::RefCell;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{self, Poll};
+use std::time::Instant;
+
+/// A snapshot of a thread's logging context. See the [module documentation](index.html).
+///
+/// Cloning a `Context` is cheap.
+#[derive(Clone, Default)]
+pub struct Context {
+    inner: Option<Arc<Inner>>,
+}
+
+struct Inner {
+    start: Option<Instant>,
+    fields: Vec<(String, String)>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Context> = RefCell::new(Context::default());
+}
+
+impl Context {
+    /// Returns the current thread's context.
+    pub fn current() -> Context {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Makes this the current thread's context until the returned guard is dropped.
+    pub fn enter(&self) -> ScopeGuard {
+        ScopeGuard {
+            previous: CURRENT.with(|current| current.replace(self.clone())),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Calls `f` with this as the current thread's context.
+    pub fn scope<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let _guard = self.enter();
+        f()
+    }
+
+    /// Returns a copy of this context with the field `key` set to `value`, replacing any
+    /// existing field with the same key.
+    pub fn with_field(&self, key: &str, value: &str) -> Context {
+        self.with(None, Some((key, value)))
+    }
+
+    /// Returns a copy of this context with a new scope entered now. See [`scope`].
+    ///
+    /// [`scope`]: fn.scope.html
+    pub fn with_scope(&self) -> Context {
+        self.with(Some(Instant::now()), None)
+    }
+
+    /// Wraps `fut` so that this context is the current context whenever `fut` is polled, on
+    /// whichever thread that happens.
+    ///
+    /// Changes that `fut` makes to the context while being polled, such as adding a field,
+    /// are carried over to its next poll.
+    pub fn wrap<F: Future>(&self, fut: F) -> WithContext<F> {
+        WithContext {
+            ctx: self.clone(),
+            fut: Box::pin(fut),
+        }
+    }
+
+    fn with(&self, start: Option<Instant>, field: Option<(&str, &str)>) -> Context {
+        let (prev_start, mut fields) = match &self.inner {
+            Some(inner) => (inner.start, inner.fields.clone()),
+            None => (None, Vec::new()),
+        };
+        if let Some((key, value)) = field {
+            fields.retain(|(k, _)| k != key);
+            fields.push((String::from(key), String::from(value)));
+        }
+        Context {
+            inner: Some(Arc::new(Inner {
+                start: start.or(prev_start),
+                fields,
+            })),
+        }
+    }
+}
+
+/// A future with an attached context. Returned by [`Context::wrap`].
+///
+/// [`Context::wrap`]: struct.Context.html#method.wrap
+pub struct WithContext<F: Future> {
+    ctx: Context,
+    fut: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithContext<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<F::Output> {
+        let this = &mut *self;
+        let _guard = this.ctx.enter();
+        let poll = this.fut.as_mut().poll(cx);
+        this.ctx = Context::current();
+        poll
+    }
+}
+
+/// Restores the previous context when dropped.
+#[must_use = "the previous context is restored when the guard is dropped"]
+pub struct ScopeGuard {
+    previous: Context,
+    // The guard must be dropped on the thread that created it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let previous = std::mem::take(&mut self.previous);
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Enters a new scope on the current thread. The scope is exited when the returned guard is
+/// dropped.
+pub fn scope() -> ScopeGuard {
+    Context::current().with_scope().enter()
+}
+
+/// Adds the field `key` with `value` to the current thread's context, replacing any existing
+/// field with the same key. The field is removed when the returned guard is dropped.
+pub fn field(key: &str, value: &str) -> ScopeGuard {
+    Context::current().with_field(key, value).enter()
+}
+
+// Returns the fields contributed by the current thread's context.
+pub(crate) fn fields() -> Vec<(String, String)> {
+    CURRENT.with(|current| match &current.borrow().inner {
+        Some(inner) => {
+            let mut fields = inner.fields.clone();
+            if let Some(start) = inner.start {
+                fields.push((String::from("elapsed"), format!("{:?}", start.elapsed())));
+            }
+            fields
+        }
+        None => Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_scope() {
+        assert!(fields().is_empty());
+        {
+            let _scope = scope();
+            let fields = fields();
+            assert_eq!(1, fields.len());
+            assert_eq!("elapsed", fields[0].0);
+        }
+        assert!(fields().is_empty());
+    }
+
+    #[test]
+    fn test_context() {
+        let _a = field("a", "1");
+        let ctx = {
+            let _b = field("b", "2");
+            let _a = field("a", "3");
+            Context::current()
+        };
+        assert_eq!(vec![(String::from("a"), String::from("1"))], fields());
+
+        let got = thread::spawn(move || ctx.scope(fields)).join().unwrap();
+        let expect = vec![
+            (String::from("b"), String::from("2")),
+            (String::from("a"), String::from("3")),
+        ];
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_wrap() {
+        // A future that is pending on its first poll, and records the fields on each poll.
+        struct Yield(Vec<Vec<(String, String)>>);
+        impl Future for Yield {
+            type Output = Vec<Vec<(String, String)>>;
+            fn poll(mut self: Pin<&mut Self>, _: &mut task::Context<'_>) -> Poll<Self::Output> {
+                self.0.push(fields());
+                if self.0.len() < 2 {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(std::mem::take(&mut self.0))
+                }
+            }
+        }
+
+        let poll = |fut: &mut WithContext<Yield>| {
+            Pin::new(fut).poll(&mut task::Context::from_waker(task::Waker::noop()))
+        };
+        let mut fut = Context::default()
+            .with_field("a", "1")
+            .wrap(Yield(Vec::new()));
+        assert!(poll(&mut fut).is_pending());
+        assert!(fields().is_empty());
+        let got = thread::spawn(move || poll(&mut fut)).join().unwrap();
+
+        let expect = vec![(String::from("a"), String::from("1"))];
+        assert_eq!(Poll::Ready(vec![expect.clone(), expect]), got);
+    }
+}
@@ -0,0 +1,199 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// A writer that writes to a fallback writer when its primary writer fails.
+///
+/// When a write to the primary writer returns an error, the data is written to the fallback
+/// writer instead, and so are subsequent writes. By default the `FallbackWriter` stays on the
+/// fallback writer; with `set_retry_interval`, it tries the primary writer again once the
+/// interval has passed, and switches back to it if the write succeeds.
+///
+/// Losing all records because a network destination is unreachable is usually worse than
+/// writing them somewhere else for a while. A primary writer may have written part of the data
+/// before failing, so a record can appear partially on the primary and in full on the fallback.
+///
+/// # Example
+///
+/// ```no_run
+/// use logosaurus::{FallbackWriter, Logger, TcpWriter};
+/// use std::time::Duration;
+///
+/// # fn main() -> std::io::Result<()> {
+/// // Fail over to stderr once three connection attempts in a row have failed.
+/// let tcp = TcpWriter::new("logs.example.com:5170")?.set_fail_after(3);
+/// let w = FallbackWriter::new(tcp).set_retry_interval(Duration::from_secs(30));
+/// let logger = Logger::builder().set_out(w).build();
+/// # Ok(())
+/// # }
+/// ```
+pub struct FallbackWriter<W: Write> {
+    primary: W,
+    fallback: Box<dyn Write + Send>,
+    retry_interval: Option<Duration>,
+    failed: Option<Instant>, // when the primary writer last failed, while on the fallback
+}
+
+impl<W: Write> FallbackWriter<W> {
+    /// Returns a `FallbackWriter` that writes to `primary`, falling back to stderr.
+    pub fn new(primary: W) -> FallbackWriter<W> {
+        FallbackWriter {
+            primary,
+            fallback: Box::new(io::stderr()),
+            retry_interval: None,
+            failed: None,
+        }
+    }
+
+    /// Set the writer used when the primary writer fails. The default is stderr.
+    pub fn set_fallback<F: Write + Send + 'static>(mut self, w: F) -> FallbackWriter<W> {
+        self.fallback = Box::new(w);
+        self
+    }
+
+    /// Retry the primary writer once `interval` has passed since it last failed. By default the
+    /// primary writer is not retried.
+    pub fn set_retry_interval(mut self, interval: Duration) -> FallbackWriter<W> {
+        self.retry_interval = Some(interval);
+        self
+    }
+
+    /// Reports whether writes currently go to the fallback writer.
+    pub fn is_failed_over(&self) -> bool {
+        self.failed.is_some()
+    }
+
+    fn retry_due(&self) -> bool {
+        match (self.failed, self.retry_interval) {
+            (None, _) => true,
+            (Some(failed), Some(interval)) => failed.elapsed() >= interval,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+impl<W: Write> Write for FallbackWriter<W> {
+    // Writes `buf` in full, to one of the writers.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.retry_due() {
+            match self.primary.write_all(buf) {
+                Ok(()) => {
+                    self.failed = None;
+                    return Ok(buf.len());
+                }
+                Err(_) => self.failed = Some(Instant::now()),
+            }
+        }
+        self.fallback.write_all(buf).map(|_| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.failed.is_some() {
+            self.fallback.flush()
+        } else {
+            self.primary.flush()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Buffer;
+    use crate::TcpWriter;
+    use std::cell::Cell;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::rc::Rc;
+
+    // Fails while `down` is set.
+    struct Flaky {
+        down: Rc<Cell<bool>>,
+        data: Vec<u8>,
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.down.get() {
+                return Err(io::Error::from(io::ErrorKind::ConnectionReset));
+            }
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_fallback() {
+        let down = Rc::new(Cell::new(false));
+        let v = Buffer::new();
+        let mut w = FallbackWriter::new(Flaky {
+            down: Rc::clone(&down),
+            data: Vec::new(),
+        })
+        .set_fallback(v.clone());
+
+        w.write_all(b"one\n").unwrap();
+        down.set(true);
+        w.write_all(b"two\n").unwrap();
+        assert!(w.is_failed_over());
+        down.set(false);
+        w.write_all(b"three\n").unwrap(); // not retried
+        assert_eq!(b"one\n", w.primary.data.as_slice());
+        assert_eq!(b"two\nthree\n", v.bytes().as_slice());
+    }
+
+    #[test]
+    fn test_retry() {
+        let down = Rc::new(Cell::new(true));
+        let v = Buffer::new();
+        let mut w = FallbackWriter::new(Flaky {
+            down: Rc::clone(&down),
+            data: Vec::new(),
+        })
+        .set_fallback(v.clone())
+        .set_retry_interval(Duration::ZERO);
+
+        w.write_all(b"one\n").unwrap();
+        assert!(w.is_failed_over());
+        down.set(false);
+        w.write_all(b"two\n").unwrap();
+        assert!(!w.is_failed_over());
+        assert_eq!(b"two\n", w.primary.data.as_slice());
+        assert_eq!(b"one\n", v.bytes().as_slice());
+    }
+
+    #[test]
+    fn test_tcp_fallback() {
+        // Find a free port, and leave it unbound so that connecting fails.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let tcp = TcpWriter::new(addr)
+            .unwrap()
+            .set_backoff(Duration::ZERO, Duration::ZERO)
+            .set_fail_after(1);
+        let v = Buffer::new();
+        let mut w = FallbackWriter::new(tcp)
+            .set_fallback(v.clone())
+            .set_retry_interval(Duration::ZERO);
+
+        w.write_all(b"one\n").unwrap();
+        assert!(w.is_failed_over());
+        assert_eq!(b"one\n", v.bytes().as_slice());
+
+        let listener = TcpListener::bind(addr).unwrap();
+        w.write_all(b"two\n").unwrap();
+        assert!(!w.is_failed_over());
+        drop(w);
+
+        let mut got = String::new();
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.read_to_string(&mut got).unwrap();
+        assert_eq!("two\n", got);
+        assert_eq!(b"one\n", v.bytes().as_slice());
+    }
+}
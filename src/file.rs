@@ -0,0 +1,246 @@
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// A writer that appends records to a file.
+///
+/// The file is created if it does not exist. By default it is opened when the `FileWriter` is
+/// built; with `set_lazy` it is opened when the first record is written instead, so that a
+/// program that never logs does not leave an empty log file behind.
+///
+/// The file is opened in append mode, and a `Logger` hands each record to its writer in a
+/// single write, so records from several processes appending to the same file usually do not
+/// interleave. A single write can still be split, for example for very long records or on some
+/// network file systems; use `set_lock` to take an exclusive advisory lock on the file around
+/// every write.
+///
+/// Alternatively, `set_exclusive` makes the `FileWriter` the file's only writer. If another
+/// process already writes to the file, for example after the program was accidentally started
+/// twice, the `FileWriter` writes to a file named after the process instead.
+///
+/// # Example
+///
+/// ```no_run
+/// use logosaurus::{FileWriter, Logger};
+///
+/// let w = FileWriter::builder("/var/log/myprogram.log")
+///     .set_lazy(true)
+///     .build()
+///     .unwrap();
+/// let logger = Logger::builder().set_out(w).build();
+/// ```
+pub struct FileWriter {
+    path: PathBuf,
+    file: Option<File>,
+    lock: bool,
+    exclusive: bool,
+}
+
+/// Builds a [`FileWriter`].
+///
+/// [`FileWriter`]: struct.FileWriter.html
+pub struct FileWriterBuilder {
+    path: PathBuf,
+    lazy: bool,
+    lock: bool,
+    exclusive: bool,
+}
+
+impl FileWriterBuilder {
+    /// Open the file when the first record is written, rather than in `build`. If opening the
+    /// file fails, the write returns the error, and the next write tries again.
+    pub fn set_lazy(mut self, lazy: bool) -> FileWriterBuilder {
+        self.lazy = lazy;
+        self
+    }
+
+    /// Take an exclusive advisory lock on the file (`flock` on Unix, `LockFileEx` on Windows)
+    /// around every write, so that processes that also lock the file cannot interleave partial
+    /// records. Writes block while another process holds the lock.
+    pub fn set_lock(mut self, lock: bool) -> FileWriterBuilder {
+        self.lock = lock;
+        self
+    }
+
+    /// Hold an exclusive advisory lock on the file for as long as the `FileWriter` exists. If
+    /// another process holds the lock, append `.<pid>` to the file name, for example
+    /// `myprogram.log.1234`, and write to that file instead. `FileWriter::path` returns the
+    /// path actually used.
+    pub fn set_exclusive(mut self, exclusive: bool) -> FileWriterBuilder {
+        self.exclusive = exclusive;
+        self
+    }
+
+    /// Builds the `FileWriter`, opening the file unless it is lazy.
+    pub fn build(self) -> io::Result<FileWriter> {
+        let mut w = FileWriter {
+            path: self.path,
+            file: None,
+            lock: self.lock,
+            exclusive: self.exclusive,
+        };
+        if !self.lazy {
+            w.file()?;
+        }
+        Ok(w)
+    }
+}
+
+impl FileWriter {
+    /// Returns a builder for a `FileWriter` that appends to the file at `path`.
+    pub fn builder<P: AsRef<Path>>(path: P) -> FileWriterBuilder {
+        FileWriterBuilder {
+            path: path.as_ref().to_path_buf(),
+            lazy: false,
+            lock: false,
+            exclusive: false,
+        }
+    }
+
+    /// Returns the path of the file. With `set_exclusive`, this is the path with the process ID
+    /// appended if the file was locked by another process.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None if self.exclusive => self.open_exclusive()?,
+            None => open(&self.path)?,
+        };
+        Ok(self.file.insert(file))
+    }
+
+    fn open_exclusive(&mut self) -> io::Result<File> {
+        let file = open(&self.path)?;
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        let mut path = OsString::from(&self.path);
+        path.push(format!(".{}", process::id()));
+        let path = PathBuf::from(path);
+        let file = open(&path)?;
+        file.try_lock().map_err(io::Error::from)?;
+        self.path = path;
+        Ok(file)
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+impl Write for FileWriter {
+    // Writes `buf` in full, so that a record is never left partially written.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // An exclusive writer holds the lock already, and unlocking would release it.
+        let lock = self.lock && !self.exclusive;
+        let file = self.file()?;
+        if lock {
+            file.lock()?;
+        }
+        let result = file.write_all(buf);
+        if lock {
+            let _ = file.unlock();
+        }
+        result.map(|_| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_lazy() {
+        let dir = env::temp_dir().join(format!("logosaurus-file-lazy-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+
+        let mut w = FileWriter::builder(&path).set_lazy(true).build().unwrap();
+        w.flush().unwrap();
+        assert!(!path.exists());
+        w.write_all(b"hello\n").unwrap();
+        assert_eq!("hello\n", fs::read_to_string(&path).unwrap());
+
+        let mut w = FileWriter::builder(&path).build().unwrap();
+        w.write_all(b"world\n").unwrap();
+        assert_eq!("hello\nworld\n", fs::read_to_string(&path).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_exclusive() {
+        let dir = env::temp_dir().join(format!("logosaurus-file-exclusive-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+
+        let mut first = FileWriter::builder(&path)
+            .set_exclusive(true)
+            .build()
+            .unwrap();
+        let mut second = FileWriter::builder(&path)
+            .set_exclusive(true)
+            .build()
+            .unwrap();
+        assert_eq!(path, first.path());
+        let fallback = dir.join(format!("app.log.{}", process::id()));
+        assert_eq!(fallback, second.path());
+
+        first.write_all(b"first\n").unwrap();
+        second.write_all(b"second\n").unwrap();
+        assert_eq!("first\n", fs::read_to_string(&path).unwrap());
+        assert_eq!("second\n", fs::read_to_string(&fallback).unwrap());
+
+        // The lock is released when the writer is dropped.
+        drop(first);
+        let third = FileWriter::builder(&path)
+            .set_exclusive(true)
+            .build()
+            .unwrap();
+        assert_eq!(path, third.path());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_lock() {
+        let dir = env::temp_dir().join(format!("logosaurus-file-lock-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+
+        let line = format!("{}\n", "x".repeat(1000));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let mut w = FileWriter::builder(&path).set_lock(true).build().unwrap();
+                let line = line.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..50 {
+                        w.write_all(line.as_bytes()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let got = fs::read_to_string(&path).unwrap();
+        assert_eq!(200, got.lines().count());
+        assert!(got.lines().all(|l| l.len() == 1000));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use crate::redact::Pattern;
+
+// Level directives in the syntax used by env_logger and RUST_LOG: a comma-separated list of
+// `target=level` pairs. A bare level sets the default level, and a bare target enables all
+// levels for that target. Directives that cannot be parsed are ignored.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Directives {
+    pub(crate) default: Option<log::LevelFilter>,
+    pub(crate) targets: Vec<(String, log::LevelFilter)>,
+}
+
+pub(crate) fn parse(spec: &str) -> Directives {
+    let mut directives = Directives::default();
+    for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let mut parts = directive.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        match parts.next().map(str::trim) {
+            Some(level) => {
+                if let Ok(level) = level.parse() {
+                    directives.targets.push((String::from(name), level));
+                }
+            }
+            None => match name.parse() {
+                Ok(level) => directives.default = Some(level),
+                Err(_) => directives
+                    .targets
+                    .push((String::from(name), log::LevelFilter::Trace)),
+            },
+        }
+    }
+    directives
+}
+
+// Returns the level of the most specific directive matching `target`, if any. A directive
+// matches its own target and the modules nested under it: `myapp::db` matches `myapp::db` and
+// `myapp::db::pool`, but not `myapp::dbx`.
+pub(crate) fn target_level(
+    targets: &[(String, log::LevelFilter)],
+    target: &str,
+) -> Option<log::LevelFilter> {
+    targets
+        .iter()
+        .filter(|(name, _)| target_matches(name, target))
+        .max_by_key(|(name, _)| name.len())
+        .map(|(_, level)| *level)
+}
+
+// Reports whether `target` is `name` or a module nested under it.
+pub(crate) fn target_matches(name: &str, target: &str) -> bool {
+    target.starts_with(name)
+        && (target.len() == name.len() || target[name.len()..].starts_with("::"))
+}
+
+// Reports whether `target` passes the allowlist and the denylist. An empty allowlist allows
+// every target.
+pub(crate) fn target_allowed(allow: &[String], deny: &[String], target: &str) -> bool {
+    (allow.is_empty() || allow.iter().any(|name| target_matches(name, target)))
+        && !deny.iter().any(|name| target_matches(name, target))
+}
+
+/// A filter on the message of records, added with `LoggerBuilder::add_message_filter`.
+#[derive(Clone, Debug)]
+pub enum MessageFilter {
+    /// Discard records whose message contains the string.
+    DropContaining(String),
+    /// Discard records whose message matches the pattern.
+    DropMatching(Pattern),
+    /// Keep only records whose message contains the string, or matches another `Keep` filter.
+    KeepContaining(String),
+    /// Keep only records whose message matches the pattern, or matches another `Keep` filter.
+    KeepMatching(Pattern),
+}
+
+impl MessageFilter {
+    fn matches(&self, msg: &str) -> bool {
+        match self {
+            MessageFilter::DropContaining(s) | MessageFilter::KeepContaining(s) => msg.contains(s),
+            MessageFilter::DropMatching(p) | MessageFilter::KeepMatching(p) => p.is_match(msg),
+        }
+    }
+
+    fn is_keep(&self) -> bool {
+        matches!(
+            self,
+            MessageFilter::KeepContaining(_) | MessageFilter::KeepMatching(_)
+        )
+    }
+}
+
+// Reports whether a record with the message `msg` passes `filters`.
+pub(crate) fn keep_message(filters: &[MessageFilter], msg: &str) -> bool {
+    let (keep, drop): (Vec<_>, Vec<_>) = filters.iter().partition(|f| f.is_keep());
+    (keep.is_empty() || keep.iter().any(|f| f.matches(msg))) && !drop.iter().any(|f| f.matches(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::LevelFilter;
+
+    #[test]
+    fn test_parse() {
+        let got = parse("info, hyper=warn,myapp::db=trace,mio,bogus=loud");
+        let expect = Directives {
+            default: Some(LevelFilter::Info),
+            targets: vec![
+                (String::from("hyper"), LevelFilter::Warn),
+                (String::from("myapp::db"), LevelFilter::Trace),
+                (String::from("mio"), LevelFilter::Trace),
+            ],
+        };
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_target_level() {
+        let targets = parse("myapp=info,myapp::db=trace").targets;
+        assert_eq!(Some(LevelFilter::Info), target_level(&targets, "myapp"));
+        assert_eq!(
+            Some(LevelFilter::Trace),
+            target_level(&targets, "myapp::db::pool")
+        );
+        assert_eq!(
+            Some(LevelFilter::Info),
+            target_level(&targets, "myapp::dbx")
+        );
+        assert_eq!(None, target_level(&targets, "myapplication"));
+    }
+
+    #[test]
+    fn test_target_allowed() {
+        let list = |names: &[&str]| names.iter().map(|n| String::from(*n)).collect::<Vec<_>>();
+        assert!(target_allowed(&[], &[], "mio"));
+        assert!(!target_allowed(&[], &list(&["mio"]), "mio::poll"));
+        assert!(target_allowed(&[], &list(&["mio"]), "miox"));
+
+        let allow = list(&["myapp", "tower"]);
+        let deny = list(&["myapp::noisy"]);
+        assert!(target_allowed(&allow, &deny, "myapp::db"));
+        assert!(target_allowed(&allow, &deny, "tower"));
+        assert!(!target_allowed(&allow, &deny, "myapp::noisy::inner"));
+        assert!(!target_allowed(&allow, &deny, "hyper"));
+    }
+
+    #[test]
+    fn test_keep_message() {
+        let drop = [
+            MessageFilter::DropContaining(String::from("harmless")),
+            MessageFilter::DropMatching(Pattern::new(r"^retry \d+$").unwrap()),
+        ];
+        assert!(keep_message(&drop, "hello"));
+        assert!(!keep_message(&drop, "a harmless warning"));
+        assert!(!keep_message(&drop, "retry 3"));
+        assert!(keep_message(&drop, "retry 3 of 5"));
+
+        let keep = [
+            MessageFilter::KeepContaining(String::from("audit")),
+            MessageFilter::KeepMatching(Pattern::new("^user ").unwrap()),
+            MessageFilter::DropContaining(String::from("debug")),
+        ];
+        assert!(keep_message(&keep, "audit: login"));
+        assert!(keep_message(&keep, "user alice"));
+        assert!(!keep_message(&keep, "audit debug"));
+        assert!(!keep_message(&keep, "hello"));
+    }
+}
@@ -0,0 +1,158 @@
+//! Support for forking the process while logging.
+//!
+//! `fork` copies only the calling thread into the child. If another thread holds one of the
+//! logger's locks at that moment, the lock stays held forever in the child, and any background
+//! writer thread simply does not exist there. Output buffered in memory at the time of the fork
+//! is written twice, once by each process.
+//!
+//! To fork safely, call [`prepare_fork`] right before `fork`, and [`parent_after_fork`] or
+//! [`child_after_fork`] right after it in the respective process, for example through
+//! `pthread_atfork` or around a daemonizing `fork` call:
+//!
+//! ```ignore
+//! use logosaurus::fork;
+//!
+//! fork::prepare_fork();
+//! match unsafe { libc::fork() } {
+//!     0 => fork::child_after_fork(),
+//!     _ => fork::parent_after_fork(),
+//! }
+//! ```
+//!
+//! [`prepare_fork`] flushes the global logger, waits for in-progress records to be written, and
+//! blocks further logging until one of the after-fork functions is called. Background threads of
+//! [`AsyncWriter`]s and of loggers with staging enabled are paused in the parent and restarted
+//! in the child.
+//!
+//! Programs that never fork pay little for this: each record marks its thread as writing in a
+//! flag of its own and checks a global flag set while a fork is pending. Neither is shared with
+//! other threads that log, so logging from many threads does not contend on them. Only while a
+//! fork is pending do records wait on a lock.
+//!
+//! [`prepare_fork`]: fn.prepare_fork.html
+//! [`parent_after_fork`]: fn.parent_after_fork.html
+//! [`child_after_fork`]: fn.child_after_fork.html
+//! [`AsyncWriter`]: ../struct.AsyncWriter.html
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::thread;
+
+// Set while a fork is pending.
+static PENDING: AtomicBool = AtomicBool::new(false);
+
+// Held for writing while a fork is pending. Records written meanwhile hold it for reading, so
+// that they wait for the fork to be done.
+static GATE: RwLock<()> = RwLock::new(());
+
+// The `WRITING` flags of all threads that have logged.
+static THREADS: Mutex<Vec<Weak<AtomicBool>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static HELD: RefCell<Option<RwLockWriteGuard<'static, ()>>> = const { RefCell::new(None) };
+    // The number of live `Gate`s on the thread. Only the outermost one marks the thread as
+    // writing or takes the lock, because a logger may be attached as a sink of another logger,
+    // and taking a read lock again while a fork is pending would deadlock.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    // Set while the thread writes a record without holding `GATE`.
+    static WRITING: Arc<AtomicBool> = register();
+}
+
+fn register() -> Arc<AtomicBool> {
+    let writing = Arc::new(AtomicBool::new(false));
+    let mut threads = THREADS.lock().unwrap_or_else(|e| e.into_inner());
+    threads.retain(|w| w.strong_count() > 0);
+    threads.push(Arc::downgrade(&writing));
+    writing
+}
+
+// Prevents the process from forking until it is dropped.
+pub(crate) struct Gate {
+    // Whether the thread's `WRITING` flag was set, rather than the lock taken.
+    writing: bool,
+    _guard: Option<RwLockReadGuard<'static, ()>>,
+}
+
+pub(crate) fn gate() -> Gate {
+    let depth = DEPTH.with(|d| d.replace(d.get() + 1));
+    if depth > 0 {
+        return Gate {
+            writing: false,
+            _guard: None,
+        };
+    }
+    // The flag is set before `PENDING` is read, and `prepare_fork` sets `PENDING` before it
+    // reads the flags, so either this thread sees the pending fork or the fork waits for it.
+    let writing = WRITING
+        .try_with(|writing| {
+            writing.store(true, Ordering::SeqCst);
+            if PENDING.load(Ordering::SeqCst) {
+                writing.store(false, Ordering::SeqCst);
+                false
+            } else {
+                true
+            }
+        })
+        .unwrap_or(false);
+    Gate {
+        writing,
+        _guard: if writing {
+            None
+        } else {
+            Some(GATE.read().unwrap_or_else(|e| e.into_inner()))
+        },
+    }
+}
+
+impl Drop for Gate {
+    fn drop(&mut self) {
+        if self.writing {
+            let _ = WRITING.try_with(|writing| writing.store(false, Ordering::SeqCst));
+        }
+        DEPTH.with(|d| d.set(d.get() - 1));
+    }
+}
+
+/// Prepares the logger for a call to `fork`. See the [module documentation](index.html).
+///
+/// Logging from any thread blocks until [`parent_after_fork`] or [`child_after_fork`] is called
+/// on the thread that called `prepare_fork`. Do not log between the calls.
+///
+/// [`parent_after_fork`]: fn.parent_after_fork.html
+/// [`child_after_fork`]: fn.child_after_fork.html
+pub fn prepare_fork() {
+    log::logger().flush();
+    crate::staging::pause_all();
+    PENDING.store(true, Ordering::SeqCst);
+    let guard = GATE.write().unwrap_or_else(|e| e.into_inner());
+    // Wait for the records that were being written before the fork became pending.
+    let threads: Vec<_> = THREADS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for writing in threads {
+        while writing.load(Ordering::SeqCst) {
+            thread::yield_now();
+        }
+    }
+    crate::async_writer::pause_all();
+    HELD.with(|held| *held.borrow_mut() = Some(guard));
+}
+
+/// Resumes logging in the parent process after a call to `fork`.
+pub fn parent_after_fork() {
+    crate::async_writer::resume_all(false);
+    crate::staging::resume_all(false);
+    PENDING.store(false, Ordering::SeqCst);
+    HELD.with(|held| held.borrow_mut().take());
+}
+
+/// Resumes logging in the child process after a call to `fork`, restarting background threads.
+pub fn child_after_fork() {
+    crate::async_writer::resume_all(true);
+    crate::staging::resume_all(true);
+    PENDING.store(false, Ordering::SeqCst);
+    HELD.with(|held| held.borrow_mut().take());
+}
@@ -0,0 +1,59 @@
+// Matches `s` against the glob `pattern`.
+//
+// `*` matches any sequence of characters other than `/`, `**` matches any sequence of
+// characters including `/`, and `?` matches any single character other than `/`. A `**/`
+// component may also match zero directories, so that `src/**/*.rs` matches `src/lib.rs`.
+pub(crate) fn matches(pattern: &str, s: &str) -> bool {
+    match_bytes(pattern.as_bytes(), s.as_bytes())
+}
+
+fn match_bytes(p: &[u8], s: &[u8]) -> bool {
+    match p.first() {
+        None => s.is_empty(),
+        Some(b'*') if p.get(1) == Some(&b'*') => {
+            let rest = &p[2..];
+            if rest.first() == Some(&b'/') && match_bytes(&rest[1..], s) {
+                return true;
+            }
+            (0..=s.len()).any(|i| match_bytes(rest, &s[i..]))
+        }
+        Some(b'*') => {
+            let rest = &p[1..];
+            for i in 0..=s.len() {
+                if match_bytes(rest, &s[i..]) {
+                    return true;
+                }
+                if i < s.len() && s[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => !s.is_empty() && s[0] != b'/' && match_bytes(&p[1..], &s[1..]),
+        Some(c) => s.first() == Some(c) && match_bytes(&p[1..], &s[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("src/net/**", "src/net/conn.rs"));
+        assert!(matches("src/net/**", "src/net/tls/conn.rs"));
+        assert!(!matches("src/net/**", "src/netx/conn.rs"));
+        assert!(matches("src/*.rs", "src/lib.rs"));
+        assert!(!matches("src/*.rs", "src/net/conn.rs"));
+        assert!(matches("src/**/*.rs", "src/lib.rs"));
+        assert!(matches("src/**/*.rs", "src/net/tls/conn.rs"));
+        assert!(matches(
+            "**/generated.rs",
+            "target/debug/build/generated.rs"
+        ));
+        assert!(matches("src/?.rs", "src/a.rs"));
+        assert!(!matches("src/?.rs", "src/ab.rs"));
+        assert!(matches("src/lib.rs", "src/lib.rs"));
+        assert!(!matches("src/lib.rs", "src/lib.rsx"));
+    }
+}
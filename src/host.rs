@@ -0,0 +1,12 @@
+use std::env;
+use std::fs;
+
+// Returns the machine's hostname, from the `HOSTNAME` environment variable or, on Linux, the
+// kernel.
+pub(crate) fn hostname() -> Option<String> {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
@@ -0,0 +1,56 @@
+//! Generators for per-record IDs. See `LoggerBuilder::set_id_generator`.
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Returns a new [ULID]: 26 characters encoding a 48-bit millisecond timestamp followed by 80
+/// random bits, so that IDs sort by creation time.
+///
+/// The random bits come from the standard library's randomly seeded hasher. They are unique
+/// enough to tell records apart but are not suitable for cryptographic purposes.
+///
+/// [ULID]: https://github.com/ulid/spec
+pub fn ulid() -> String {
+    static STATE: OnceLock<RandomState> = OnceLock::new();
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let state = STATE.get_or_init(RandomState::new);
+    let random = |salt: u64| {
+        let mut h = state.build_hasher();
+        h.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        h.write_u64(salt);
+        h.finish()
+    };
+
+    let value = (u128::from(millis & 0xffff_ffff_ffff) << 80)
+        | (u128::from(random(0) & 0xffff) << 64)
+        | u128::from(random(1));
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD[((value >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ulid() {
+        let a = ulid();
+        let b = ulid();
+        assert_eq!(26, a.len());
+        assert_ne!(a, b);
+        assert!(a.bytes().all(|c| CROCKFORD.contains(&c)));
+        // The timestamp prefix is shared by IDs created within the same millisecond, and
+        // sorts before later ones.
+        assert!(a[..10] <= b[..10]);
+    }
+}
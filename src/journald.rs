@@ -0,0 +1,202 @@
+//! A sink that sends records to the systemd journal using its native protocol. Requires the
+//! `journald` feature and a Unix platform.
+//!
+//! Unlike writing text to stderr under systemd, which produces records with two timestamps and
+//! flattened metadata, each record is sent as a set of journal fields:
+//!   * `MESSAGE`: the message,
+//!   * `PRIORITY`: the log level mapped to a syslog priority,
+//!   * `CODE_FILE`, `CODE_LINE`: the source location, when known,
+//!   * `TARGET`: the record's target, and
+//!   * `SYSLOG_IDENTIFIER`: the program name.
+//!
+//! Fields from the current [`context`] are included as well, with their names converted to
+//! valid journal field names (e.g. `elapsed` becomes `ELAPSED`).
+//!
+//! # Example
+//!
+//! ```no_run
+//! use logosaurus::journald::JournaldLogger;
+//!
+//! log::set_boxed_logger(Box::new(JournaldLogger::new().unwrap())).unwrap();
+//! log::set_max_level(log::LevelFilter::Info);
+//! ```
+//!
+//! [`context`]: ../context/index.html
+use std::env;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+use crate::context;
+
+const SOCKET_PATH: &str = "/run/systemd/journal/socket";
+
+/// Sends records to the systemd journal. See the [module documentation](index.html).
+///
+/// Each record is sent as a single datagram, so records larger than the socket's maximum
+/// datagram size are dropped.
+pub struct JournaldLogger {
+    socket: UnixDatagram,
+    level: log::LevelFilter,
+    identifier: Option<String>,
+}
+
+impl JournaldLogger {
+    /// Connects to the journal's socket at its default location.
+    pub fn new() -> io::Result<JournaldLogger> {
+        JournaldLogger::with_path(SOCKET_PATH)
+    }
+
+    /// Connects to the journal's socket at `path`.
+    pub fn with_path<P: AsRef<Path>>(path: P) -> io::Result<JournaldLogger> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        let identifier = env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.file_name()?.to_string_lossy().into_owned()));
+        Ok(JournaldLogger {
+            socket,
+            level: log::LevelFilter::Trace,
+            identifier,
+        })
+    }
+
+    /// Set the allowed log level.
+    pub fn set_level(mut self, level: log::LevelFilter) -> JournaldLogger {
+        self.level = level;
+        self
+    }
+
+    /// Set the `SYSLOG_IDENTIFIER` field. Defaults to the name of the executable.
+    pub fn set_identifier(mut self, identifier: &str) -> JournaldLogger {
+        self.identifier = Some(String::from(identifier));
+        self
+    }
+
+    fn encode(&self, record: &log::Record) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let msg = record.args().to_string();
+        push_field(&mut buf, "MESSAGE", msg.strip_suffix('\n').unwrap_or(&msg));
+        push_field(&mut buf, "PRIORITY", priority(record.level()));
+        if let Some(file) = record.file() {
+            push_field(&mut buf, "CODE_FILE", file);
+        }
+        if let Some(line) = record.line() {
+            push_field(&mut buf, "CODE_LINE", &line.to_string());
+        }
+        push_field(&mut buf, "TARGET", record.target());
+        if let Some(identifier) = &self.identifier {
+            push_field(&mut buf, "SYSLOG_IDENTIFIER", identifier);
+        }
+        for (key, value) in context::fields() {
+            if let Some(key) = field_name(&key) {
+                push_field(&mut buf, &key, &value);
+            }
+        }
+        buf
+    }
+}
+
+impl log::Log for JournaldLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let _ = self.socket.send(&self.encode(record));
+    }
+
+    fn flush(&self) {}
+}
+
+fn priority(level: log::Level) -> &'static str {
+    match level {
+        log::Level::Error => "3",
+        log::Level::Warn => "4",
+        log::Level::Info => "6",
+        log::Level::Debug | log::Level::Trace => "7",
+    }
+}
+
+// Appends a field in the journal's native format. Values containing newlines use the binary
+// encoding: the name, a newline, the value's length as a little-endian u64, and the value.
+fn push_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+// Converts `key` into a valid journal field name: uppercase ASCII letters, digits and
+// underscores, not starting with an underscore or a digit.
+fn field_name(key: &str) -> Option<String> {
+    let name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = name.trim_start_matches(|c: char| c == '_' || c.is_ascii_digit());
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journald() {
+        let dir = env::temp_dir().join(format!("logosaurus-journald-{}", std::process::id()));
+        let _ = std::fs::create_dir(&dir);
+        let path = dir.join("socket");
+        let _ = std::fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        let journald = JournaldLogger::with_path(&path)
+            .unwrap()
+            .set_identifier("myprog");
+        log::Log::log(
+            &journald,
+            &log::Record::builder()
+                .args(format_args!("two\nlines"))
+                .level(log::Level::Warn)
+                .target("foo")
+                .file(Some("src/a.rs"))
+                .line(Some(9))
+                .build(),
+        );
+
+        let mut buf = [0; 1024];
+        let n = server.recv(&mut buf).unwrap();
+        let mut expect = b"MESSAGE\n".to_vec();
+        expect.extend_from_slice(&9u64.to_le_bytes());
+        expect.extend_from_slice(
+            b"two\nlines\nPRIORITY=4\nCODE_FILE=src/a.rs\nCODE_LINE=9\nTARGET=foo\nSYSLOG_IDENTIFIER=myprog\n",
+        );
+        assert_eq!(expect, &buf[..n]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_field_name() {
+        assert_eq!(Some(String::from("ELAPSED")), field_name("elapsed"));
+        assert_eq!(Some(String::from("REQUEST_ID")), field_name("_request-id"));
+        assert_eq!(None, field_name("__"));
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt::Write;
+
+// Appends `s` to `buf` as a JSON string literal.
+pub(crate) fn push_str(buf: &mut String, s: &str) {
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(buf, "\\u{:04x}", c as u32);
+            }
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+// Incrementally builds a single-line JSON object.
+pub(crate) struct Object<'a> {
+    buf: &'a mut String,
+    empty: bool,
+}
+
+impl<'a> Object<'a> {
+    pub(crate) fn new(buf: &'a mut String) -> Object<'a> {
+        buf.push('{');
+        Object { buf, empty: true }
+    }
+
+    fn key(&mut self, key: &str) {
+        if !self.empty {
+            self.buf.push(',');
+        }
+        self.empty = false;
+        push_str(self.buf, key);
+        self.buf.push(':');
+    }
+
+    pub(crate) fn str(&mut self, key: &str, value: &str) {
+        self.key(key);
+        push_str(self.buf, value);
+    }
+
+    pub(crate) fn raw(&mut self, key: &str, value: &str) {
+        self.key(key);
+        self.buf.push_str(value);
+    }
+
+    pub(crate) fn end(self) {
+        self.buf.push('}');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object() {
+        let mut buf = String::new();
+        let mut obj = Object::new(&mut buf);
+        obj.str("msg", "say \"hi\"\n\t\\ \u{1}");
+        obj.raw("line", "9");
+        obj.end();
+        assert_eq!(r#"{"msg":"say \"hi\"\n\t\\ \u0001","line":9}"#, buf);
+    }
+}
@@ -0,0 +1,62 @@
+//! Kubernetes metadata for enriching records. Requires the `kubernetes` feature.
+//!
+//! Values are read from the environment variables conventionally populated through the
+//! downward API:
+//!
+//! ```yaml
+//! env:
+//!   - name: POD_NAME
+//!     valueFrom: { fieldRef: { fieldPath: metadata.name } }
+//!   - name: POD_NAMESPACE
+//!     valueFrom: { fieldRef: { fieldPath: metadata.namespace } }
+//!   - name: NODE_NAME
+//!     valueFrom: { fieldRef: { fieldPath: spec.nodeName } }
+//! ```
+//!
+//! When these are not set, the pod name falls back to `HOSTNAME` (which Kubernetes sets to the
+//! pod name), and the namespace falls back to the service account's namespace file.
+//!
+//! Use [`LoggerBuilder::add_kubernetes_fields`] to include the metadata in every record.
+//!
+//! [`LoggerBuilder::add_kubernetes_fields`]: ../struct.LoggerBuilder.html#method.add_kubernetes_fields
+use std::env;
+use std::fs;
+
+const NAMESPACE_FILE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+/// Returns the name of the pod, if known.
+pub fn pod_name() -> Option<String> {
+    var("POD_NAME").or_else(|| var("HOSTNAME"))
+}
+
+/// Returns the namespace of the pod, if known.
+pub fn namespace() -> Option<String> {
+    var("POD_NAMESPACE").or_else(|| {
+        fs::read_to_string(NAMESPACE_FILE)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    })
+}
+
+/// Returns the name of the node the pod is scheduled on, if known.
+pub fn node_name() -> Option<String> {
+    var("NODE_NAME")
+}
+
+/// Returns the `pod`, `namespace`, and `node` fields, omitting fields whose value is unknown.
+pub fn fields() -> Vec<(String, String)> {
+    let fields = [
+        ("pod", pod_name()),
+        ("namespace", namespace()),
+        ("node", node_name()),
+    ];
+    fields
+        .iter()
+        .filter_map(|(k, v)| v.as_ref().map(|v| (k.to_string(), v.clone())))
+        .collect()
+}
+
+fn var(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.is_empty())
+}
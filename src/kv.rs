@@ -0,0 +1,36 @@
+use log::kv::{Error, Key, Value, VisitSource};
+
+struct Collect(Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for Collect {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        self.0.push((String::from(key.as_str()), value.to_string()));
+        Ok(())
+    }
+}
+
+// Returns the record's key-value pairs, with the values formatted using `Display`.
+pub(crate) fn fields(record: &log::Record) -> Vec<(String, String)> {
+    let mut collect = Collect(Vec::new());
+    let _ = record.key_values().visit(&mut collect);
+    collect.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        let kvs = [("user", "alice"), ("attempt", "3")];
+        let record = log::Record::builder()
+            .args(format_args!("login"))
+            .key_values(&kvs)
+            .build();
+        let expect = vec![
+            (String::from("user"), String::from("alice")),
+            (String::from("attempt"), String::from("3")),
+        ];
+        assert_eq!(expect, fields(&record));
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const LEVELS: [log::LevelFilter; 6] = [
+    log::LevelFilter::Off,
+    log::LevelFilter::Error,
+    log::LevelFilter::Warn,
+    log::LevelFilter::Info,
+    log::LevelFilter::Debug,
+    log::LevelFilter::Trace,
+];
+
+// A level that can be changed while the logger is in use.
+pub(crate) struct AtomicLevel(AtomicUsize);
+
+impl AtomicLevel {
+    pub(crate) fn new(level: log::LevelFilter) -> AtomicLevel {
+        AtomicLevel(AtomicUsize::new(level as usize))
+    }
+
+    pub(crate) fn load(&self) -> log::LevelFilter {
+        LEVELS[self.0.load(Ordering::Relaxed)]
+    }
+
+    pub(crate) fn store(&self, level: log::LevelFilter) {
+        self.0.store(level as usize, Ordering::Relaxed);
+    }
+}
+
+/// A handle for changing the level of the global logger at runtime. Returned by [`init`].
+///
+/// Changing the level takes effect immediately for all threads, and also updates the `log`
+/// crate's maximum level, so that records that were previously filtered out by the `log` macros
+/// are passed to the logger.
+///
+/// ```
+/// use logosaurus::Logger;
+///
+/// let handle = logosaurus::init(Logger::builder()
+///     .set_level(log::LevelFilter::Info)
+///     .build()).unwrap();
+///
+/// // Temporarily enable debugging output, for example from an admin endpoint.
+/// handle.set_level(log::LevelFilter::Trace);
+/// ```
+///
+/// [`init`]: fn.init.html
+#[derive(Clone)]
+pub struct LevelHandle {
+    level: Arc<AtomicLevel>,
+    // The most verbose level of the file and target levels, which are not changed by the
+    // handle but still determine the `log` crate's maximum level.
+    directive_level: log::LevelFilter,
+}
+
+impl LevelHandle {
+    pub(crate) fn new(level: Arc<AtomicLevel>, directive_level: log::LevelFilter) -> LevelHandle {
+        LevelHandle {
+            level,
+            directive_level,
+        }
+    }
+
+    /// Returns the logger's current level.
+    pub fn level(&self) -> log::LevelFilter {
+        self.level.load()
+    }
+
+    /// Set the logger's level. Levels set for specific files or targets are not affected.
+    pub fn set_level(&self, level: log::LevelFilter) {
+        self.level.store(level);
+        log::set_max_level(std::cmp::max(level, self.directive_level));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_level() {
+        let level = AtomicLevel::new(log::LevelFilter::Warn);
+        assert_eq!(log::LevelFilter::Warn, level.load());
+        for l in LEVELS.iter() {
+            level.store(*l);
+            assert_eq!(*l, level.load());
+        }
+    }
+}
@@ -5,7 +5,7 @@
 //! [`init`] to globally initialize a logger with the `log` crate.
 //!
 //! Every log message is output on a separate line: if the message being printed does not end in a
-//! newline, the logger will add one.
+//! newline, the logger will add one. This can be disabled with `LoggerBuilder::set_newline`.
 //!
 //! The default logger writes logs to stderr using this format:
 //! ```txt
@@ -34,7 +34,8 @@
 //! use std::io;
 //!
 //! fn main() {
-//!   let logger = Logger::builder(io::stdout())
+//!   let logger = Logger::builder()
+//!                   .set_out(io::stdout())
 //!                   .set_level(log::LevelFilter::Debug)
 //!                   .set_flags(L_STD | L_SHORT_FILE | L_MICROSECONDS)
 //!                   .set_prefix("myprogram: ")
@@ -48,18 +49,22 @@
 //! [`log`]: https://crates.io/crates/log
 //! [`Logger`]: struct.Logger.html
 //! [`init`]: fn.init.html
-use chrono::{self, Timelike};
-use log;
+use chrono::{self, Datelike, Timelike};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::env;
 use std::fmt;
 use std::io::{self, Write};
-use std::path;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock, RwLockReadGuard};
+use std::time;
 
 /// Formatting flags for the header in log output.
 /// See the `L_*` constants.
 ///
-/// With the exception of the `L_MSG_PREFIX` flag, there is no control over the order that header
-/// text appears, or the format they present (described in the `L*` constants).
+/// The order that header text appears in can be changed with `LoggerBuilder::set_header_order`;
+/// the `L_MSG_PREFIX` flag moves the prefix. The format of each part is described in the `L_*`
+/// constants.
 ///
 /// For example, the `L_DATE | L_TIME` flags produce:
 /// ```txt
@@ -69,29 +74,283 @@ use std::sync::Mutex;
 /// ```txt
 /// INFO  2009/01/23 17:05:23.123123 main.rs:3: message
 /// ```
-pub type Flag = u8;
+///
+/// Flags are combined with `|` and tested with `contains` or `intersects`. They display in
+/// their symbolic form, as produced by [`format_flags`].
+///
+/// [`format_flags`]: fn.format_flags.html
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Flags(u32);
+
+/// The former name of [`Flags`], kept for compatibility.
+///
+/// [`Flags`]: struct.Flags.html
+pub type Flag = Flags;
+
+impl Flags {
+    /// No header.
+    pub const NONE: Flags = Flags(0);
+    /// Date in local time zone: 2009/01/23.
+    pub const DATE: Flags = Flags(1);
+    /// Time in local time zone: 17:05:23.
+    pub const TIME: Flags = Flags(2);
+    /// Microsecond resolution: 17:05:23.023123; assumes `TIME`.
+    pub const MICROSECONDS: Flags = Flags(4);
+    /// Module, file name, and line number: `foo src/file.rs:3`.
+    pub const LONG_FILE: Flags = Flags(8);
+    /// Final file name element and line number: `file.rs:3`.
+    pub const SHORT_FILE: Flags = Flags(16);
+    /// If `DATE` or `TIME` is set, use UTC rather than the local time.
+    pub const UTC: Flags = Flags(32);
+    /// Move the "prefix" from the beginning of the header to the end of the header, just before
+    /// the message.
+    pub const MSG_PREFIX: Flags = Flags(64);
+    /// Log level printed in capitalized form: INFO, TRACE, etc. Padded to width 5.
+    pub const LEVEL: Flags = Flags(128);
+    /// Millisecond resolution: 17:05:23.023; assumes `TIME`. `MICROSECONDS` takes precedence.
+    pub const MILLISECONDS: Flags = Flags(256);
+    /// Nanosecond resolution: 17:05:23.023123456; assumes `TIME`. Takes precedence over
+    /// `MICROSECONDS` and `MILLISECONDS`.
+    pub const NANOSECONDS: Flags = Flags(512);
+    /// Unix epoch seconds instead of the date and time: 1232730323, with a fractional part
+    /// if a resolution flag is set.
+    pub const EPOCH: Flags = Flags(1024);
+    /// Name of the logging thread, or its ID if it is unnamed: `[worker-1]`.
+    pub const THREAD_NAME: Flags = Flags(2048);
+    /// Process ID: `[1234]`.
+    pub const PID: Flags = Flags(4096);
+    /// Hostname of the machine: `host1`.
+    pub const HOSTNAME: Flags = Flags(8192);
+    /// Target of the record, usually the module path: `myapp::db`.
+    pub const TARGET: Flags = Flags(16384);
+    /// Sequence number of the record: `#42`.
+    pub const SEQUENCE: Flags = Flags(32768);
+    /// Initial values for the default logger constructed with `Logger::default()`.
+    pub const STD: Flags = Flags(1 | 2 | 128);
+
+    /// Returns the raw bits of the flags.
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns the flags for `bits`, ignoring bits that do not correspond to a flag.
+    pub const fn from_bits_truncate(bits: u32) -> Flags {
+        Flags(bits & Flags::ALL.0)
+    }
+
+    /// Returns true if no flags are set.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if all flags in `other` are set.
+    pub const fn contains(self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if any flag in `other` is set.
+    pub const fn intersects(self, other: Flags) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    // Every defined flag.
+    const ALL: Flags = Flags(0xffff);
+}
+
+impl std::ops::BitOr for Flags {
+    type Output = Flags;
+
+    fn bitor(self, rhs: Flags) -> Flags {
+        Flags(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for Flags {
+    fn bitor_assign(&mut self, rhs: Flags) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl std::ops::BitAnd for Flags {
+    type Output = Flags;
+
+    fn bitand(self, rhs: Flags) -> Flags {
+        Flags(self.0 & rhs.0)
+    }
+}
+
+impl std::ops::BitAndAssign for Flags {
+    fn bitand_assign(&mut self, rhs: Flags) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl std::ops::Not for Flags {
+    type Output = Flags;
+
+    fn not(self) -> Flags {
+        Flags::from_bits_truncate(!self.0)
+    }
+}
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_flags(*self))
+    }
+}
+
+impl fmt::Debug for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Flags({})", format_flags(*self))
+    }
+}
 
 /// No header.
-pub const L_NONE: Flag = 0;
+pub const L_NONE: Flags = Flags::NONE;
 /// Date in local time zone: 2009/01/23.
-pub const L_DATE: Flag = 1;
+pub const L_DATE: Flags = Flags::DATE;
 /// Time in local time zone: 17:05:23.
-pub const L_TIME: Flag = 2;
+pub const L_TIME: Flags = Flags::TIME;
 /// Microsecond resolution: 17:05:23.023123; assumes `L_TIME`.
-pub const L_MICROSECONDS: Flag = 4;
+pub const L_MICROSECONDS: Flags = Flags::MICROSECONDS;
 /// Module, file name, and line number: `foo src/file.rs:3`.
-pub const L_LONG_FILE: Flag = 8;
+pub const L_LONG_FILE: Flags = Flags::LONG_FILE;
 /// Final file name element and line number: `file.rs:3`.
-pub const L_SHORT_FILE: Flag = 16;
+pub const L_SHORT_FILE: Flags = Flags::SHORT_FILE;
 /// If `L_DATE` or `L_TIME` is set, use UTC rather than the local time.
-pub const L_UTC: Flag = 32;
+pub const L_UTC: Flags = Flags::UTC;
 /// Move the "prefix" from the beginning of the header to the end of the header, just before the
 /// message.
-pub const L_MSG_PREFIX: Flag = 64;
+pub const L_MSG_PREFIX: Flags = Flags::MSG_PREFIX;
 /// Log level printed in capitalized form: INFO, TRACE, etc. Padded to width 5.
-pub const L_LEVEL: Flag = 128;
+pub const L_LEVEL: Flags = Flags::LEVEL;
+/// Millisecond resolution: 17:05:23.023; assumes `L_TIME`. `L_MICROSECONDS` takes precedence.
+pub const L_MILLISECONDS: Flags = Flags::MILLISECONDS;
+/// Nanosecond resolution: 17:05:23.023123456; assumes `L_TIME`. Takes precedence over
+/// `L_MICROSECONDS` and `L_MILLISECONDS`, so that setting it on top of either gives the finest
+/// resolution.
+pub const L_NANOSECONDS: Flags = Flags::NANOSECONDS;
+/// Unix epoch seconds instead of the date and time: 1232730323. With `L_MILLISECONDS`,
+/// `L_MICROSECONDS` or `L_NANOSECONDS`, the fractional part is included: 1232730323.023.
+/// `L_DATE`, `L_TIME` and `L_UTC` have no effect when this flag is set.
+pub const L_EPOCH: Flags = Flags::EPOCH;
+/// Name of the logging thread, in brackets: `[worker-1]`. Unnamed threads are shown by their
+/// ID: `[ThreadId(3)]`.
+pub const L_THREAD_NAME: Flags = Flags::THREAD_NAME;
+/// Process ID, in brackets: `[1234]`. It is read for every record, so that a forked child
+/// shows its own ID.
+pub const L_PID: Flags = Flags::PID;
+/// Hostname of the machine: `host1`. It is resolved when the logger is built, from the
+/// `HOSTNAME` environment variable or, on Linux, the kernel, and is `-` if it is unknown.
+pub const L_HOSTNAME: Flags = Flags::HOSTNAME;
+/// Target of the record, usually the module path, without the file name: `myapp::db: `. With
+/// `L_SHORT_FILE`, the target precedes the file name as with `L_LONG_FILE`: `myapp::db
+/// db.rs:3: `.
+pub const L_TARGET: Flags = Flags::TARGET;
+/// Sequence number of the record: `#42`. Each logger numbers the records it writes with this
+/// flag from 0, in the order they are formatted, so that gaps reveal lost records. Clones and
+/// children share the counter. The number wraps to 0 after `u64::MAX`.
+pub const L_SEQUENCE: Flags = Flags::SEQUENCE;
 /// Initial values for the default logger constructed with `Logger::default()`.
-pub const L_STD: Flag = L_DATE | L_TIME | L_LEVEL;
+pub const L_STD: Flags = Flags::STD;
+
+// The individual flags, in bit order, with their names.
+const FLAG_NAMES: [(Flag, &str); 16] = [
+    (L_DATE, "L_DATE"),
+    (L_TIME, "L_TIME"),
+    (L_MICROSECONDS, "L_MICROSECONDS"),
+    (L_LONG_FILE, "L_LONG_FILE"),
+    (L_SHORT_FILE, "L_SHORT_FILE"),
+    (L_UTC, "L_UTC"),
+    (L_MSG_PREFIX, "L_MSG_PREFIX"),
+    (L_LEVEL, "L_LEVEL"),
+    (L_MILLISECONDS, "L_MILLISECONDS"),
+    (L_NANOSECONDS, "L_NANOSECONDS"),
+    (L_EPOCH, "L_EPOCH"),
+    (L_THREAD_NAME, "L_THREAD_NAME"),
+    (L_PID, "L_PID"),
+    (L_HOSTNAME, "L_HOSTNAME"),
+    (L_TARGET, "L_TARGET"),
+    (L_SEQUENCE, "L_SEQUENCE"),
+];
+
+// The flags that add a time of day to the header.
+const TIME_FLAGS: Flags = Flags(L_TIME.0 | L_MILLISECONDS.0 | L_MICROSECONDS.0 | L_NANOSECONDS.0);
+
+/// Returns the symbolic form of `flag`, such as `L_DATE | L_TIME | L_LEVEL`, or `L_NONE` if no
+/// flags are set. The result can be parsed back with [`parse_flags`].
+///
+/// [`parse_flags`]: fn.parse_flags.html
+pub fn format_flags(flag: Flag) -> String {
+    let names: Vec<&str> = FLAG_NAMES
+        .iter()
+        .filter(|(f, _)| flag.contains(*f))
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() {
+        String::from("L_NONE")
+    } else {
+        names.join(" | ")
+    }
+}
+
+/// Parses flags from their symbolic form, such as `L_STD|L_SHORT_FILE`, so that flags can be
+/// read from configuration files. The names of the `L_*` constants are accepted, separated by
+/// `|` and optional whitespace.
+pub fn parse_flags(s: &str) -> Result<Flag, ParseFlagsError> {
+    s.split('|').map(str::trim).try_fold(L_NONE, |acc, name| {
+        let flag = match name {
+            "L_NONE" => L_NONE,
+            "L_STD" => L_STD,
+            _ => FLAG_NAMES
+                .iter()
+                .find(|(_, n)| *n == name)
+                .map(|(f, _)| *f)
+                .ok_or_else(|| ParseFlagsError {
+                    name: String::from(name),
+                })?,
+        };
+        Ok(acc | flag)
+    })
+}
+
+/// Converts the flags of Go's log package, such as `log.LstdFlags|log.Lshortfile` (19), to
+/// logosaurus flags, so that configurations ported from Go programs produce the same headers.
+///
+/// Go's flags from `Ldate` (1) through `Lmsgprefix` (64) have the same values as the
+/// corresponding `L_*` constants. Other bits, including 128, which is `L_LEVEL` in logosaurus
+/// but not a Go flag, are ignored. As in Go, `Lshortfile` overrides `Llongfile`.
+///
+/// Note that with `L_LONG_FILE`, logosaurus also prints the record's target.
+///
+/// ```
+/// use logosaurus::{from_go_flags, L_DATE, L_SHORT_FILE, L_TIME};
+///
+/// assert_eq!(L_DATE | L_TIME | L_SHORT_FILE, from_go_flags(3 | 16));
+/// ```
+pub fn from_go_flags(flags: u32) -> Flag {
+    let mut flag = Flags::from_bits_truncate(flags & 0x7f);
+    if flag.contains(L_SHORT_FILE) {
+        flag &= !L_LONG_FILE;
+    }
+    flag
+}
+
+/// The error returned by [`parse_flags`] for an unknown flag name.
+///
+/// [`parse_flags`]: fn.parse_flags.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseFlagsError {
+    name: String,
+}
+
+impl fmt::Display for ParseFlagsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown flag {:?}", self.name)
+    }
+}
+
+impl std::error::Error for ParseFlagsError {}
 
 // TODO: https://doc.rust-lang.org/beta/unstable-book/language-features/trait-alias.html
 // Rewrite as trait alias when stable.
@@ -99,7 +358,7 @@ pub const L_STD: Flag = L_DATE | L_TIME | L_LEVEL;
 
 /// Builder for [`Logger`].
 ///
-/// Use `Logger:builder()` to obtain a `LoggerBuilder`.
+/// Use `Logger::builder()` to obtain a `LoggerBuilder`.
 ///
 /// Unmodified or unset values in the builder will default to the values used by
 /// [`Logger::default()`].
@@ -111,8 +370,8 @@ pub const L_STD: Flag = L_DATE | L_TIME | L_LEVEL;
 /// use logosaurus::{Logger, L_STD, L_SHORT_FILE};
 /// use std::io;
 ///
-/// let mut builder = Logger::builder(io::stdout());
-/// let logger = builder
+/// let logger = Logger::builder()
+///                 .set_out(io::stdout())
 ///                 .set_level(log::LevelFilter::Debug)
 ///                 .set_flags(L_STD | L_SHORT_FILE)
 ///                 .set_prefix("myprogram: ")
@@ -123,335 +382,4676 @@ pub const L_STD: Flag = L_DATE | L_TIME | L_LEVEL;
 /// [`Logger::default()`]: struct.Logger.html#impl-Default
 pub struct LoggerBuilder<W: Write + Send> {
     level: log::LevelFilter,
-    out: Option<W>,
+    out: W,
     flag: Flag,
     prefix: String,
+    overflow: Option<(usize, Box<dyn Write + Send>)>,
+    buffer: Option<(usize, FlushPolicy)>,
+    file_levels: Vec<(String, log::LevelFilter)>,
+    flush_level: Option<log::Level>,
+    fields: Vec<(String, String)>,
+    format: Format,
+    newline: bool,
+    multiline: Multiline,
+    escape_control: bool,
+    max_message_len: Option<usize>,
+    crlf: bool,
+    rate_limits: Vec<(log::Level, u32)>,
+    repeat_window: Option<time::Duration>,
+    site_rate_limit: Option<(u32, time::Duration)>,
+    sample_rates: [u32; 5],
+    filter: Option<RecordFilter>,
+    message_filters: Vec<MessageFilter>,
+    middleware: Vec<middleware::Middleware>,
+    redactions: Vec<redact::Pattern>,
+    id_generator: Option<IdGenerator>,
+    target_levels: Vec<(String, log::LevelFilter)>,
+    allowed_targets: Vec<String>,
+    denied_targets: Vec<String>,
+    target_fields: Vec<(String, String, String)>,
+    prefix_routes: Vec<(String, Box<dyn log::Log>)>,
+    error_hook: Option<ErrorHook>,
+    async_handle: Option<AsyncHandle>,
+    staging: Option<(time::Duration, StagingFactory<W>, staging::Worker)>,
+    timezone: Option<TimeZoneFn>,
+    level_labels: Option<[String; 5]>,
+    level_case: Option<LevelCase>,
+    level_width: Option<usize>,
+    level_prefixes: [String; 5],
+    header_order: Vec<HeaderField>,
+    location_width: usize,
 }
 
 impl<W: Write + Send> LoggerBuilder<W> {
+    /// Set the output to `w`, boxed, so that the output can be chosen at runtime without a
+    /// generic parameter for the writer type. The resulting logger is a
+    /// `Logger<Box<dyn Write + Send>>`.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    /// use std::io::{self, Write};
+    ///
+    /// fn logger(quiet: bool) -> Logger<Box<dyn Write + Send>> {
+    ///     let builder = Logger::builder();
+    ///     if quiet {
+    ///         builder.set_boxed_out(io::sink()).build()
+    ///     } else {
+    ///         builder.set_boxed_out(io::stderr()).build()
+    ///     }
+    /// }
+    /// ```
+    pub fn set_boxed_out<V: Write + Send + 'static>(
+        self,
+        w: V,
+    ) -> LoggerBuilder<Box<dyn Write + Send>> {
+        self.set_out(Box::new(w))
+    }
+
+    /// Set the output that the logger writes to. The default is stderr. Other settings,
+    /// including staging, are kept.
+    pub fn set_out<V: Write + Send + 'static>(self, w: V) -> LoggerBuilder<V> {
+        LoggerBuilder {
+            level: self.level,
+            out: w,
+            flag: self.flag,
+            prefix: self.prefix,
+            overflow: self.overflow,
+            buffer: self.buffer,
+            file_levels: self.file_levels,
+            flush_level: self.flush_level,
+            fields: self.fields,
+            format: self.format,
+            newline: self.newline,
+            multiline: self.multiline,
+            escape_control: self.escape_control,
+            max_message_len: self.max_message_len,
+            crlf: self.crlf,
+            rate_limits: self.rate_limits,
+            repeat_window: self.repeat_window,
+            site_rate_limit: self.site_rate_limit,
+            sample_rates: self.sample_rates,
+            filter: self.filter,
+            message_filters: self.message_filters,
+            middleware: self.middleware,
+            redactions: self.redactions,
+            id_generator: self.id_generator,
+            target_levels: self.target_levels,
+            allowed_targets: self.allowed_targets,
+            denied_targets: self.denied_targets,
+            target_fields: self.target_fields,
+            prefix_routes: self.prefix_routes,
+            error_hook: self.error_hook,
+            async_handle: self.async_handle,
+            staging: self
+                .staging
+                .map(|(interval, _, worker)| (interval, new_stager as StagingFactory<V>, worker)),
+            timezone: self.timezone,
+            level_labels: self.level_labels,
+            level_case: self.level_case,
+            level_width: self.level_width,
+            level_prefixes: self.level_prefixes,
+            header_order: self.header_order,
+            location_width: self.location_width,
+        }
+    }
+
     /// Set the allowed log level.
     pub fn set_level(mut self, level: log::LevelFilter) -> LoggerBuilder<W> {
         self.level = level;
         self
     }
 
-    /// Set the formatting flags.
-    pub fn set_flags(mut self, flag: Flag) -> LoggerBuilder<W> {
-        self.flag = flag;
+    /// Set the allowed log levels per target using directives like those used by `env_logger`,
+    /// for example `"hyper=warn,myapp::db=trace"`.
+    ///
+    /// Directives are separated by commas. A `target=level` directive sets the level for the
+    /// target and the modules nested under it; the most specific matching directive wins. A
+    /// bare level, such as `info`, sets the default level like `set_level` does, and a bare
+    /// target enables all levels for that target. Directives that cannot be parsed are ignored.
+    ///
+    /// Levels set with `set_file_level` take precedence over target levels.
+    pub fn set_target_levels(mut self, directives: &str) -> LoggerBuilder<W> {
+        let directives = filter::parse(directives);
+        if let Some(level) = directives.default {
+            self.level = level;
+        }
+        self.target_levels = directives.targets;
         self
     }
 
-    /// Set the prefix.
-    pub fn set_prefix(mut self, prefix: &str) -> LoggerBuilder<W> {
-        self.prefix = String::from(prefix);
+    /// Add a target to the allowlist. Once a target is allowed, only records whose target is an
+    /// allowed target, or a module nested under one, are logged: `myapp` allows `myapp` and
+    /// `myapp::db`, but not `myapp_cli`.
+    ///
+    /// This is coarser than `set_target_levels` but cheaper, since it needs no level lookup.
+    pub fn add_allowed_target(mut self, target: &str) -> LoggerBuilder<W> {
+        self.allowed_targets.push(String::from(target));
         self
     }
 
-    /// Construct a `Logger` from this `LoggerBuilder`. Consumes the
-    /// `LoggerBuilder`.
-    pub fn build(mut self) -> Logger<W> {
-        Logger {
-            level: self.level,
-            out: Mutex::new(self.out.take().unwrap()),
-            flag: self.flag,
-            prefix: self.prefix.clone(),
-        }
+    /// Add a target to the denylist. Records whose target is a denied target, or a module
+    /// nested under one, are not logged, even if the target is also allowed.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    ///
+    /// let logger = Logger::builder()
+    ///     .add_denied_target("mio")
+    ///     .add_denied_target("want")
+    ///     .build();
+    /// ```
+    pub fn add_denied_target(mut self, target: &str) -> LoggerBuilder<W> {
+        self.denied_targets.push(String::from(target));
+        self
     }
-}
-
-/// Represents a logging object that writes to a specified output. It can be used simultaneously
-/// from multiple threads; it guarantees to serialize writes.
-///
-/// Use [`LoggerBuilder`] to construct a `Logger`, or use `Logger::default()`.
-///
-/// [`LoggerBuilder`]: struct.LoggerBuilder.html
-pub struct Logger<W: Write + Send> {
-    level: log::LevelFilter,
-    out: Mutex<W>,
-    flag: Flag,
-    prefix: String,
-}
-
-/// Initialize the logger to use with the [`log`] crate.
-///
-/// ```
-/// use log::{debug};
-///
-/// fn main() {
-///   logosaurus::init(logosaurus::Logger::default()).unwrap();
-///   debug!("hello, world");
-/// }
-/// ```
-//
-/// See [`LoggerBuilder`] to create a custom logger.
-///
-/// [`log`]: https://crates.io/crates/log
-/// [`LoggerBuilder`]: struct.LoggerBuilder.html
-pub fn init<W: Write + Send + 'static>(l: Logger<W>) -> Result<(), log::SetLoggerError> {
-    log::set_max_level(l.level);
-    log::set_boxed_logger(Box::new(l))
-}
 
-impl<W: Write + Send> Logger<W> {
-    /// Returns a `LoggerBuilder` that can be used to build a `Logger`.
-    pub fn builder(w: W) -> LoggerBuilder<W> {
-        LoggerBuilder {
-            level: log::LevelFilter::Trace,
-            out: Some(w),
-            flag: L_STD,
-            prefix: String::from(""),
-        }
+    /// Set the allowed log level for records logged from source files matching the glob
+    /// `pattern`, overriding the level set with `set_level`.
+    ///
+    /// In the pattern, `*` matches any sequence of characters other than `/`, `**` matches any
+    /// sequence of characters including `/`, and `?` matches any single character other than
+    /// `/`. Patterns are matched against the whole file path as reported by the `log` macros,
+    /// which is usually relative to the crate root, e.g. `src/net/**`. When several patterns
+    /// match, the one added last wins.
+    ///
+    /// This complements filtering by target, which is unhelpful for generated code and
+    /// macro-heavy crates.
+    pub fn set_file_level(mut self, pattern: &str, level: log::LevelFilter) -> LoggerBuilder<W> {
+        self.file_levels.push((String::from(pattern), level));
+        self
     }
 
-    /// Writes the given string `s` using the logger. Typically, you would not use this directly
-    /// but instead use the macros provided by the `log` crate.
-    pub fn write_output(
-        &self,
-        level: log::Level,
-        target: &str,
-        file: Option<&str>,
-        line: Option<u32>,
-        s: &str,
-    ) {
-        if !self.enabled(level) {
-            return;
-        }
-
-        let now = chrono::offset::Local::now(); // get this early
-        let file = match file {
-            Some(f) => f,
-            None => "???",
-        };
-        let line = match line {
-            Some(n) => n,
-            None => 0,
-        };
-        let h = self.header(target, file, line, level, now);
-        let maybe_newline = if s.ends_with("\n") { "" } else { "\n" };
-
-        let mut out = self.out.lock().unwrap();
-        let _ = write!(out, "{}{}{}", h, s, maybe_newline);
+    /// Set the formatting flags.
+    pub fn set_flags(mut self, flag: Flag) -> LoggerBuilder<W> {
+        self.flag = flag;
+        self
     }
 
-    fn write_record(&self, record: &log::Record) {
-        self.write_output(
-            record.level(),
-            record.target(),
-            record.file(),
-            record.line(),
-            &record.args().to_string(),
-        );
+    /// Set the labels printed for the levels with `L_LEVEL`, in the order error, warn, info,
+    /// debug, trace. The default labels are `ERROR`, `WARN`, `INFO`, `DEBUG` and `TRACE`. Labels
+    /// are padded to the width of the longest label.
+    ///
+    /// The labels are used in the text format only; `Format::Json` always uses the lowercase
+    /// level names.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    ///
+    /// let logger = Logger::builder()
+    ///     .set_level_labels(["ERR", "WRN", "INF", "DBG", "TRC"])
+    ///     .build();
+    /// ```
+    pub fn set_level_labels(mut self, labels: [&str; 5]) -> LoggerBuilder<W> {
+        self.level_labels = Some(labels.map(String::from));
+        self
     }
 
-    fn header<Tz: chrono::TimeZone>(
-        &self,
-        target: &str,
-        file: &str,
-        line: u32,
-        level: log::Level,
-        now: chrono::DateTime<Tz>,
-    ) -> String
-    where
-        Tz::Offset: fmt::Display,
-    {
-        let mut buf = String::new();
-        format_header(
-            &mut buf,
-            target,
-            file,
-            line,
-            level,
-            now,
-            self.flag,
-            &self.prefix,
-        );
-        buf
+    /// Set the capitalization of the level labels printed with `L_LEVEL`. By default, the
+    /// labels are printed as they are, which is uppercase for the default labels.
+    pub fn set_level_case(mut self, case: LevelCase) -> LoggerBuilder<W> {
+        self.level_case = Some(case);
+        self
     }
 
-    fn enabled(&self, incoming_level: log::Level) -> bool {
-        incoming_level <= self.level
+    /// Set the width that level labels are padded to with spaces. By default, labels are padded
+    /// to the width of the longest label. A width of 0 disables the padding, so that the label
+    /// is followed by a single space.
+    ///
+    /// ```
+    /// use logosaurus::{LevelCase, Logger, L_LEVEL};
+    ///
+    /// // "info hello", "error hello"
+    /// let logger = Logger::builder()
+    ///     .set_flags(L_LEVEL)
+    ///     .set_level_case(LevelCase::Lower)
+    ///     .set_level_width(0)
+    ///     .build();
+    /// ```
+    pub fn set_level_width(mut self, width: usize) -> LoggerBuilder<W> {
+        self.level_width = Some(width);
+        self
     }
-}
 
-fn format_datetime<Tz: chrono::TimeZone>(buf: &mut String, flag: Flag, now: chrono::DateTime<Tz>)
-where
-    Tz::Offset: fmt::Display,
-{
-    if flag & L_DATE != 0 {
-        buf.push_str(&format!("{} ", now.format("%Y/%m/%d")));
-    }
-    if flag & (L_TIME | L_MICROSECONDS) != 0 {
-        buf.push_str(&format!("{}", now.format("%H:%M:%S")));
-        if flag & L_MICROSECONDS != 0 {
-            let micro = now.nanosecond() / 1000;
-            buf.push_str(&format!(".{:0>wid$}", micro, wid = 6));
-        }
-        buf.push_str(&format!(" "));
+    /// Set the order of the parts of the header in the text format. Parts that are not listed
+    /// follow in the default order, which is the order of the [`HeaderField`] variants. The
+    /// flags still determine which parts are present, and the prefix stays at the beginning or,
+    /// with `L_MSG_PREFIX`, the end of the header.
+    ///
+    /// ```
+    /// use logosaurus::{HeaderField, Logger, L_LEVEL, L_SHORT_FILE, L_TIME};
+    ///
+    /// // 17:05:23 main.rs:3: INFO  message
+    /// let logger = Logger::builder()
+    ///     .set_flags(L_TIME | L_SHORT_FILE | L_LEVEL)
+    ///     .set_header_order(&[HeaderField::Time, HeaderField::Location, HeaderField::Level])
+    ///     .build();
+    /// ```
+    ///
+    /// [`HeaderField`]: enum.HeaderField.html
+    pub fn set_header_order(mut self, order: &[HeaderField]) -> LoggerBuilder<W> {
+        self.header_order = order.to_vec();
+        self
     }
-}
 
-fn format_header<Tz: chrono::TimeZone>(
-    buf: &mut String,
-    target: &str,
-    file: &str,
-    line: u32,
-    level: log::Level,
-    now: chrono::DateTime<Tz>,
-    flag: Flag,
-    prefix: &str,
-) where
-    Tz::Offset: fmt::Display,
-{
-    if flag & L_MSG_PREFIX == 0 {
-        buf.push_str(&format!("{}", prefix));
+    /// Pad the location part of the header, the target, file and line written with
+    /// `L_LONG_FILE`, `L_SHORT_FILE` or `L_TARGET`, with spaces to at least `width` characters,
+    /// so that the parts after it line up across records. Longer locations are not truncated.
+    /// The default width is 0, which disables the padding.
+    ///
+    /// ```txt
+    /// main.rs:12:      starting
+    /// db/pool.rs:340:  connected
+    /// ```
+    pub fn set_location_width(mut self, width: usize) -> LoggerBuilder<W> {
+        self.location_width = width;
+        self
     }
 
-    if flag & L_LEVEL != 0 {
-        buf.push_str(&format!("{: <5} ", level));
+    /// Set the output format.
+    pub fn set_format(mut self, format: Format) -> LoggerBuilder<W> {
+        self.format = format;
+        self
     }
 
-    if flag & (L_DATE | L_TIME | L_MICROSECONDS) != 0 {
-        if flag & L_UTC != 0 {
-            let now = now.with_timezone(&chrono::Utc);
-            format_datetime(buf, flag, now);
-        } else {
-            format_datetime(buf, flag, now);
-        }
+    /// Set whether records are terminated with a newline. Defaults to `true`.
+    ///
+    /// By default, the logger adds a newline to every record that does not already end in one.
+    /// When set to `false`, records are written exactly as formatted, with no newline added;
+    /// this is useful for writers that frame records themselves, for example one datagram or
+    /// one length-prefixed frame per record, where a newline would corrupt the payload.
+    pub fn set_newline(mut self, newline: bool) -> LoggerBuilder<W> {
+        self.newline = newline;
+        self
     }
 
-    if flag & (L_LONG_FILE | L_SHORT_FILE) != 0 {
-        if flag & L_LONG_FILE != 0 {
-            buf.push_str(&format!("{} ", target));
-        }
-
-        // TODO: reduce String::from calls
-        let f = if flag & L_SHORT_FILE != 0 {
-            match path::Path::new(file).file_name() {
-                Some(base) => base.to_string_lossy().into_owned(),
-                None => String::from("???"),
-            }
-        } else {
-            String::from(file)
-        };
-        buf.push_str(&format!("{}:{}: ", f, line));
+    /// Set how messages that contain newlines are written in the text format. The default,
+    /// `Multiline::Keep`, writes them as they are, so that the continuation lines have no header.
+    pub fn set_multiline(mut self, multiline: Multiline) -> LoggerBuilder<W> {
+        self.multiline = multiline;
+        self
     }
 
-    if flag & L_MSG_PREFIX != 0 {
-        buf.push_str(&format!("{}", prefix));
+    /// Set whether control characters in messages and field values are escaped in the text
+    /// format, so that untrusted input cannot start fake records with a newline or send escape
+    /// sequences to a terminal. Control characters other than tabs are written as Rust escapes,
+    /// for example `\n` and `\u{1b}`. A trailing newline is kept. Defaults to `false`.
+    ///
+    /// The JSON format always escapes control characters.
+    pub fn set_escape_control(mut self, escape: bool) -> LoggerBuilder<W> {
+        self.escape_control = escape;
+        self
     }
-}
 
-impl Default for Logger<io::Stderr> {
-    /// Returns a default `Logger`.
-    ///
-    /// A default `Logger` has
-    ///   * level:  `log::LevelFilter::Trace`,
-    ///   * out:    stderr,
-    ///   * flags:  `L_STD`, and
-    ///   * prefix: `""` (empty string)
+    /// Truncate messages longer than `len` bytes, marking them with ` …[truncated N bytes]`,
+    /// where N is the number of bytes removed. Messages are cut at a character boundary, and a
+    /// trailing newline is not counted. By default, messages are not truncated.
     ///
-    fn default() -> Logger<io::Stderr> {
-        Logger::builder(io::stderr()).build()
+    /// This applies to the message only, not to the header or fields. Messages are truncated
+    /// after `set_overflow` has checked their size.
+    pub fn set_max_message_len(mut self, len: usize) -> LoggerBuilder<W> {
+        self.max_message_len = Some(len);
+        self
     }
-}
 
-impl<W: Write + Send> log::Log for Logger<W> {
-    fn enabled(&self, metadata: &log::Metadata) -> bool {
-        self.enabled(metadata.level())
+    /// Set whether records end in `\r\n` rather than `\n`, for Windows log viewers and
+    /// ingestion agents that require it. Newlines within records are converted too, and
+    /// existing `\r\n` line endings are kept. Defaults to `false`.
+    pub fn set_crlf(mut self, crlf: bool) -> LoggerBuilder<W> {
+        self.crlf = crlf;
+        self
     }
 
-    fn log(&self, record: &log::Record) {
-        self.write_record(record);
+    /// Set the prefix.
+    pub fn set_prefix(mut self, prefix: &str) -> LoggerBuilder<W> {
+        self.prefix = String::from(prefix);
+        self
     }
 
-    fn flush(&self) {
-        let _ = self.out.lock().unwrap().flush();
+    /// Set an additional prefix for records of `level`. It is written just before the prefix,
+    /// so that it also moves to the end of the header with `L_MSG_PREFIX`. Level prefixes are
+    /// used in the text format only.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    ///
+    /// // Highlight errors on the console.
+    /// let logger = Logger::builder()
+    ///     .set_level_prefix(log::Level::Error, "!! ")
+    ///     .build();
+    /// ```
+    pub fn set_level_prefix(mut self, level: log::Level, prefix: &str) -> LoggerBuilder<W> {
+        self.level_prefixes[level as usize - 1] = String::from(prefix);
+        self
     }
-}
 
-#[doc(hidden)]
-pub mod test_util;
+    /// Override settings from environment variables. Call this after the other setters, so that
+    /// the environment takes precedence over the values configured in code.
+    ///
+    /// The following variables are recognized:
+    ///   * `LOGOSAURUS_LEVEL`:  the allowed log level, e.g. `debug` or `off`,
+    ///   * `LOGOSAURUS_FLAGS`:  the formatting flags, e.g. `L_STD|L_SHORT_FILE` (see
+    ///     [`parse_flags`]),
+    ///   * `LOGOSAURUS_FORMAT`: the output format, `text` or `json`, and
+    ///   * `LOGOSAURUS_PREFIX`: the prefix.
+    ///
+    /// Unset variables, and variables with values that cannot be parsed, are ignored.
+    ///
+    /// [`parse_flags`]: fn.parse_flags.html
+    pub fn apply_env(self) -> LoggerBuilder<W> {
+        self.apply_vars(|name| env::var(name).ok())
+    }
 
-#[cfg(test)]
+    // Applies the variables of `apply_env`, looking up their values with `var`.
+    fn apply_vars<F: Fn(&str) -> Option<String>>(mut self, var: F) -> LoggerBuilder<W> {
+        if let Some(level) = var("LOGOSAURUS_LEVEL") {
+            if let Ok(level) = level.trim().parse() {
+                self.level = level;
+            }
+        }
+        if let Some(flags) = var("LOGOSAURUS_FLAGS") {
+            if let Ok(flags) = parse_flags(&flags) {
+                self.flag = flags;
+            }
+        }
+        if let Some(format) = var("LOGOSAURUS_FORMAT") {
+            match format.trim().to_ascii_lowercase().as_str() {
+                "text" => self.format = Format::Text,
+                "json" => self.format = Format::Json,
+                _ => {}
+            }
+        }
+        if let Some(prefix) = var("LOGOSAURUS_PREFIX") {
+            self.prefix = prefix;
+        }
+        self
+    }
+
+    /// Set the allowed log levels from the environment variable `name`, which uses the directive
+    /// syntax of `RUST_LOG`; see `set_target_levels` for the syntax. The builder is left
+    /// unchanged if the variable is unset or is not valid unicode.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    /// use std::io;
+    ///
+    /// let logger = Logger::builder().parse_env("RUST_LOG").build();
+    /// ```
+    pub fn parse_env(self, name: &str) -> LoggerBuilder<W> {
+        self.parse_var(env::var(name).ok())
+    }
+
+    // Applies the value of the variable read by `parse_env`, if it is set.
+    fn parse_var(self, directives: Option<String>) -> LoggerBuilder<W> {
+        match directives {
+            Some(directives) => self.set_target_levels(&directives),
+            None => self,
+        }
+    }
+
+    /// Buffer writes to the output in memory, using a buffer of `capacity` bytes, instead of
+    /// issuing a write to the output for every record. `policy` controls how often the buffer is
+    /// flushed.
+    ///
+    /// The buffer is always flushed when the `Logger` is flushed, for example through the guard
+    /// returned by [`init_with_guard`].
+    ///
+    /// [`init_with_guard`]: fn.init_with_guard.html
+    pub fn set_buffer(mut self, capacity: usize, policy: FlushPolicy) -> LoggerBuilder<W> {
+        self.buffer = Some((capacity, policy));
+        self
+    }
+
+    /// Flush the output immediately after writing a record at `level` or a more severe level,
+    /// regardless of the flush policy. This ensures, for example, that errors reach the disk
+    /// before a potential crash while keeping the common path buffered.
+    pub fn set_flush_level(mut self, level: log::Level) -> LoggerBuilder<W> {
+        self.flush_level = Some(level);
+        self
+    }
+
+    /// Add a field that is included in every record. Fields are appended to the message as
+    /// `key=value` pairs, in the order they were added. Values that are empty or contain
+    /// whitespace, `"` or `=` are quoted.
+    pub fn add_field(mut self, key: &str, value: &str) -> LoggerBuilder<W> {
+        self.fields.push((String::from(key), String::from(value)));
+        self
+    }
+
+    /// Add a field that is included in records whose target is `namespace` or a module nested
+    /// under it. For example, with `add_target_field("tenant_a", "tenant", "a")`, records logged
+    /// from `tenant_a` and `tenant_a::billing` have the field `tenant=a`, so that the logs of
+    /// tenants sharing a process can be separated downstream without changing call sites.
+    ///
+    /// Target fields follow the fields added with `add_field`, in the order they were added.
+    pub fn add_target_field(mut self, namespace: &str, key: &str, value: &str) -> LoggerBuilder<W> {
+        self.target_fields.push((
+            String::from(namespace),
+            String::from(key),
+            String::from(value),
+        ));
+        self
+    }
+
+    /// Route records whose message starts with `prefix` to `sink` only. Such records are not
+    /// written to the logger's output or passed to its other sinks, but they are still subject to
+    /// the logger's level, rate limits and other filters. The message is passed to `sink`
+    /// unchanged, including the prefix.
+    ///
+    /// This is a lightweight convention for separating records by their content, for example to
+    /// keep messages starting with `[sensitive]` out of the regular logs:
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    /// use std::fs::File;
+    /// use std::io;
+    ///
+    /// # fn main() -> io::Result<()> {
+    /// # let dir = std::env::temp_dir();
+    /// let sensitive = Logger::builder().set_out(File::create(dir.join("sensitive.log"))?).build();
+    /// let logger = Logger::builder()
+    ///     .add_prefix_route("[sensitive]", sensitive)
+    ///     .build();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// If several prefixes match, the route added first is used.
+    pub fn add_prefix_route<L: log::Log + 'static>(
+        mut self,
+        prefix: &str,
+        sink: L,
+    ) -> LoggerBuilder<W> {
+        self.prefix_routes
+            .push((String::from(prefix), Box::new(sink)));
+        self
+    }
+
+    /// Add fields describing the Kubernetes pod the program runs in: `pod`, `namespace`, and
+    /// `node`. See the [`kubernetes`] module for where the values are read from. Fields whose
+    /// value cannot be determined are omitted.
+    ///
+    /// [`kubernetes`]: kubernetes/index.html
+    #[cfg(feature = "kubernetes")]
+    pub fn add_kubernetes_fields(mut self) -> LoggerBuilder<W> {
+        self.fields.extend(kubernetes::fields());
+        self
+    }
+
+    /// Limit records at `level` to at most `per_second` records per second. Records over the
+    /// limit are discarded. Levels without a limit are unlimited, so that, for example, errors
+    /// can always get through while debug noise is throttled:
+    ///
+    /// ```
+    /// use log::Level;
+    /// use logosaurus::Logger;
+    /// use std::io;
+    ///
+    /// let logger = Logger::builder()
+    ///                 .set_rate_limit(Level::Info, 50)
+    ///                 .set_rate_limit(Level::Debug, 10)
+    ///                 .build();
+    /// ```
+    ///
+    /// The limit allows bursts of up to `per_second` records.
+    pub fn set_rate_limit(mut self, level: log::Level, per_second: u32) -> LoggerBuilder<W> {
+        self.rate_limits.retain(|(l, _)| *l != level);
+        self.rate_limits.push((level, per_second));
+        self
+    }
+
+    /// Collapse consecutive identical records, with the same level, target and message, like
+    /// syslogd. The first record is written, and the repetitions that follow are counted
+    /// instead. The count is written as `last message repeated N times`, with the level and
+    /// target of the repeated record, when a different record is logged, when a repetition is
+    /// logged more than `window` after the first counted one, and when the logger is flushed.
+    ///
+    /// There is no timer: if no further records are logged, the count is written on the next
+    /// flush.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    /// use std::time::Duration;
+    ///
+    /// let logger = Logger::builder()
+    ///     .set_repeat_window(Duration::from_secs(30))
+    ///     .build();
+    /// ```
+    pub fn set_repeat_window(mut self, window: time::Duration) -> LoggerBuilder<W> {
+        self.repeat_window = Some(window);
+        self
+    }
+
+    /// Allow each call site, identified by its file and line, to log at most `limit` records
+    /// per `interval`. Records without a file are limited by their target instead. This keeps
+    /// a log statement in a hot loop from flooding the output.
+    ///
+    /// When a call site that had records suppressed logs again in a later interval, a record
+    /// like `suppressed 120 records from src/main.rs:12` is written first, with the level and
+    /// target of that record.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    /// use std::time::Duration;
+    ///
+    /// let logger = Logger::builder()
+    ///     .set_site_rate_limit(10, Duration::from_secs(1))
+    ///     .build();
+    /// ```
+    pub fn set_site_rate_limit(mut self, limit: u32, interval: time::Duration) -> LoggerBuilder<W> {
+        self.site_rate_limit = Some((limit, interval));
+        self
+    }
+
+    /// Keep a random 1 in `n` records of `level`, so that verbose levels can stay enabled in
+    /// production at a fraction of the volume. Levels without a sample rate, and rates of 0 or
+    /// 1, keep every record. The number of records that were not kept is available from
+    /// `Logger::sampled_out`.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    ///
+    /// let logger = Logger::builder()
+    ///     .set_sample_rate(log::Level::Debug, 100)
+    ///     .set_sample_rate(log::Level::Trace, 100)
+    ///     .build();
+    /// ```
+    pub fn set_sample_rate(mut self, level: log::Level, n: u32) -> LoggerBuilder<W> {
+        self.sample_rates[level as usize - 1] = n;
+        self
+    }
+
+    /// Set a function that decides whether a record is logged. It is called for every record
+    /// that passes the level filters, before the record is formatted, and the record is
+    /// discarded if it returns `false`.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    ///
+    /// // Drop the health checks that the load balancer makes every second.
+    /// let logger = Logger::builder()
+    ///     .set_filter(|record| {
+    ///         record.target() != "myapp::http" || record.args().to_string() != "GET /health"
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn set_filter<F>(mut self, f: F) -> LoggerBuilder<W>
+    where
+        F: Fn(&log::Record) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Arc::new(f));
+        self
+    }
+
+    /// Add a filter on the message of records. A record is discarded if its message matches any
+    /// `Drop` filter, or if there are `Keep` filters and its message matches none of them.
+    ///
+    /// ```
+    /// use logosaurus::redact::Pattern;
+    /// use logosaurus::{Logger, MessageFilter};
+    ///
+    /// let logger = Logger::builder()
+    ///     .add_message_filter(MessageFilter::DropContaining(String::from(
+    ///         "connection reset by peer",
+    ///     )))
+    ///     .add_message_filter(MessageFilter::DropMatching(
+    ///         Pattern::new(r"^retrying in \d+ms$").unwrap(),
+    ///     ))
+    ///     .build();
+    /// ```
+    pub fn add_message_filter(mut self, filter: MessageFilter) -> LoggerBuilder<W> {
+        self.message_filters.push(filter);
+        self
+    }
+
+    /// Add a function that rewrites records before they are formatted. It can change the
+    /// message and the level, and add fields, through [`RecordMut`]. Functions run in the
+    /// order they were added, after all filtering, so the level filters and rate limits apply
+    /// to the original level. The rewritten record is also passed to the sinks.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    ///
+    /// let logger = Logger::builder()
+    ///     .add_middleware(|r| r.add_field("tenant", "acme"))
+    ///     .add_middleware(|r| {
+    ///         if r.message().contains("timed out") {
+    ///             r.set_level(log::Level::Warn);
+    ///         }
+    ///     })
+    ///     .build();
+    /// ```
+    ///
+    /// [`RecordMut`]: struct.RecordMut.html
+    pub fn add_middleware<F>(mut self, f: F) -> LoggerBuilder<W>
+    where
+        F: Fn(&mut RecordMut) + Send + Sync + 'static,
+    {
+        self.middleware.push(Arc::new(f));
+        self
+    }
+
+    /// Add a pattern whose matches are masked in messages and field values, before they are
+    /// written to the output. Patterns are applied in the order they were added. The
+    /// [`redact`] module has patterns for common kinds of sensitive data.
+    ///
+    /// Redaction applies to this logger's output. Sinks and routes receive the original record
+    /// and need their own patterns.
+    ///
+    /// ```
+    /// use logosaurus::redact::{self, Pattern};
+    /// use logosaurus::Logger;
+    ///
+    /// let logger = Logger::builder()
+    ///     .add_redaction(Pattern::new(redact::CREDIT_CARD).unwrap())
+    ///     .add_redaction(Pattern::new(redact::BEARER_TOKEN).unwrap())
+    ///     .add_redaction(Pattern::new(r"password=\S+").unwrap())
+    ///     .build();
+    /// ```
+    ///
+    /// [`redact`]: redact/index.html
+    pub fn add_redaction(mut self, pattern: redact::Pattern) -> LoggerBuilder<W> {
+        self.redactions.push(pattern);
+        self
+    }
+
+    /// Set a generator for per-record IDs. When set, every record gets a unique `id` field, so
+    /// that individual records can be referenced unambiguously, for example in tickets.
+    ///
+    /// Use [`id::ulid`] for ULIDs, or any other function that returns unique strings, such as a
+    /// UUIDv7 generator.
+    ///
+    /// ```
+    /// use logosaurus::{id, Logger};
+    /// use std::io;
+    ///
+    /// let logger = Logger::builder().set_id_generator(id::ulid).build();
+    /// ```
+    ///
+    /// [`id::ulid`]: id/fn.ulid.html
+    pub fn set_id_generator<F>(mut self, f: F) -> LoggerBuilder<W>
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.id_generator = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the time zone of the timestamps, instead of the local time zone. `tz` can be a
+    /// `chrono::FixedOffset`, or any other `chrono::TimeZone`, such as a named time zone like
+    /// `chrono_tz::America::New_York` from the chrono-tz crate. `L_UTC` takes precedence.
+    ///
+    /// ```
+    /// use chrono::FixedOffset;
+    /// use logosaurus::Logger;
+    ///
+    /// let ist = FixedOffset::east_opt(5 * 3600 + 1800).unwrap();
+    /// let logger = Logger::builder().set_timezone(ist).build();
+    /// ```
+    pub fn set_timezone<Tz>(mut self, tz: Tz) -> LoggerBuilder<W>
+    where
+        Tz: chrono::TimeZone + Send + Sync + 'static,
+    {
+        self.timezone = Some(Arc::new(move |now| now.with_timezone(&tz).fixed_offset()));
+        self
+    }
+
+    /// Set a function that is called with the error when writing or flushing the output fails,
+    /// for example because the disk is full. By default such errors are only counted; see
+    /// `Logger::error_count`.
+    ///
+    /// The function is called on the thread that logged the record, after the output has been
+    /// unlocked. It may log with the same logger, but such records are likely to fail too.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    ///
+    /// let logger = Logger::builder()
+    ///     .set_error_hook(|e| eprintln!("failed to write log record: {}", e))
+    ///     .build();
+    /// ```
+    pub fn set_error_hook<F>(mut self, f: F) -> LoggerBuilder<W>
+    where
+        F: Fn(&io::Error) + Send + Sync + 'static,
+    {
+        self.error_hook = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the handle of the [`AsyncWriter`] that the output writes to, so that
+    /// `Logger::status` reports the length of its queue.
+    ///
+    /// ```
+    /// use logosaurus::{AsyncWriter, Logger};
+    /// use std::io;
+    ///
+    /// let w = AsyncWriter::new(io::stderr(), 1024);
+    /// let handle = w.handle();
+    /// let logger = Logger::builder().set_out(w).set_async_handle(handle).build();
+    /// assert_eq!(Some(0), logger.status().queue_len);
+    /// ```
+    ///
+    /// [`AsyncWriter`]: struct.AsyncWriter.html
+    pub fn set_async_handle(mut self, handle: AsyncHandle) -> LoggerBuilder<W> {
+        self.async_handle = Some(handle);
+        self
+    }
+
+    /// Stage formatted records in a buffer per thread, and write them to the output in the order
+    /// they were logged on a background thread, every `interval`. This removes the contention on
+    /// the output's lock when many threads log at very high rates.
+    ///
+    /// A thread writes its staged records itself once they exceed 64 KiB, and records at or
+    /// above the level set with `set_flush_level` are written and flushed right away, along with
+    /// all records staged before them. Flushing the logger writes all staged records.
+    ///
+    /// The background thread is started here, and an error is returned if it cannot be. If it
+    /// cannot be restarted in a child process after a fork, records are written by the threads
+    /// that log them.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    /// use std::time::Duration;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let logger = Logger::builder()
+    ///     .set_staging(Duration::from_millis(10))?
+    ///     .build();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_staging(mut self, interval: time::Duration) -> io::Result<LoggerBuilder<W>>
+    where
+        W: 'static,
+    {
+        self.staging = Some((interval, new_stager, staging::Worker::spawn()?));
+        Ok(self)
+    }
+
+    /// Set an overflow output for oversized messages.
+    ///
+    /// Messages longer than `limit` bytes are written, together with their header, to `out`
+    /// instead of the main output. The main output receives a short reference line in their
+    /// place, which keeps the main output scannable when the occasional large payload (such as a
+    /// request dump) is logged. Each overflowed record is tagged with `[overflow #N]` in both
+    /// outputs so the two can be correlated.
+    pub fn set_overflow_out<V: Write + Send + 'static>(
+        mut self,
+        limit: usize,
+        out: V,
+    ) -> LoggerBuilder<W> {
+        self.overflow = Some((limit, Box::new(out)));
+        self
+    }
+
+    /// Construct a `Logger` from this `LoggerBuilder`. Consumes the
+    /// `LoggerBuilder`.
+    pub fn build(mut self) -> Logger<W> {
+        let health = Arc::new(status::Health::default());
+        let out = Arc::new(Mutex::new(Output::new(
+            self.out,
+            self.buffer,
+            Arc::clone(&health),
+        )));
+        let error_hook = self.error_hook.clone();
+        let sequence = Arc::new(AtomicU64::new(0));
+        let staging = self.staging.take().map(|(interval, staging, worker)| {
+            Arc::new(staging(
+                interval,
+                Arc::clone(&out),
+                Arc::clone(&health),
+                error_hook,
+                Arc::clone(&sequence),
+                worker,
+            ))
+        });
+        Logger {
+            level: Arc::new(level::AtomicLevel::new(self.level)),
+            out,
+            settings: Arc::new(Settings {
+                flag: AtomicU32::new(self.flag.bits()),
+                prefix: RwLock::new(self.prefix.clone()),
+            }),
+            overflow: self.overflow.take().map(|(limit, out)| {
+                Arc::new(Overflow {
+                    limit,
+                    out: Mutex::new((out, 0)),
+                })
+            }),
+            sinks: Sinks::default(),
+            file_levels: std::mem::take(&mut self.file_levels),
+            flush_level: self.flush_level,
+            fields: std::mem::take(&mut self.fields),
+            format: self.format,
+            newline: self.newline,
+            multiline: self.multiline,
+            escape_control: self.escape_control,
+            max_message_len: self.max_message_len,
+            crlf: self.crlf,
+            id_generator: self.id_generator.take(),
+            target_levels: std::mem::take(&mut self.target_levels),
+            allowed_targets: std::mem::take(&mut self.allowed_targets),
+            denied_targets: std::mem::take(&mut self.denied_targets),
+            target_fields: std::mem::take(&mut self.target_fields),
+            prefix_routes: Arc::new(std::mem::take(&mut self.prefix_routes)),
+            health,
+            counters: Arc::new(stats::Counters::default()),
+            error_hook: self.error_hook.take(),
+            async_handle: self.async_handle.take(),
+            staging,
+            timezone: self.timezone.take(),
+            header: Arc::new(HeaderStyle::new(
+                self.level_labels.take(),
+                self.level_case,
+                self.level_width,
+                std::mem::take(&mut self.level_prefixes),
+                &self.header_order,
+                self.location_width,
+            )),
+            sequence,
+            partial_line: Arc::new(Mutex::new(Vec::new())),
+            repeats: self
+                .repeat_window
+                .map(|w| Arc::new(repeat::Repeats::new(w))),
+            site_limits: self
+                .site_rate_limit
+                .map(|(limit, interval)| Arc::new(ratelimit::SiteLimits::new(limit, interval))),
+            sampler: Some(self.sample_rates)
+                .filter(|rates| rates.iter().any(|n| *n > 1))
+                .map(|rates| Arc::new(sample::Sampler::new(rates))),
+            filter: self.filter.take(),
+            message_filters: Arc::new(std::mem::take(&mut self.message_filters)),
+            middleware: Arc::new(std::mem::take(&mut self.middleware)),
+            redactions: Arc::new(std::mem::take(&mut self.redactions)),
+            rate_limits: {
+                let mut limits: [Option<ratelimit::Bucket>; 5] = Default::default();
+                for (level, per_second) in &self.rate_limits {
+                    limits[*level as usize - 1] = Some(ratelimit::Bucket::new(
+                        *per_second,
+                        time::Duration::from_secs(1),
+                    ));
+                }
+                Arc::new(limits)
+            },
+        }
+    }
+}
+
+/// Represents a logging object that writes to a specified output. It can be used simultaneously
+/// from multiple threads; it guarantees to serialize writes.
+///
+/// Use [`LoggerBuilder`] to construct a `Logger`, or use `Logger::default()`.
+///
+/// [`LoggerBuilder`]: struct.LoggerBuilder.html
+pub struct Logger<W: Write + Send> {
+    level: Arc<level::AtomicLevel>,
+    out: Arc<Mutex<Output<W>>>,
+    settings: Arc<Settings>,
+    overflow: Option<Arc<Overflow>>,
+    sinks: Sinks,
+    file_levels: Vec<(String, log::LevelFilter)>,
+    flush_level: Option<log::Level>,
+    fields: Vec<(String, String)>,
+    format: Format,
+    newline: bool,
+    multiline: Multiline,
+    escape_control: bool,
+    max_message_len: Option<usize>,
+    crlf: bool,
+    rate_limits: Arc<[Option<ratelimit::Bucket>; 5]>, // indexed by level, Error first
+    repeats: Option<Arc<repeat::Repeats>>,
+    site_limits: Option<Arc<ratelimit::SiteLimits>>,
+    sampler: Option<Arc<sample::Sampler>>,
+    filter: Option<RecordFilter>,
+    message_filters: Arc<Vec<MessageFilter>>,
+    middleware: Arc<Vec<middleware::Middleware>>,
+    redactions: Arc<Vec<redact::Pattern>>,
+    id_generator: Option<IdGenerator>,
+    target_levels: Vec<(String, log::LevelFilter)>,
+    allowed_targets: Vec<String>,
+    denied_targets: Vec<String>,
+    target_fields: Vec<(String, String, String)>, // namespace, key, value
+    prefix_routes: Arc<Vec<(String, Box<dyn log::Log>)>>,
+    health: Arc<status::Health>,
+    counters: Arc<stats::Counters>,
+    error_hook: Option<ErrorHook>,
+    async_handle: Option<AsyncHandle>,
+    staging: Option<Arc<staging::Stager>>,
+    timezone: Option<TimeZoneFn>,
+    header: Arc<HeaderStyle>,
+    sequence: Arc<AtomicU64>, // the number of the next record with `L_SEQUENCE`
+    partial_line: Arc<Mutex<Vec<u8>>>, // written through `io::Write` but not yet terminated
+}
+
+/// Returns a handle to the same logger. The clone shares the original's output, level, flags,
+/// prefix and sinks, so changes made through either are visible to both. Unlike
+/// [`Logger::child`], no configuration is copied.
+///
+/// This allows keeping a handle to a logger installed with [`init`]:
+///
+/// ```
+/// use logosaurus::{Logger, L_STD};
+///
+/// let logger = Logger::builder().set_flags(L_STD).build();
+/// logosaurus::init(logger.clone()).unwrap();
+///
+/// // Later, for example when reloading the configuration:
+/// logger.set_prefix("myapp: ");
+/// ```
+///
+/// [`Logger::child`]: struct.Logger.html#method.child
+/// [`init`]: fn.init.html
+impl<W: Write + Send> Clone for Logger<W> {
+    fn clone(&self) -> Self {
+        Logger {
+            level: Arc::clone(&self.level),
+            out: Arc::clone(&self.out),
+            settings: Arc::clone(&self.settings),
+            overflow: self.overflow.clone(),
+            sinks: self.sinks.clone(),
+            file_levels: self.file_levels.clone(),
+            flush_level: self.flush_level,
+            fields: self.fields.clone(),
+            format: self.format,
+            newline: self.newline,
+            multiline: self.multiline,
+            escape_control: self.escape_control,
+            max_message_len: self.max_message_len,
+            crlf: self.crlf,
+            rate_limits: Arc::clone(&self.rate_limits),
+            repeats: self.repeats.clone(),
+            site_limits: self.site_limits.clone(),
+            sampler: self.sampler.clone(),
+            filter: self.filter.clone(),
+            message_filters: Arc::clone(&self.message_filters),
+            middleware: Arc::clone(&self.middleware),
+            redactions: Arc::clone(&self.redactions),
+            id_generator: self.id_generator.clone(),
+            target_levels: self.target_levels.clone(),
+            allowed_targets: self.allowed_targets.clone(),
+            denied_targets: self.denied_targets.clone(),
+            target_fields: self.target_fields.clone(),
+            prefix_routes: Arc::clone(&self.prefix_routes),
+            health: Arc::clone(&self.health),
+            counters: Arc::clone(&self.counters),
+            error_hook: self.error_hook.clone(),
+            async_handle: self.async_handle.clone(),
+            staging: self.staging.clone(),
+            timezone: self.timezone.clone(),
+            header: Arc::clone(&self.header),
+            sequence: Arc::clone(&self.sequence),
+            partial_line: Arc::clone(&self.partial_line),
+        }
+    }
+}
+
+// The keys used for built-in values in `Format::Json`.
+const JSON_KEYS: &[&str] = &[
+    "seq", "time", "level", "prefix", "hostname", "pid", "thread", "target", "file", "line", "msg",
+    "panic", "location",
+];
+
+type IdGenerator = Arc<dyn Fn() -> String + Send + Sync>;
+
+type ErrorHook = Arc<dyn Fn(&io::Error) + Send + Sync>;
+
+type RecordFilter = Arc<dyn Fn(&log::Record) -> bool + Send + Sync>;
+
+type TimeZoneFn = Arc<
+    dyn Fn(chrono::DateTime<chrono::Utc>) -> chrono::DateTime<chrono::FixedOffset> + Send + Sync,
+>;
+
+// Creates the staging of a logger from its interval, output, health, error hook, sequence and
+// background thread.
+type StagingFactory<W> = fn(
+    time::Duration,
+    Arc<Mutex<Output<W>>>,
+    Arc<status::Health>,
+    Option<ErrorHook>,
+    Arc<AtomicU64>,
+    staging::Worker,
+) -> staging::Stager;
+
+fn new_stager<W: Write + Send + 'static>(
+    interval: time::Duration,
+    out: Arc<Mutex<Output<W>>>,
+    health: Arc<status::Health>,
+    hook: Option<ErrorHook>,
+    sequence: Arc<AtomicU64>,
+    worker: staging::Worker,
+) -> staging::Stager {
+    staging::Stager::new(
+        interval,
+        STAGING_LIMIT,
+        sequence,
+        move |batch| {
+            let mut out = lock(&out);
+            for (_, buf, force_flush) in batch {
+                out.write_record(buf, *force_flush)?;
+            }
+            Ok(())
+        },
+        move |e| report(&health, hook.as_ref(), Err::<(), _>(e)),
+        worker,
+    )
+}
+
+// The staged bytes above which a thread writes its staged records itself.
+const STAGING_LIMIT: usize = 64 * 1024;
+
+// Settings that can be changed after the logger is built.
+struct Settings {
+    flag: AtomicU32,
+    prefix: RwLock<String>,
+}
+
+impl Settings {
+    fn flags(&self) -> Flag {
+        Flags::from_bits_truncate(self.flag.load(Ordering::Relaxed))
+    }
+
+    fn prefix(&self) -> RwLockReadGuard<'_, String> {
+        self.prefix.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_flags(&self, flag: Flag) {
+        self.flag.store(flag.bits(), Ordering::Relaxed);
+    }
+
+    fn set_prefix(&self, prefix: &str) {
+        *self.prefix.write().unwrap_or_else(|e| e.into_inner()) = String::from(prefix);
+    }
+}
+
+// The settings of the global logger, set by `init`.
+static GLOBAL_SETTINGS: OnceLock<Arc<Settings>> = OnceLock::new();
+
+/// The output format of a [`Logger`].
+///
+/// [`Logger`]: struct.Logger.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// The header followed by the message, as described in the `L_*` constants. This is the
+    /// default.
+    Text,
+    /// One JSON object per line. The flags determine which keys are present:
+    ///   * `seq`:    present with `L_SEQUENCE`, as a number.
+    ///   * `time`:   present with `L_DATE` or any of the time flags; RFC 3339 with
+    ///     millisecond precision, or microsecond or nanosecond precision with `L_MICROSECONDS`
+    ///     or `L_NANOSECONDS`. Honors `L_UTC`. With `L_EPOCH`, a number of seconds as in
+    ///     the text format instead.
+    ///   * `level`:  present with `L_LEVEL`, in lowercase.
+    ///   * `hostname`: present with `L_HOSTNAME`.
+    ///   * `pid`:    present with `L_PID`, as a number.
+    ///   * `thread`: present with `L_THREAD_NAME`.
+    ///   * `target`: present with `L_LONG_FILE` or `L_TARGET`.
+    ///   * `file` and `line`: present with `L_LONG_FILE` or `L_SHORT_FILE`.
+    ///
+    /// The `prefix` key is present if the prefix is non-empty, and the message is always present
+    /// as `msg`. Fields follow as string values: the logger's fields, the record ID, the
+    /// context's fields and, with the `kv` feature, the record's key-value pairs. A field named
+    /// like one of the keys above is written as `fields.<name>` instead.
+    Json,
+}
+
+/// How messages that contain newlines are written in the text format. See
+/// [`LoggerBuilder::set_multiline`].
+///
+/// A trailing newline is not affected; see `LoggerBuilder::set_newline`.
+///
+/// [`LoggerBuilder::set_multiline`]: struct.LoggerBuilder.html#method.set_multiline
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Multiline {
+    /// Write the message as it is. This is the default.
+    Keep,
+    /// Repeat the header at the beginning of every line.
+    RepeatHeader,
+    /// Start every continuation line with a tab, so that it can be told apart from the
+    /// beginning of a record.
+    Indent,
+    /// Write the record on a single line, with newlines escaped as `\n` and carriage returns as
+    /// `\r`.
+    Escape,
+}
+
+/// A part of the header in the text format. See [`LoggerBuilder::set_header_order`].
+///
+/// [`LoggerBuilder::set_header_order`]: struct.LoggerBuilder.html#method.set_header_order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderField {
+    /// The sequence number, with `L_SEQUENCE`.
+    Sequence,
+    /// The level, with `L_LEVEL`.
+    Level,
+    /// The date and time, with `L_DATE`, the time flags or `L_EPOCH`.
+    Time,
+    /// The hostname, with `L_HOSTNAME`.
+    Hostname,
+    /// The process ID, with `L_PID`.
+    Pid,
+    /// The thread name, with `L_THREAD_NAME`.
+    Thread,
+    /// The target, file and line, with `L_LONG_FILE`, `L_SHORT_FILE` or `L_TARGET`.
+    Location,
+}
+
+// The order of the header parts when it is not configured.
+const HEADER_ORDER: [HeaderField; 7] = [
+    HeaderField::Sequence,
+    HeaderField::Level,
+    HeaderField::Time,
+    HeaderField::Hostname,
+    HeaderField::Pid,
+    HeaderField::Thread,
+    HeaderField::Location,
+];
+
+/// The capitalization of level labels. See [`LoggerBuilder::set_level_case`].
+///
+/// [`LoggerBuilder::set_level_case`]: struct.LoggerBuilder.html#method.set_level_case
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelCase {
+    /// `INFO`.
+    Upper,
+    /// `info`.
+    Lower,
+    /// `Info`.
+    Title,
+}
+
+impl LevelCase {
+    fn apply(self, label: &str) -> String {
+        match self {
+            LevelCase::Upper => label.to_uppercase(),
+            LevelCase::Lower => label.to_lowercase(),
+            LevelCase::Title => {
+                let mut chars = label.chars();
+                match chars.next() {
+                    Some(first) => first
+                        .to_uppercase()
+                        .chain(chars.flat_map(char::to_lowercase))
+                        .collect(),
+                    None => String::new(),
+                }
+            }
+        }
+    }
+}
+
+/// Determines when a buffered [`Logger`] flushes its output. See
+/// [`LoggerBuilder::set_buffer`].
+///
+/// [`Logger`]: struct.Logger.html
+/// [`LoggerBuilder::set_buffer`]: struct.LoggerBuilder.html#method.set_buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Flush after every record.
+    EveryRecord,
+    /// Flush after every N records.
+    EveryNRecords(usize),
+    /// Flush after a record when at least the given duration has passed since the last flush.
+    /// There is no timer; a logger that stops receiving records does not flush until it is
+    /// explicitly flushed.
+    Interval(time::Duration),
+}
+
+// The main output of a Logger, optionally buffered.
+struct Output<W: Write> {
+    w: io::BufWriter<W>,
+    policy: Option<FlushPolicy>,
+    unflushed: usize,
+    last_flush: time::Instant,
+    paused: Option<Paused>,
+    health: Arc<status::Health>,
+    // The bytes written to the buffer since the last flush. They are counted as written once
+    // the buffer has been flushed to the writer.
+    buffered: usize,
+}
+
+// Records held back while the output is paused with `Logger::pause`.
+struct Paused {
+    held: Vec<u8>,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl Paused {
+    fn hold(&mut self, buf: &[u8]) {
+        if self.held.len() + buf.len() <= self.capacity {
+            self.held.extend_from_slice(buf);
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
+
+impl<W: Write> Output<W> {
+    fn new(w: W, buffer: Option<(usize, FlushPolicy)>, health: Arc<status::Health>) -> Output<W> {
+        // A zero-capacity BufWriter passes writes straight through to the inner writer.
+        let (capacity, policy) = match buffer {
+            Some((capacity, policy)) => (capacity, Some(policy)),
+            None => (0, None),
+        };
+        Output {
+            w: io::BufWriter::with_capacity(capacity, w),
+            policy,
+            unflushed: 0,
+            last_flush: time::Instant::now(),
+            paused: None,
+            health,
+            buffered: 0,
+        }
+    }
+
+    fn write_record(&mut self, buf: &[u8], force_flush: bool) -> io::Result<()> {
+        if let Some(paused) = &mut self.paused {
+            paused.hold(buf);
+            return Ok(());
+        }
+        self.w.write_all(buf)?;
+        if self.policy.is_some() {
+            self.buffered += buf.len();
+        } else {
+            self.health.wrote(buf.len());
+        }
+        self.unflushed += 1;
+        let flush = force_flush
+            || match self.policy {
+                None => false,
+                Some(FlushPolicy::EveryRecord) => true,
+                Some(FlushPolicy::EveryNRecords(n)) => self.unflushed >= n,
+                Some(FlushPolicy::Interval(d)) => self.last_flush.elapsed() >= d,
+            };
+        if flush {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.unflushed = 0;
+        self.last_flush = time::Instant::now();
+        self.w.flush()?;
+        if self.buffered > 0 {
+            self.health.wrote(std::mem::take(&mut self.buffered));
+        }
+        Ok(())
+    }
+}
+
+// Metadata of a record being formatted, along with fields that are specific to the record.
+struct Meta<'a> {
+    level: log::Level,
+    target: &'a str,
+    file: &'a str,
+    // The file, if it is known to be a static string. Headers for such records are cached.
+    file_static: Option<&'static str>,
+    line: u32,
+    fields: Vec<(String, String)>,
+    panic: Option<panic_hook::Location>,
+}
+
+// How the parts of the header are written, besides the flags.
+struct HeaderStyle {
+    hostname: String,
+    level_labels: [String; 5], // indexed by level, Error first
+    level_width: usize,
+    level_prefixes: [String; 5], // indexed by level, Error first
+    order: Vec<HeaderField>,
+    location_width: usize,
+}
+
+impl HeaderStyle {
+    fn new(
+        level_labels: Option<[String; 5]>,
+        level_case: Option<LevelCase>,
+        level_width: Option<usize>,
+        level_prefixes: [String; 5],
+        order: &[HeaderField],
+        location_width: usize,
+    ) -> HeaderStyle {
+        let level_labels = level_labels
+            .unwrap_or_else(|| ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"].map(String::from));
+        let level_labels = match level_case {
+            Some(case) => level_labels.map(|l| case.apply(&l)),
+            None => level_labels,
+        };
+        HeaderStyle {
+            hostname: host::hostname().unwrap_or_else(|| String::from("-")),
+            level_width: level_width.unwrap_or_else(|| {
+                level_labels
+                    .iter()
+                    .map(|l| l.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            }),
+            level_labels,
+            level_prefixes,
+            location_width,
+            order: order
+                .iter()
+                .chain(HEADER_ORDER.iter())
+                .fold(Vec::new(), |mut order, field| {
+                    if !order.contains(field) {
+                        order.push(*field);
+                    }
+                    order
+                }),
+        }
+    }
+}
+
+// Overflow output for oversized messages, along with the number of records written to it.
+struct Overflow {
+    limit: usize,
+    out: Mutex<(Box<dyn Write + Send>, u64)>,
+}
+
+/// Initialize the logger to use with the [`log`] crate.
+///
+/// ```
+/// use log::{debug};
+///
+/// fn main() {
+///   logosaurus::init(logosaurus::Logger::default()).unwrap();
+///   debug!("hello, world");
+/// }
+/// ```
+//
+/// See [`LoggerBuilder`] to create a custom logger.
+///
+/// The returned [`LevelHandle`] can be used to change the logger's level at runtime.
+///
+/// [`log`]: https://crates.io/crates/log
+/// [`LoggerBuilder`]: struct.LoggerBuilder.html
+/// [`LevelHandle`]: struct.LevelHandle.html
+pub fn init<W: Write + Send + 'static>(l: Logger<W>) -> Result<LevelHandle, log::SetLoggerError> {
+    let handle = LevelHandle::new(Arc::clone(&l.level), l.directive_level());
+    let max_level = l.max_level();
+    let settings = Arc::clone(&l.settings);
+    log::set_boxed_logger(Box::new(l))?;
+    let _ = GLOBAL_SETTINGS.set(settings);
+    log::set_max_level(max_level);
+    Ok(handle)
+}
+
+/// Returns the flags of the global logger, or `L_NONE` if [`init`] has not been called.
+///
+/// [`init`]: fn.init.html
+pub fn flags() -> Flag {
+    GLOBAL_SETTINGS.get().map_or(L_NONE, |s| s.flags())
+}
+
+/// Set the flags of the global logger. Does nothing if [`init`] has not been called.
+///
+/// [`init`]: fn.init.html
+pub fn set_flags(flag: Flag) {
+    if let Some(settings) = GLOBAL_SETTINGS.get() {
+        settings.set_flags(flag);
+    }
+}
+
+/// Returns the prefix of the global logger, or an empty string if [`init`] has not been called.
+///
+/// [`init`]: fn.init.html
+pub fn prefix() -> String {
+    GLOBAL_SETTINGS
+        .get()
+        .map_or_else(String::new, |s| s.prefix().clone())
+}
+
+/// Set the prefix of the global logger. Does nothing if [`init`] has not been called.
+///
+/// [`init`]: fn.init.html
+pub fn set_prefix(prefix: &str) {
+    if let Some(settings) = GLOBAL_SETTINGS.get() {
+        settings.set_prefix(prefix);
+    }
+}
+
+/// Returns the standard logger, creating it on first use. The standard logger is configured
+/// like `Logger::default()`, with its output boxed so that it can be replaced; it is used by the
+/// [`print!`] and [`fatal!`] macros, like the standard logger of Go's log package.
+///
+/// The standard logger is independent of the logger installed with [`init`]. Use its
+/// `set_flags`, `set_prefix` and `set_output` methods to configure it.
+///
+/// ```
+/// use std::io;
+///
+/// logosaurus::std_logger().set_prefix("myprogram: ");
+/// logosaurus::std_logger().set_output(Box::new(io::stdout()));
+/// logosaurus::print!("hello, world"); // myprogram: INFO  2020/10/02 21:27:03 hello, world
+/// ```
+///
+/// [`print!`]: macro.print.html
+/// [`fatal!`]: macro.fatal.html
+/// [`init`]: fn.init.html
+pub fn std_logger() -> &'static Logger<Box<dyn Write + Send>> {
+    static STD: OnceLock<Logger<Box<dyn Write + Send>>> = OnceLock::new();
+    STD.get_or_init(|| Logger::builder().set_boxed_out(io::stderr()).build())
+}
+
+#[doc(hidden)]
+pub fn __print(module: &str, file: &str, line: u32, args: fmt::Arguments) {
+    std_logger().write_output(
+        log::Level::Info,
+        module,
+        Some(file),
+        Some(line),
+        &format_message(&args),
+    );
+}
+
+/// Initialize a default logger, configured by the `RUST_LOG` environment variable, to use with
+/// the [`log`] crate. This is a convenient replacement for `env_logger::init`.
+///
+/// `RUST_LOG` holds comma-separated directives such as `info,hyper=warn,myapp::db=trace`. See
+/// `LoggerBuilder::set_target_levels` for the syntax. If `RUST_LOG` is unset, all levels are
+/// logged, as with `Logger::default()`.
+///
+/// ```
+/// use log::{debug};
+///
+/// fn main() {
+///   logosaurus::init_from_env().unwrap();
+///   debug!("hello, world");
+/// }
+/// ```
+///
+/// [`log`]: https://crates.io/crates/log
+pub fn init_from_env() -> Result<LevelHandle, log::SetLoggerError> {
+    init(Logger::builder().parse_env("RUST_LOG").build())
+}
+
+/// Initialize the logger to use with the [`log`] crate, like [`init`], and return a guard that
+/// flushes the logger when dropped.
+///
+/// Keep the guard alive until the end of `main`. This ensures that records logged just before
+/// exit are written out when the logger buffers its output, for example when writing through an
+/// [`AsyncWriter`].
+///
+/// ```
+/// use log::{debug};
+/// use logosaurus::{AsyncWriter, Logger};
+/// use std::io;
+///
+/// fn main() {
+///   let logger = Logger::builder().set_out(AsyncWriter::new(io::stderr(), 1024)).build();
+///   let _guard = logosaurus::init_with_guard(logger).unwrap();
+///   debug!("hello, world");
+/// }
+/// ```
+///
+/// [`log`]: https://crates.io/crates/log
+/// [`init`]: fn.init.html
+/// [`AsyncWriter`]: struct.AsyncWriter.html
+pub fn init_with_guard<W: Write + Send + 'static>(
+    l: Logger<W>,
+) -> Result<FlushGuard, log::SetLoggerError> {
+    init(l).map(|_| FlushGuard { _priv: () })
+}
+
+/// Flushes the global logger when dropped. Returned by [`init_with_guard`].
+///
+/// [`init_with_guard`]: fn.init_with_guard.html
+#[must_use = "the logger is flushed when the guard is dropped"]
+pub struct FlushGuard {
+    _priv: (),
+}
+
+impl Drop for FlushGuard {
+    fn drop(&mut self) {
+        log::logger().flush();
+    }
+}
+
+impl Logger<io::Stderr> {
+    /// Returns a `LoggerBuilder` that can be used to build a `Logger`. The builder writes to
+    /// stderr unless the output is changed with `set_out`.
+    pub fn builder() -> LoggerBuilder<io::Stderr> {
+        LoggerBuilder {
+            level: log::LevelFilter::Trace,
+            out: io::stderr(),
+            flag: L_STD,
+            prefix: String::from(""),
+            overflow: None,
+            buffer: None,
+            file_levels: Vec::new(),
+            flush_level: None,
+            fields: Vec::new(),
+            format: Format::Text,
+            newline: true,
+            multiline: Multiline::Keep,
+            escape_control: false,
+            max_message_len: None,
+            crlf: false,
+            rate_limits: Vec::new(),
+            repeat_window: None,
+            site_rate_limit: None,
+            sample_rates: [1; 5],
+            filter: None,
+            message_filters: Vec::new(),
+            middleware: Vec::new(),
+            redactions: Vec::new(),
+            id_generator: None,
+            target_levels: Vec::new(),
+            allowed_targets: Vec::new(),
+            denied_targets: Vec::new(),
+            target_fields: Vec::new(),
+            prefix_routes: Vec::new(),
+            error_hook: None,
+            async_handle: None,
+            staging: None,
+            timezone: None,
+            level_labels: None,
+            level_case: None,
+            level_width: None,
+            level_prefixes: Default::default(),
+            header_order: Vec::new(),
+            location_width: 0,
+        }
+    }
+}
+
+impl<W: Write + Send> Logger<W> {
+    /// Returns a handle for attaching additional sinks to this logger. The handle remains usable
+    /// after the logger has been installed with [`init`].
+    ///
+    /// [`init`]: fn.init.html
+    pub fn sinks(&self) -> Sinks {
+        self.sinks.clone()
+    }
+
+    /// Writes `buf` to the output verbatim, without a header or a trailing newline.
+    ///
+    /// The write is serialized with the logger's own writes, so pre-formatted blocks, such as a
+    /// child process's output or a report, can be interleaved with records without racing the
+    /// logger. Like a record, `buf` counts as one write for the flush policy and for `status`.
+    pub fn write_raw(&self, buf: &[u8]) -> io::Result<()> {
+        let _gate = fork::gate();
+        self.drain_staged();
+        let result = lock(&self.out).write_record(buf, false);
+        if let Err(e) = &result {
+            self.health.failed(e);
+        }
+        result
+    }
+
+    /// Pause the logger's output, for example to freeze a console view or to silence a
+    /// misbehaving service without stopping it. While paused, records are held in memory, up to
+    /// `capacity` bytes; records that do not fit are dropped. Sinks are not paused.
+    ///
+    /// Pausing a paused logger changes the capacity. The output is shared with the logger's
+    /// clones and children, so to pause the global logger, keep a clone of it when calling
+    /// [`init`].
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    ///
+    /// let logger = Logger::default();
+    /// logger.pause(64 * 1024);
+    /// // ...
+    /// let dropped = logger.resume();
+    /// ```
+    ///
+    /// [`init`]: fn.init.html
+    pub fn pause(&self, capacity: usize) {
+        let _gate = fork::gate();
+        self.drain_staged();
+        let mut out = lock(&self.out);
+        match &mut out.paused {
+            Some(paused) => paused.capacity = capacity,
+            None => {
+                out.paused = Some(Paused {
+                    held: Vec::new(),
+                    capacity,
+                    dropped: 0,
+                })
+            }
+        }
+    }
+
+    /// Resume output paused with `pause`, writing the held records. Returns the number of
+    /// records dropped while paused because they did not fit.
+    pub fn resume(&self) -> u64 {
+        let _gate = fork::gate();
+        self.drain_staged();
+        let (result, dropped) = {
+            let mut out = lock(&self.out);
+            match out.paused.take() {
+                Some(paused) => {
+                    let result = out.write_record(&paused.held, true);
+                    (result, paused.dropped)
+                }
+                None => return 0,
+            }
+        };
+        self.report(result);
+        dropped
+    }
+
+    /// Returns the number of times writing or flushing the logger's output has failed. See
+    /// `LoggerBuilder::set_error_hook` to be notified of failures.
+    pub fn error_count(&self) -> u64 {
+        self.health.errors()
+    }
+
+    /// Returns the state of the logger's output: failed writes, the time of the last successful
+    /// write, and the number of bytes written. The state is shared with the logger's clones.
+    /// Sinks and the overflow output are not included.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    /// use std::io;
+    ///
+    /// let logger = Logger::builder().set_out(io::sink()).build();
+    /// logger.write_output(log::Level::Info, "myapp", None, None, "hello");
+    /// let status = logger.status();
+    /// assert!(status.is_healthy());
+    /// assert!(status.last_write.is_some());
+    /// ```
+    pub fn status(&self) -> Status {
+        let mut status = self.health.status(None);
+        if let Some(handle) = &self.async_handle {
+            // The background thread writes to the destination, so its state is the one that
+            // tells whether records arrive.
+            let inner = handle.health().status(Some(handle.queue_len()));
+            status.last_write = inner.last_write;
+            status.bytes_written = inner.bytes_written;
+            status.queue_len = inner.queue_len;
+            status.errors += inner.errors;
+            if let Some((time, _)) = &inner.last_error {
+                if status.last_error.as_ref().is_none_or(|(t, _)| t < time) {
+                    status.last_error = inner.last_error;
+                }
+            }
+        }
+        status
+    }
+
+    /// Returns the number of records emitted and dropped so far, by level. The counts are
+    /// shared with the logger's clones.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    /// use std::io;
+    ///
+    /// let logger = Logger::builder().set_out(io::sink()).build();
+    /// logger.write_output(log::Level::Error, "myapp", None, None, "failed");
+    /// assert_eq!(1, logger.stats().emitted(log::Level::Error));
+    /// ```
+    pub fn stats(&self) -> Stats {
+        self.counters.snapshot()
+    }
+
+    /// Returns the number of records that were not kept because of the sample rates set with
+    /// `LoggerBuilder::set_sample_rate`.
+    pub fn sampled_out(&self) -> u64 {
+        self.sampler.as_ref().map_or(0, |s| s.dropped())
+    }
+
+    // Counts and reports a failed write or flush of the output.
+    fn report<T>(&self, result: io::Result<T>) {
+        report(&self.health, self.error_hook.as_ref(), result);
+    }
+
+    // Formats a record with `format`, given its sequence number, and writes it to the output or
+    // stages it. With staging, the stager numbers the records, so that they are written in the
+    // order of their numbers.
+    fn write_line<F: FnOnce(&mut String, u64)>(
+        &self,
+        force_flush: bool,
+        format: F,
+    ) -> io::Result<()> {
+        with_line_buffer(|buf| match &self.staging {
+            Some(staging) => {
+                let ticket = staging.ticket();
+                format(buf, ticket.seq());
+                ticket.push(buf.as_bytes(), force_flush);
+                Ok(())
+            }
+            None => {
+                format(buf, self.next_seq());
+                lock(&self.out).write_record(buf.as_bytes(), force_flush)
+            }
+        })
+    }
+
+    // Writes the staged records, if any, so that data written directly to the output follows
+    // them.
+    fn drain_staged(&self) {
+        if let Some(staging) = &self.staging {
+            staging.drain();
+        }
+    }
+
+    /// Reports whether the logger's output is paused.
+    pub fn is_paused(&self) -> bool {
+        let _gate = fork::gate();
+        lock(&self.out).paused.is_some()
+    }
+
+    /// Calls `f` with the logger's output, like Go's `(*Logger).Writer`. Buffered records are
+    /// flushed first, and the logger's own writes wait until `f` returns, so data written by `f`
+    /// is not interleaved with records. To write bytes only, `write_raw` is simpler.
+    ///
+    /// ```
+    /// use logosaurus::Logger;
+    /// use std::io::{self, Write};
+    ///
+    /// let logger = Logger::builder().set_out(io::stdout()).build();
+    /// logger.with_writer(|w| w.write_all(b"-----\n")).unwrap();
+    /// ```
+    pub fn with_writer<R, F: FnOnce(&mut W) -> R>(&self, f: F) -> R {
+        let _gate = fork::gate();
+        self.drain_staged();
+        let (result, ret) = {
+            let mut out = lock(&self.out);
+            let result = out.flush();
+            (result, f(out.w.get_mut()))
+        };
+        self.report(result);
+        ret
+    }
+
+    /// Returns the logger's flags.
+    pub fn flags(&self) -> Flag {
+        self.settings.flags()
+    }
+
+    /// Set the logger's flags. Records logged after the call use the new flags.
+    pub fn set_flags(&self, flag: Flag) {
+        self.settings.set_flags(flag);
+    }
+
+    /// Returns the logger's prefix.
+    pub fn prefix(&self) -> String {
+        self.settings.prefix().clone()
+    }
+
+    /// Set the logger's prefix. Records logged after the call use the new prefix.
+    pub fn set_prefix(&self, prefix: &str) {
+        self.settings.set_prefix(prefix);
+    }
+
+    /// Returns a new logger that shares this logger's output, overflow output and sinks, and
+    /// starts out with a copy of the rest of its configuration. The child's level, flags and
+    /// prefix can then be changed independently of the parent with `set_level`, `set_flags` and
+    /// `set_prefix`.
+    ///
+    /// Records from the parent and its children are written under the same lock, so they do
+    /// not interleave.
+    ///
+    /// ```
+    /// use logosaurus::{Logger, L_LEVEL};
+    ///
+    /// let logger = Logger::builder().set_flags(L_LEVEL).build();
+    /// let db = logger.child();
+    /// db.set_level(log::LevelFilter::Warn);
+    /// ```
+    pub fn child(&self) -> Logger<W> {
+        Logger {
+            level: Arc::new(level::AtomicLevel::new(self.level.load())),
+            settings: Arc::new(Settings {
+                flag: AtomicU32::new(self.settings.flags().bits()),
+                prefix: RwLock::new(self.settings.prefix().clone()),
+            }),
+            partial_line: Arc::new(Mutex::new(Vec::new())),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a child logger, like `child`, whose prefix is this logger's prefix followed by
+    /// `prefix`.
+    ///
+    /// ```
+    /// use logosaurus::{Logger, L_LEVEL};
+    ///
+    /// let logger = Logger::builder().set_flags(L_LEVEL).build();
+    /// let db = logger.with_prefix("db: ");
+    /// ```
+    pub fn with_prefix(&self, prefix: &str) -> Logger<W> {
+        let child = self.child();
+        child.settings.set_prefix(&(self.prefix() + prefix));
+        child
+    }
+
+    /// Set the logger's level. Levels set for specific files or targets are not affected.
+    ///
+    /// The `log` crate's maximum level is not updated. To change the level of the logger
+    /// installed with [`init`], use the [`LevelHandle`] returned by `init`.
+    ///
+    /// [`init`]: fn.init.html
+    /// [`LevelHandle`]: struct.LevelHandle.html
+    pub fn set_level(&self, level: log::LevelFilter) {
+        self.level.store(level);
+    }
+
+    /// Append `prefix` to the logger's prefix for records logged on the current thread, until the
+    /// returned guard is dropped. This lets a subsystem tag its output without constructing a
+    /// separate logger. Scoped prefixes nest, in the order they were added.
+    ///
+    /// ```
+    /// use logosaurus::{Logger, L_LEVEL};
+    ///
+    /// let logger = Logger::builder().set_flags(L_LEVEL).build();
+    /// {
+    ///     let _guard = logger.scoped_prefix("worker-3: ");
+    ///     logger.write_output(log::Level::Info, "", None, None, "started"); // worker-3: INFO  started
+    /// }
+    /// logger.write_output(log::Level::Info, "", None, None, "done"); // INFO  done
+    /// ```
+    pub fn scoped_prefix(&self, prefix: &str) -> PrefixGuard {
+        prefix::push(self.id(), prefix)
+    }
+
+    // Identifies the logger, for state kept outside of it.
+    fn id(&self) -> usize {
+        Arc::as_ptr(&self.settings) as usize
+    }
+
+    /// Set the logger's output, and return the previous output. The previous output is flushed
+    /// first; data that cannot be flushed is discarded.
+    pub fn set_output(&self, w: W) -> W {
+        let _gate = fork::gate();
+        self.drain_staged();
+        let mut out = lock(&self.out);
+        let _ = out.w.flush();
+        let capacity = out.w.capacity();
+        let prev = std::mem::replace(&mut out.w, io::BufWriter::with_capacity(capacity, w));
+        prev.into_parts().0
+    }
+
+    /// Writes `args` at the `Error` level, flushes the logger, and exits the process with status
+    /// 1, like Go's `log.Fatalf`. The record is written regardless of the logger's level and
+    /// rate limits. Flushing ensures that the record is not lost when the output is buffered.
+    ///
+    /// The [`fatal!`] macro also records the file and line of the call.
+    ///
+    /// ```no_run
+    /// use logosaurus::Logger;
+    ///
+    /// let logger = Logger::default();
+    /// logger.fatal(format_args!("failed to bind to port {}", 8080));
+    /// ```
+    ///
+    /// [`fatal!`]: macro.fatal.html
+    pub fn fatal(&self, args: fmt::Arguments) -> ! {
+        self.__fatal("", None, None, args)
+    }
+
+    #[doc(hidden)]
+    pub fn __fatal(
+        &self,
+        target: &str,
+        file: Option<&str>,
+        line: Option<u32>,
+        args: fmt::Arguments,
+    ) -> ! {
+        self.emit(
+            &log::Record::builder()
+                .args(args)
+                .level(log::Level::Error)
+                .target(target)
+                .file(file)
+                .line(line)
+                .build(),
+        );
+        log::Log::flush(self);
+        std::process::exit(1)
+    }
+
+    /// Writes `args` at the `Error` level, flushes the logger, and panics with the same message,
+    /// like Go's `log.Panicf`. The record is written regardless of the logger's level and rate
+    /// limits.
+    ///
+    /// ```should_panic
+    /// use logosaurus::Logger;
+    ///
+    /// let logger = Logger::default();
+    /// logger.log_panic(format_args!("invalid configuration: {}", "missing port"));
+    /// ```
+    pub fn log_panic(&self, args: fmt::Arguments) -> ! {
+        let msg = format_message(&args);
+        self.emit(
+            &log::Record::builder()
+                .args(format_args!("{}", msg))
+                .level(log::Level::Error)
+                .build(),
+        );
+        log::Log::flush(self);
+        panic!("{}", msg)
+    }
+
+    /// Returns `record` formatted as the logger would write it, including the trailing newline,
+    /// without writing it or applying level filters and rate limits. The record is timestamped
+    /// with the current time.
+    ///
+    /// This is intended for testing the output format, for example to check with a fuzzer or a
+    /// property-based test that every formatted record ends in a newline. Overflow handling is
+    /// not applied, so long messages are formatted in full. With `L_SEQUENCE`, the record gets
+    /// the sequence number of the next record written, and the sequence is not advanced.
+    pub fn format_record(&self, record: &log::Record) -> String {
+        let now = clock::now();
+        let meta = self.record_meta(record);
+        let mut buf = String::new();
+        let seq = self.sequence.load(Ordering::Relaxed);
+        self.push_line(&mut buf, &meta, now, seq, record.args());
+        buf
+    }
+
+    /// Writes the given string `s` using the logger. Typically, you would not use this directly
+    /// but instead use the macros provided by the `log` crate.
+    ///
+    /// To log with a specific `Logger` rather than the global logger, prefer the [`logo_info!`]
+    /// family of macros, which record the location of the call.
+    ///
+    /// [`logo_info!`]: macro.logo_info.html
+    pub fn write_output(
+        &self,
+        level: log::Level,
+        target: &str,
+        file: Option<&str>,
+        line: Option<u32>,
+        s: &str,
+    ) {
+        self.write_record(
+            &log::Record::builder()
+                .args(format_args!("{}", s))
+                .level(level)
+                .target(target)
+                .file(file)
+                .line(line)
+                .build(),
+        );
+    }
+
+    #[doc(hidden)]
+    pub fn __log(
+        &self,
+        level: log::Level,
+        target: &str,
+        file: &'static str,
+        line: u32,
+        args: fmt::Arguments,
+    ) {
+        self.write_record(
+            &log::Record::builder()
+                .args(args)
+                .level(level)
+                .target(target)
+                .file_static(Some(file))
+                .line(Some(line))
+                .build(),
+        );
+    }
+
+    fn write_record(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) || record.level() > self.record_level(record) {
+            return;
+        }
+        if !self.admit(record) {
+            self.counters.dropped(record.level());
+            return;
+        }
+        if self.middleware.is_empty() {
+            self.emit(record);
+        } else {
+            middleware::apply(&self.middleware, record, |record| self.emit(record));
+        }
+    }
+
+    // Applies the filters, sampling, rate limits and repeat suppression to a record that
+    // passed the level filters, and reports whether it should be written.
+    fn admit(&self, record: &log::Record) -> bool {
+        if mute::is_muted(record) || self.filter.as_ref().is_some_and(|f| !f(record)) {
+            return false;
+        }
+        if !self.message_filters.is_empty() {
+            let msg = match record.args().as_str() {
+                Some(s) => Cow::Borrowed(s),
+                None => Cow::Owned(format_message(record.args())),
+            };
+            if !filter::keep_message(&self.message_filters, &msg) {
+                return false;
+            }
+        }
+        if let Some(sampler) = &self.sampler {
+            if !sampler.keep(record.level()) {
+                return false;
+            }
+        }
+        if let Some(bucket) = &self.rate_limits[record.level() as usize - 1] {
+            if !bucket.allow() {
+                return false;
+            }
+        }
+        if let Some(limits) = &self.site_limits {
+            let site = record.file().unwrap_or_else(|| record.target());
+            let line = record.line().unwrap_or(0);
+            let (allow, suppressed) = limits.allow(site, line);
+            if let Some(n) = suppressed {
+                self.emit(
+                    &log::Record::builder()
+                        .args(format_args!(
+                            "suppressed {} records from {}:{}",
+                            n, site, line
+                        ))
+                        .level(record.level())
+                        .target(record.target())
+                        .file(record.file())
+                        .line(record.line())
+                        .build(),
+                );
+            }
+            if !allow {
+                return false;
+            }
+        }
+        if let Some(repeats) = &self.repeats {
+            let msg = match record.args().as_str() {
+                Some(s) => Cow::Borrowed(s),
+                None => Cow::Owned(format_message(record.args())),
+            };
+            let (repeated, summary) = repeats.check(record.level(), record.target(), &msg);
+            if let Some(summary) = summary {
+                self.emit_repeats(summary);
+            }
+            if repeated {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Writes the number of times a record was repeated, for `set_repeat_window`.
+    fn emit_repeats(&self, (level, target, count): repeat::Summary) {
+        self.emit(
+            &log::Record::builder()
+                .args(format_args!("last message repeated {} times", count))
+                .level(level)
+                .target(&target)
+                .build(),
+        );
+    }
+
+    // Writes the record to the output and the sinks, without filtering.
+    fn emit(&self, record: &log::Record) {
+        let _gate = fork::gate();
+        self.counters.emitted(record.level());
+        match self.prefix_route(record) {
+            Some(sink) => sink.log(record),
+            None => {
+                self.write_message(record);
+                self.sinks.log(record);
+            }
+        }
+
+        #[cfg(all(feature = "kv", debug_assertions))]
+        for violation in schema::violations(&kv::fields(record)) {
+            self.emit(
+                &log::Record::builder()
+                    .args(format_args!("{}", violation))
+                    .level(log::Level::Warn)
+                    .target(schema::TARGET)
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            );
+        }
+    }
+
+    // Returns the sink added with `add_prefix_route` for the record's message, if any.
+    fn prefix_route(&self, record: &log::Record) -> Option<&dyn log::Log> {
+        if self.prefix_routes.is_empty() {
+            return None;
+        }
+        let s = match record.args().as_str() {
+            Some(s) => Cow::Borrowed(s),
+            None => Cow::Owned(format_message(record.args())),
+        };
+        self.prefix_routes
+            .iter()
+            .find(|(prefix, _)| s.starts_with(prefix.as_str()))
+            .map(|(_, sink)| sink.as_ref())
+    }
+
+    fn write_message(&self, record: &log::Record) {
+        let now = clock::now(); // get this early
+        let force_flush = self.flush_level.is_some_and(|l| record.level() <= l);
+        let meta = self.record_meta(record);
+
+        // The message is formatted separately only when its length is needed for the overflow
+        // output.
+        let s = match &self.overflow {
+            Some(overflow) => {
+                let s = format_message(record.args());
+                if s.len() > overflow.limit {
+                    self.write_overflow(overflow, &meta, now, &s, force_flush);
+                    return;
+                }
+                Some(s)
+            }
+            None => None,
+        };
+
+        // Each record is formatted in full and handed to the writer in a single call, so that
+        // writers such as `AsyncWriter` see whole records.
+        let result = self.write_line(force_flush, |buf, seq| match &s {
+            Some(s) => self.push_line(buf, &meta, now, seq, s),
+            None => self.push_line(buf, &meta, now, seq, record.args()),
+        });
+        self.report(result);
+    }
+
+    // Writes the oversized message `s` to the overflow output, and a placeholder referring to it
+    // to the main output.
+    fn write_overflow(
+        &self,
+        overflow: &Overflow,
+        meta: &Meta,
+        now: chrono::DateTime<chrono::FixedOffset>,
+        s: &str,
+        force_flush: bool,
+    ) {
+        // The record in the overflow output and the placeholder share a sequence number.
+        let result = self.write_line(force_flush, |buf, seq| {
+            let (id, result) = {
+                let mut o = lock(&overflow.out);
+                let (w, count) = &mut *o;
+                *count += 1;
+                self.push_line(
+                    buf,
+                    meta,
+                    now,
+                    seq,
+                    format_args!("[overflow #{}] {}", count, s),
+                );
+                (*count, w.write_all(buf.as_bytes()))
+            };
+            self.report(result);
+            buf.clear();
+            self.push_line(
+                buf,
+                meta,
+                now,
+                seq,
+                format_args!(
+                    "[overflow #{}] message of {} bytes written to overflow output",
+                    id,
+                    s.len()
+                ),
+            );
+        });
+        self.report(result);
+    }
+
+    // Collects the metadata of `record`, including its key-value pairs when the `kv` feature is
+    // enabled.
+    fn record_meta<'a>(&self, record: &'a log::Record) -> Meta<'a> {
+        #[allow(unused_mut)]
+        let mut meta = self.meta(
+            record.level(),
+            record.target(),
+            record.file(),
+            record.line(),
+        );
+        meta.file_static = record.file_static();
+        #[cfg(feature = "kv")]
+        meta.fields.extend(kv::fields(record));
+        meta
+    }
+
+    // Collects the per-record metadata, including the record ID and the current context's
+    // fields.
+    fn meta<'a>(
+        &self,
+        level: log::Level,
+        target: &'a str,
+        file: Option<&'a str>,
+        line: Option<u32>,
+    ) -> Meta<'a> {
+        let mut fields: Vec<(String, String)> = self
+            .target_fields
+            .iter()
+            .filter(|(namespace, _, _)| filter::target_matches(namespace, target))
+            .map(|(_, key, value)| (key.clone(), value.clone()))
+            .collect();
+        if let Some(id_generator) = &self.id_generator {
+            fields.push((String::from("id"), id_generator()));
+        }
+        fields.extend(context::fields());
+        Meta {
+            level,
+            target,
+            file: file.unwrap_or("???"),
+            file_static: None,
+            line: line.unwrap_or(0),
+            fields,
+            panic: panic_hook::current(),
+        }
+    }
+
+    // Formats a complete record to be written, including the trailing newline. This takes the
+    // next sequence number.
+    fn format_line<Tz: chrono::TimeZone, M: fmt::Display>(
+        &self,
+        meta: &Meta,
+        now: chrono::DateTime<Tz>,
+        msg: M,
+    ) -> String
+    where
+        Tz::Offset: fmt::Display,
+    {
+        let mut buf = String::new();
+        self.push_line(&mut buf, meta, now, self.next_seq(), msg);
+        buf
+    }
+
+    // Returns the sequence number of the next record to be written, and advances the sequence.
+    // `fetch_add` wraps around on overflow.
+    fn next_seq(&self) -> u64 {
+        if self.settings.flags().intersects(L_SEQUENCE) {
+            self.sequence.fetch_add(1, Ordering::Relaxed)
+        } else {
+            0
+        }
+    }
+
+    // Appends a complete record with the sequence number `seq` to `buf`, like `format_line`.
+    //
+    // In the text format, the message is written straight after the header, without an
+    // intermediate String.
+    fn push_line<Tz: chrono::TimeZone, M: fmt::Display>(
+        &self,
+        buf: &mut String,
+        meta: &Meta,
+        now: chrono::DateTime<Tz>,
+        seq: u64,
+        msg: M,
+    ) where
+        Tz::Offset: fmt::Display,
+    {
+        let (level, target, file, line) = (meta.level, meta.target, meta.file, meta.line);
+        let record_start = buf.len();
+        let now = match &self.timezone {
+            Some(tz) => tz(now.with_timezone(&chrono::Utc)),
+            None => now.fixed_offset(),
+        };
+        let flag = self.settings.flags();
+        match self.format {
+            Format::Text => {
+                let header_start = buf.len();
+                format_header(
+                    buf,
+                    meta,
+                    now,
+                    flag,
+                    &prefix::scoped(self.id(), &self.settings.prefix()),
+                    &self.header,
+                    seq,
+                );
+                let start = buf.len();
+                push_message(buf, &msg);
+                self.redact_message(buf, start);
+                self.truncate_message(buf, start);
+                if self.escape_control {
+                    self.escape_control(buf, start);
+                }
+                if self.multiline != Multiline::Keep {
+                    self.split_lines(buf, header_start, start);
+                }
+                if !self.fields.is_empty() || !meta.fields.is_empty() {
+                    if self.newline && buf[start..].ends_with('\n') {
+                        buf.pop();
+                    }
+                    self.push_fields(buf, &meta.fields);
+                }
+                if self.newline && !buf[start..].ends_with('\n') {
+                    buf.push('\n');
+                }
+            }
+            Format::Json => {
+                let mut s = String::new();
+                push_message(&mut s, &msg);
+                self.redact_message(&mut s, 0);
+                self.truncate_message(&mut s, 0);
+                let mut obj = json::Object::new(buf);
+                if flag.intersects(L_SEQUENCE) {
+                    obj.raw("seq", &seq.to_string());
+                }
+                if flag.intersects(L_EPOCH) {
+                    let mut time = String::new();
+                    push_epoch(&mut time, flag, now);
+                    obj.raw("time", &time);
+                } else if flag.intersects(L_DATE | TIME_FLAGS) {
+                    let precision = if flag.intersects(L_NANOSECONDS) {
+                        chrono::SecondsFormat::Nanos
+                    } else if flag.intersects(L_MICROSECONDS) {
+                        chrono::SecondsFormat::Micros
+                    } else {
+                        chrono::SecondsFormat::Millis
+                    };
+                    let time = if flag.intersects(L_UTC) {
+                        now.with_timezone(&chrono::Utc)
+                            .to_rfc3339_opts(precision, true)
+                    } else {
+                        now.to_rfc3339_opts(precision, true)
+                    };
+                    obj.str("time", &time);
+                }
+                if flag.intersects(L_LEVEL) {
+                    obj.str("level", &level.as_str().to_lowercase());
+                }
+                let base = self.settings.prefix();
+                let prefix = prefix::scoped(self.id(), &base);
+                if !prefix.is_empty() {
+                    obj.str("prefix", &prefix);
+                }
+                if flag.intersects(L_HOSTNAME) {
+                    obj.str("hostname", &self.header.hostname);
+                }
+                if flag.intersects(L_PID) {
+                    obj.raw("pid", &std::process::id().to_string());
+                }
+                if flag.intersects(L_THREAD_NAME) {
+                    let mut thread = String::new();
+                    push_thread_name(&mut thread);
+                    obj.str("thread", &thread);
+                }
+                if flag.intersects(L_LONG_FILE | L_TARGET) {
+                    obj.str("target", target);
+                }
+                if flag.intersects(L_LONG_FILE | L_SHORT_FILE) {
+                    obj.str("file", file_for_flags(file, flag));
+                    obj.raw("line", &line.to_string());
+                }
+                obj.str("msg", self.strip_newline(&s));
+                if let Some(panic) = &meta.panic {
+                    let mut location = String::new();
+                    let mut loc = json::Object::new(&mut location);
+                    loc.str("file", &panic.file);
+                    loc.raw("line", &panic.line.to_string());
+                    loc.raw("column", &panic.column.to_string());
+                    loc.end();
+                    obj.raw("panic", "true");
+                    obj.raw("location", &location);
+                }
+                for (key, value) in self.fields.iter().chain(&meta.fields) {
+                    let value = &*redact::apply(&self.redactions, value);
+                    // Fields named like a built-in key are prefixed, so that they cannot be
+                    // mistaken for it or produce duplicate keys.
+                    if JSON_KEYS.contains(&key.as_str()) {
+                        obj.str(&format!("fields.{}", key), value);
+                    } else {
+                        obj.str(key, value);
+                    }
+                }
+                obj.end();
+                if self.newline {
+                    buf.push('\n');
+                }
+            }
+        }
+        if self.crlf {
+            to_crlf(buf, record_start);
+        }
+    }
+
+    // Masks the matches of the redaction patterns in the message at `buf[start..]`. This runs
+    // before truncation, so that truncation cannot cut a match short and leave part of it
+    // unmasked.
+    fn redact_message(&self, buf: &mut String, start: usize) {
+        if self.redactions.is_empty() {
+            return;
+        }
+        if let Cow::Owned(redacted) = redact::apply(&self.redactions, &buf[start..]) {
+            buf.truncate(start);
+            buf.push_str(&redacted);
+        }
+    }
+
+    // Truncates the message at `buf[start..]` to the length set with `set_max_message_len`.
+    fn truncate_message(&self, buf: &mut String, start: usize) {
+        use fmt::Write as _;
+
+        let limit = match self.max_message_len {
+            Some(limit) => limit,
+            None => return,
+        };
+        let len = buf.len() - start - usize::from(self.newline && buf[start..].ends_with('\n'));
+        if len <= limit {
+            return;
+        }
+        let mut end = start + limit;
+        while !buf.is_char_boundary(end) {
+            end -= 1;
+        }
+        let removed = start + len - end;
+        buf.truncate(end);
+        let _ = write!(buf, " …[truncated {} bytes]", removed);
+    }
+
+    // Escapes the control characters in the message at `buf[start..]`, for `set_escape_control`.
+    fn escape_control(&self, buf: &mut String, start: usize) {
+        let end = if self.newline && buf.ends_with('\n') {
+            buf.len() - 1
+        } else {
+            buf.len()
+        };
+        if !buf[start..end].contains(is_escaped_control) {
+            return;
+        }
+        let rest = buf.split_off(start);
+        for c in rest[..end - start].chars() {
+            if is_escaped_control(c) {
+                buf.extend(c.escape_default());
+            } else {
+                buf.push(c);
+            }
+        }
+        buf.push_str(&rest[end - start..]);
+    }
+
+    // Applies the multi-line policy to the message at `buf[start..]`, whose header is
+    // `buf[header_start..start]`.
+    fn split_lines(&self, buf: &mut String, header_start: usize, start: usize) {
+        if !buf[start..].contains(['\n', '\r']) {
+            return;
+        }
+        let mut msg = buf.split_off(start);
+        let trailing = self.newline && msg.ends_with('\n');
+        if trailing {
+            msg.pop();
+        }
+        let header = String::from(&buf[header_start..]);
+        for (i, line) in msg.split('\n').enumerate() {
+            match self.multiline {
+                Multiline::Escape => {
+                    if i > 0 {
+                        buf.push_str("\\n");
+                    }
+                    buf.push_str(&line.replace('\r', "\\r"));
+                    continue;
+                }
+                _ if i == 0 => {}
+                Multiline::Keep => buf.push('\n'),
+                Multiline::RepeatHeader => {
+                    buf.push('\n');
+                    buf.push_str(&header);
+                }
+                Multiline::Indent => buf.push_str("\n\t"),
+            }
+            buf.push_str(line);
+        }
+        if trailing {
+            buf.push('\n');
+        }
+    }
+
+    // Removes the trailing newline from `s`, unless newline normalization is disabled.
+    fn strip_newline<'a>(&self, s: &'a str) -> &'a str {
+        if self.newline {
+            s.strip_suffix('\n').unwrap_or(s)
+        } else {
+            s
+        }
+    }
+
+    // Appends the logger's fields and the record's fields to `buf`.
+    fn push_fields(&self, buf: &mut String, record_fields: &[(String, String)]) {
+        for (key, value) in self.fields.iter().chain(record_fields) {
+            let value = &*redact::apply(&self.redactions, value);
+            buf.push(' ');
+            buf.push_str(key);
+            buf.push('=');
+            if value.is_empty()
+                || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=')
+                || (self.escape_control && value.contains(is_escaped_control))
+            {
+                buf.push_str(&format!("{:?}", value));
+            } else {
+                buf.push_str(value);
+            }
+        }
+    }
+
+    // Reports whether a record with the given metadata may be logged. The source file is not
+    // known at this point, so any file level could still apply.
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        if !filter::target_allowed(
+            &self.allowed_targets,
+            &self.denied_targets,
+            metadata.target(),
+        ) {
+            return false;
+        }
+        let level = self
+            .file_levels
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.target_level(metadata.target()), std::cmp::max);
+        metadata.level() <= level
+    }
+
+    // Returns the most verbose level that any record can be logged at.
+    fn max_level(&self) -> log::LevelFilter {
+        std::cmp::max(self.level.load(), self.directive_level())
+    }
+
+    // Returns the most verbose of the file and target levels.
+    fn directive_level(&self) -> log::LevelFilter {
+        self.file_levels
+            .iter()
+            .chain(&self.target_levels)
+            .map(|(_, level)| *level)
+            .max()
+            .unwrap_or(log::LevelFilter::Off)
+    }
+
+    fn target_level(&self, target: &str) -> log::LevelFilter {
+        filter::target_level(&self.target_levels, target).unwrap_or_else(|| self.level.load())
+    }
+
+    // Returns the allowed level for the record, taking file and target levels into account.
+    fn record_level(&self, record: &log::Record) -> log::LevelFilter {
+        record
+            .file()
+            .and_then(|file| {
+                self.file_levels
+                    .iter()
+                    .rev()
+                    .find(|(pattern, _)| glob::matches(pattern, file))
+            })
+            .map_or_else(|| self.target_level(record.target()), |(_, level)| *level)
+    }
+}
+
+// Locks `m`, ignoring poisoning. A panic while the lock was held, for example in a writer,
+// leaves the output usable, and logging must not panic.
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Counts and reports a failed write or flush of a logger's output.
+fn report<T>(health: &status::Health, hook: Option<&ErrorHook>, result: io::Result<T>) {
+    if let Err(e) = result {
+        health.failed(&e);
+        if let Some(hook) = hook {
+            hook(&e);
+        }
+    }
+}
+
+// Formats the message of a record. Unlike `to_string`, this does not panic if a `Display`
+// implementation in the arguments returns an error; the message is marked instead.
+fn format_message(args: &fmt::Arguments) -> String {
+    let mut s = String::new();
+    push_message(&mut s, args);
+    s
+}
+
+// The capacity above which the per-thread line buffer is released rather than kept for reuse,
+// so that a single huge record does not hold on to memory.
+const MAX_LINE_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    static LINE: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+// Calls `f` with an empty buffer for formatting a record. The buffer is reused by later records
+// on the same thread, so that formatting a record does not allocate in the common case.
+fn with_line_buffer<R, F: FnOnce(&mut String) -> R>(f: F) -> R {
+    let mut f = Some(f);
+    let reused = LINE.try_with(|line| {
+        // The buffer is in use if formatting the message logs another record.
+        let mut buf = line.try_borrow_mut().ok()?;
+        let f = f.take()?;
+        buf.clear();
+        let r = f(&mut buf);
+        if buf.capacity() > MAX_LINE_CAPACITY {
+            *buf = String::new();
+        }
+        Some(r)
+    });
+    match (reused, f) {
+        (Ok(Some(r)), _) => r,
+        (_, Some(f)) => f(&mut String::new()),
+        (_, None) => unreachable!("line buffer callback consumed without a result"),
+    }
+}
+
+// Converts the line endings in `buf[start..]` to `\r\n`.
+fn to_crlf(buf: &mut String, start: usize) {
+    if !buf[start..].contains('\n') {
+        return;
+    }
+    let rest = buf.split_off(start);
+    let mut prev = '\0';
+    for c in rest.chars() {
+        if c == '\n' && prev != '\r' {
+            buf.push('\r');
+        }
+        buf.push(c);
+        prev = c;
+    }
+}
+
+// Reports whether `c` is escaped with `set_escape_control`.
+fn is_escaped_control(c: char) -> bool {
+    c.is_control() && c != '\t'
+}
+
+// Appends `msg` to `buf`, like `format_message`.
+fn push_message<M: fmt::Display + ?Sized>(buf: &mut String, msg: &M) {
+    use fmt::Write as _;
+
+    if write!(buf, "{}", msg).is_err() {
+        buf.push_str("<formatting error>");
+    }
+}
+
+// Returns the file name as it should be printed for the given flags.
+fn file_for_flags(file: &str, flag: Flag) -> &str {
+    if !flag.intersects(L_SHORT_FILE) {
+        return file;
+    }
+    // The final path component, as a slice of `file`. Both separators are accepted regardless
+    // of the platform, since the file names come from the compiling machine.
+    let base = match file.rfind(['/', '\\']) {
+        Some(i) => &file[i + 1..],
+        None => file,
+    };
+    match base {
+        "" | "." | ".." => "???",
+        base => base,
+    }
+}
+
+fn format_datetime<Tz: chrono::TimeZone>(buf: &mut String, flag: Flag, now: chrono::DateTime<Tz>)
+where
+    Tz::Offset: fmt::Display,
+{
+    use fmt::Write as _;
+
+    if flag.intersects(L_DATE) {
+        let year = now.year();
+        if (0..=9999).contains(&year) {
+            push_int(buf, year as u32, 4);
+        } else {
+            // Writing to a String only fails if a Display implementation fails, which chrono's
+            // does not for this format string.
+            let _ = write!(buf, "{}", now.format("%Y"));
+        }
+        buf.push('/');
+        push_int(buf, now.month(), 2);
+        buf.push('/');
+        push_int(buf, now.day(), 2);
+        buf.push(' ');
+    }
+    if flag.intersects(TIME_FLAGS) {
+        push_int(buf, now.hour(), 2);
+        buf.push(':');
+        push_int(buf, now.minute(), 2);
+        buf.push(':');
+        push_int(buf, now.second(), 2);
+        push_fraction(buf, flag, now.nanosecond());
+        buf.push(' ');
+    }
+}
+
+// Appends the fractional seconds for the resolution flags in `flag`, if any.
+fn push_fraction(buf: &mut String, flag: Flag, nanos: u32) {
+    if flag.intersects(L_NANOSECONDS) {
+        buf.push('.');
+        push_int(buf, nanos, 9);
+    } else if flag.intersects(L_MICROSECONDS) {
+        buf.push('.');
+        push_int(buf, nanos / 1000, 6);
+    } else if flag.intersects(L_MILLISECONDS) {
+        buf.push('.');
+        push_int(buf, nanos / 1_000_000, 3);
+    }
+}
+
+// Appends `now` as Unix epoch seconds, for `L_EPOCH`.
+fn push_epoch<Tz: chrono::TimeZone>(buf: &mut String, flag: Flag, now: chrono::DateTime<Tz>) {
+    use fmt::Write as _;
+
+    let _ = write!(buf, "{}", now.timestamp());
+    push_fraction(buf, flag, now.timestamp_subsec_nanos());
+}
+
+// Appends `n` in decimal, padded with zeros to at least `width` digits.
+fn push_int(buf: &mut String, n: u32, width: usize) {
+    let mut digits = [b'0'; 10];
+    let mut i = digits.len();
+    let mut n = n;
+    loop {
+        i -= 1;
+        digits[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    let start = i.min(digits.len().saturating_sub(width));
+    for &d in &digits[start..] {
+        buf.push(char::from(d));
+    }
+}
+
+fn format_header<Tz: chrono::TimeZone>(
+    buf: &mut String,
+    meta: &Meta,
+    now: chrono::DateTime<Tz>,
+    flag: Flag,
+    prefix: &str,
+    style: &HeaderStyle,
+    seq: u64,
+) where
+    Tz::Offset: fmt::Display,
+{
+    let level_prefix = &style.level_prefixes[meta.level as usize - 1];
+    if !flag.intersects(L_MSG_PREFIX) {
+        buf.push_str(level_prefix);
+        buf.push_str(prefix);
+    }
+
+    for field in style.order.iter() {
+        push_header_field(buf, *field, meta, &now, flag, style, seq);
+    }
+
+    if flag.intersects(L_MSG_PREFIX) {
+        buf.push_str(level_prefix);
+        buf.push_str(prefix);
+    }
+}
+
+// Writes one part of the header, if its flag is set.
+fn push_header_field<Tz: chrono::TimeZone>(
+    buf: &mut String,
+    field: HeaderField,
+    meta: &Meta,
+    now: &chrono::DateTime<Tz>,
+    flag: Flag,
+    style: &HeaderStyle,
+    seq: u64,
+) where
+    Tz::Offset: fmt::Display,
+{
+    match field {
+        HeaderField::Sequence if flag.intersects(L_SEQUENCE) => {
+            use fmt::Write as _;
+
+            let _ = write!(buf, "#{} ", seq);
+        }
+        HeaderField::Level if flag.intersects(L_LEVEL) => {
+            let label = &style.level_labels[meta.level as usize - 1];
+            buf.push_str(label);
+            for _ in label.chars().count()..style.level_width {
+                buf.push(' ');
+            }
+            buf.push(' ');
+        }
+        HeaderField::Time if flag.intersects(L_EPOCH) => {
+            push_epoch(buf, flag, now.clone());
+            buf.push(' ');
+        }
+        HeaderField::Time if flag.intersects(L_DATE | TIME_FLAGS) => {
+            if flag.intersects(L_UTC) {
+                format_datetime(buf, flag, now.with_timezone(&chrono::Utc));
+            } else {
+                format_datetime(buf, flag, now.clone());
+            }
+        }
+        HeaderField::Hostname if flag.intersects(L_HOSTNAME) => {
+            buf.push_str(&style.hostname);
+            buf.push(' ');
+        }
+        HeaderField::Pid if flag.intersects(L_PID) => {
+            buf.push('[');
+            push_int(buf, std::process::id(), 1);
+            buf.push_str("] ");
+        }
+        HeaderField::Thread if flag.intersects(L_THREAD_NAME) => {
+            buf.push('[');
+            push_thread_name(buf);
+            buf.push_str("] ");
+        }
+        HeaderField::Location if flag.intersects(L_LONG_FILE | L_SHORT_FILE | L_TARGET) => {
+            let start = buf.len();
+            if !flag.intersects(L_LONG_FILE | L_SHORT_FILE) {
+                buf.push_str(meta.target);
+                buf.push_str(": ");
+            } else if let Some(file) = meta.file_static {
+                site::push_location(buf, meta.target, file, meta.line, flag);
+            } else {
+                format_location(buf, meta.target, meta.file, meta.line, flag);
+            }
+            for _ in buf[start..].chars().count()..style.location_width {
+                buf.push(' ');
+            }
+        }
+        _ => {}
+    }
+}
+
+// Appends the name of the current thread, or its ID if it is unnamed.
+fn push_thread_name(buf: &mut String) {
+    use fmt::Write as _;
+
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => buf.push_str(name),
+        None => {
+            let _ = write!(buf, "{:?}", thread.id());
+        }
+    }
+}
+
+// Writes the target (with `L_LONG_FILE` or `L_TARGET`), file and line part of the header.
+fn format_location(buf: &mut String, target: &str, file: &str, line: u32, flag: Flag) {
+    if flag.intersects(L_LONG_FILE | L_TARGET) {
+        buf.push_str(target);
+        buf.push(' ');
+    }
+    buf.push_str(file_for_flags(file, flag));
+    buf.push(':');
+    push_int(buf, line, 0);
+    buf.push_str(": ");
+}
+
+impl Logger<io::Stdout> {
+    /// Returns a `Logger` preconfigured for running in a container: JSON to stdout with UTC
+    /// timestamps in RFC 3339 format with millisecond precision, and lowercase levels. This is
+    /// the shape that Docker and Kubernetes log drivers and most log collectors expect.
+    ///
+    /// ```txt
+    /// {"time":"2020-10-02T21:27:03.123Z","level":"info","msg":"hello, world"}
+    /// ```
+    pub fn container() -> Logger<io::Stdout> {
+        Logger::builder()
+            .set_out(io::stdout())
+            .set_format(Format::Json)
+            .set_flags(L_DATE | L_TIME | L_UTC | L_LEVEL)
+            .build()
+    }
+}
+
+impl Default for Logger<io::Stderr> {
+    /// Returns a default `Logger`.
+    ///
+    /// A default `Logger` has
+    ///   * level:  `log::LevelFilter::Trace`,
+    ///   * out:    stderr,
+    ///   * flags:  `L_STD`, and
+    ///   * prefix: `""` (empty string)
+    ///
+    fn default() -> Logger<io::Stderr> {
+        Logger::builder().build()
+    }
+}
+
+/// Writing to a `&Logger` logs every line written as a record at the `Info` level, with the
+/// configured header. This allows output from code that expects a writer, such as a third-party
+/// library, to be formatted like the rest of the log.
+///
+/// Incomplete lines are kept until the rest of the line is written or the writer is flushed.
+/// Invalid UTF-8 is replaced with U+FFFD.
+///
+/// ```
+/// use logosaurus::Logger;
+/// use std::io::Write;
+///
+/// let logger = Logger::default();
+/// writeln!(&logger, "from a writer").unwrap(); // INFO  2020/10/02 21:27:03 from a writer
+/// ```
+impl<W: Write + Send> Write for &Logger<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The complete lines are taken out under the lock and logged after releasing it, so
+        // that writers do not wait for each other's records to be written.
+        let complete = {
+            let mut partial = lock(&self.partial_line);
+            partial.extend_from_slice(buf);
+            match partial.iter().rposition(|b| *b == b'\n') {
+                Some(i) => partial.drain(..=i).collect::<Vec<u8>>(),
+                None => return Ok(buf.len()),
+            }
+        };
+        for line in complete[..complete.len() - 1].split(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(line);
+            self.write_output(log::Level::Info, "", None, None, &line);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let line = std::mem::take(&mut *lock(&self.partial_line));
+        if !line.is_empty() {
+            let line = String::from_utf8_lossy(&line);
+            self.write_output(log::Level::Info, "", None, None, &line);
+        }
+        log::Log::flush(*self);
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> log::Log for Logger<W> {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.write_record(record);
+    }
+
+    fn flush(&self) {
+        if let Some(summary) = self.repeats.as_ref().and_then(|r| r.take()) {
+            self.emit_repeats(summary);
+        }
+        let _gate = fork::gate();
+        self.drain_staged();
+        let result = lock(&self.out).flush();
+        self.report(result);
+        if let Some(overflow) = &self.overflow {
+            let result = lock(&overflow.out).0.flush();
+            self.report(result);
+        }
+        self.sinks.flush();
+        for (_, sink) in self.prefix_routes.iter() {
+            sink.flush();
+        }
+    }
+}
+
+mod async_writer;
+mod clock;
+pub mod context;
+mod fallback;
+pub use fallback::FallbackWriter;
+mod file;
+pub use file::{FileWriter, FileWriterBuilder};
+mod filter;
+pub use filter::MessageFilter;
+pub mod fork;
+mod glob;
+mod host;
+pub mod id;
+mod json;
+#[cfg(feature = "kv")]
+mod kv;
+mod level;
+mod macros;
+mod middleware;
+pub use middleware::RecordMut;
+mod mute;
+pub use mute::{mute_site, unmute_site};
+#[doc(hidden)]
+pub mod __private {
+    pub use log::Level;
+}
+mod panic_hook;
+pub use level::LevelHandle;
+pub use panic_hook::log_panics;
+mod prefix;
+pub use prefix::PrefixGuard;
+mod ratelimit;
+pub mod redact;
+mod regex;
+mod repeat;
+mod run;
+mod sample;
+pub use run::run;
+#[cfg(feature = "kv")]
+pub mod schema;
+
+#[cfg(feature = "kubernetes")]
+pub mod kubernetes;
+
+#[cfg(feature = "syslog")]
+pub mod syslog;
+
+#[cfg(all(unix, feature = "journald"))]
+pub mod journald;
+
+#[cfg(all(target_os = "android", feature = "android"))]
+pub mod android;
+pub use async_writer::{AsyncHandle, AsyncWriter, Backpressure};
+
+mod net;
+pub use net::{TcpWriter, UdpWriter};
+
+mod sink;
+mod site;
+mod staging;
+mod stats;
+mod status;
+mod url;
+pub use sink::{SinkId, Sinks};
+pub use stats::Stats;
+pub use status::Status;
+pub use url::sink_from_url;
+
+pub mod testing;
+
+#[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::Buffer;
     use chrono::prelude::*;
 
-    // helper
-    fn header<Tz: chrono::TimeZone>(
-        target: &str,
-        file: &str,
-        line: u32,
-        level: log::Level,
-        now: chrono::DateTime<Tz>,
-        flag: Flag,
-        prefix: &str,
-    ) -> String
-    where
-        Tz::Offset: fmt::Display,
-    {
-        let mut buf = String::new();
-        format_header(&mut buf, target, file, line, level, now, flag, prefix);
-        buf
+    // helper
+    fn header<Tz: chrono::TimeZone>(
+        target: &str,
+        file: &str,
+        line: u32,
+        level: log::Level,
+        now: chrono::DateTime<Tz>,
+        flag: Flag,
+        prefix: &str,
+    ) -> String
+    where
+        Tz::Offset: fmt::Display,
+    {
+        let meta = Meta {
+            level,
+            target,
+            file,
+            file_static: None,
+            line,
+            fields: Vec::new(),
+            panic: None,
+        };
+        let mut buf = String::new();
+        let mut style = HeaderStyle::new(None, None, None, Default::default(), &[], 0);
+        style.hostname = String::from("host1");
+        format_header(&mut buf, &meta, now, flag, prefix, &style, 42);
+        buf
+    }
+
+    #[test]
+    fn test_header() {
+        let time = NaiveDate::from_ymd_opt(2020, 10, 3)
+            .unwrap()
+            .and_hms_micro_opt(1, 2, 3, 9876)
+            .unwrap()
+            .and_local_timezone(FixedOffset::east_opt(3600 * 5 + 1800).unwrap())
+            .unwrap();
+
+        let flags = L_STD | L_MICROSECONDS | L_SHORT_FILE;
+        let expect = "TRACE 2020/10/03 01:02:03.009876 file.rs:9: ";
+        let got = header(
+            "foo",
+            "src/dir/file.rs",
+            9,
+            log::Level::Trace,
+            time,
+            flags,
+            "",
+        );
+        assert_eq!(expect, got);
+
+        let flags = L_MILLISECONDS;
+        let got = header("foo", "file.rs", 9, log::Level::Info, time, flags, "");
+        assert_eq!("01:02:03.009 ", got);
+        let flags = L_MILLISECONDS | L_MICROSECONDS;
+        let got = header("foo", "file.rs", 9, log::Level::Info, time, flags, "");
+        assert_eq!("01:02:03.009876 ", got);
+        let flags = L_MICROSECONDS | L_NANOSECONDS;
+        let got = header("foo", "file.rs", 9, log::Level::Info, time, flags, "");
+        assert_eq!("01:02:03.009876000 ", got);
+        let got = header(
+            "foo",
+            "src/file.rs",
+            9,
+            log::Level::Info,
+            time,
+            L_TARGET,
+            "",
+        );
+        assert_eq!("foo: ", got);
+        let flags = L_TARGET | L_SHORT_FILE;
+        let got = header("foo", "src/file.rs", 9, log::Level::Info, time, flags, "");
+        assert_eq!("foo file.rs:9: ", got);
+        let flags = L_SEQUENCE | L_LEVEL;
+        let got = header("foo", "src/file.rs", 9, log::Level::Info, time, flags, "");
+        assert_eq!("#42 INFO  ", got);
+        let flags = L_TIME | L_HOSTNAME | L_PID;
+        let got = header("foo", "file.rs", 9, log::Level::Info, time, flags, "");
+        assert_eq!(format!("01:02:03 host1 [{}] ", std::process::id()), got);
+        let flags = L_STD | L_EPOCH;
+        let got = header("foo", "file.rs", 9, log::Level::Info, time, flags, "");
+        assert_eq!("INFO  1601667123 ", got);
+        let flags = L_EPOCH | L_MILLISECONDS;
+        let got = header("foo", "file.rs", 9, log::Level::Info, time, flags, "");
+        assert_eq!("1601667123.009 ", got);
+
+        let flags = L_DATE | L_TIME | L_UTC | L_LONG_FILE;
+        let expect = "2020/10/02 19:32:03 foo src/dir/file.rs:9: ";
+        let got = header(
+            "foo",
+            "src/dir/file.rs",
+            9,
+            log::Level::Info,
+            time,
+            flags,
+            "",
+        );
+        assert_eq!(expect, got);
+
+        let flags = L_TIME | L_LEVEL;
+        let prefix = "myprog: ";
+        let expect = "myprog: INFO  01:02:03 ";
+        let got = header(
+            "foo",
+            "src/dir/file.rs",
+            9,
+            log::Level::Info,
+            time,
+            flags,
+            prefix,
+        );
+        assert_eq!(expect, got);
+
+        let flags = L_MSG_PREFIX | L_TIME | L_LEVEL;
+        let expect = "INFO  01:02:03 myprog: ";
+        let got = header(
+            "foo",
+            "src/dir/file.rs",
+            9,
+            log::Level::Info,
+            time,
+            flags,
+            prefix,
+        );
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_push_int() {
+        let mut buf = String::new();
+        for (n, width) in [(0, 0), (7, 2), (123, 2), (9876, 6), (u32::MAX, 4)].iter() {
+            push_int(&mut buf, *n, *width);
+            buf.push(' ');
+        }
+        assert_eq!("0 07 123 009876 4294967295 ", buf);
+    }
+
+    #[test]
+    fn test_file_for_flags() {
+        let cases = [
+            ("src/dir/file.rs", "file.rs"),
+            ("file.rs", "file.rs"),
+            ("/abs/file.rs", "file.rs"),
+            ("C:\\src\\file.rs", "file.rs"),
+            ("src/dir/", "???"),
+            ("", "???"),
+            ("..", "???"),
+        ];
+        for (file, short) in cases.iter() {
+            assert_eq!(*short, file_for_flags(file, L_SHORT_FILE), "{}", file);
+            assert_eq!(*file, file_for_flags(file, L_LONG_FILE));
+        }
+    }
+
+    #[test]
+    fn test_flags_format_parse() {
+        assert_eq!("L_DATE | L_TIME | L_LEVEL", format_flags(L_STD));
+        assert_eq!("L_NONE", format_flags(L_NONE));
+        assert_eq!(Ok(L_STD | L_SHORT_FILE), parse_flags("L_STD|L_SHORT_FILE"));
+        assert_eq!(Ok(L_STD), parse_flags(&format_flags(L_STD)));
+        assert_eq!(Ok(L_NONE), parse_flags("L_NONE"));
+        assert_eq!(
+            "unknown flag \"L_BOGUS\"",
+            parse_flags("L_DATE | L_BOGUS").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_flags_type() {
+        let flag = L_STD | L_SHORT_FILE;
+        assert!(flag.contains(L_DATE | L_TIME));
+        assert!(!flag.contains(L_DATE | L_UTC));
+        assert!(flag.intersects(L_LONG_FILE | L_SHORT_FILE));
+        assert!(L_NONE.is_empty());
+        assert_eq!(L_DATE | L_TIME | L_SHORT_FILE, flag & !L_LEVEL);
+        assert_eq!(Flags::STD, L_STD);
+        assert_eq!(L_LEVEL, Flags::from_bits_truncate(128 | 1 << 20));
+        assert_eq!("L_DATE | L_TIME", (L_DATE | L_TIME).to_string());
+        assert_eq!("Flags(L_NONE)", format!("{:?}", L_NONE));
+    }
+
+    #[test]
+    fn test_from_go_flags() {
+        assert_eq!(L_NONE, from_go_flags(0));
+        assert_eq!(L_DATE | L_TIME, from_go_flags(3)); // log.LstdFlags
+        assert_eq!(L_DATE | L_TIME | L_SHORT_FILE, from_go_flags(3 | 8 | 16));
+        assert_eq!(
+            L_MICROSECONDS | L_UTC | L_MSG_PREFIX,
+            from_go_flags(4 | 32 | 64)
+        );
+        assert_eq!(L_TIME, from_go_flags(2 | 128 | 256));
+    }
+
+    #[test]
+    fn test_apply_env() {
+        // The variables are passed in rather than set in the environment, which is shared with
+        // the tests running in parallel.
+        let vars = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| String::from(*v))
+            }
+        };
+        let builder = Logger::builder()
+            .set_out(io::sink())
+            .set_level(log::LevelFilter::Debug)
+            .set_prefix("code: ")
+            .apply_vars(vars(&[
+                ("LOGOSAURUS_LEVEL", "warn"),
+                ("LOGOSAURUS_PREFIX", "env: "),
+                ("LOGOSAURUS_FLAGS", "L_LEVEL | L_SHORT_FILE"),
+                ("LOGOSAURUS_FORMAT", "json"),
+            ]));
+        assert_eq!(log::LevelFilter::Warn, builder.level);
+        assert_eq!("env: ", builder.prefix);
+        assert_eq!(L_LEVEL | L_SHORT_FILE, builder.flag);
+        assert_eq!(Format::Json, builder.format);
+
+        let builder = Logger::builder()
+            .set_out(io::sink())
+            .set_level(log::LevelFilter::Debug)
+            .set_prefix("code: ")
+            .set_format(Format::Json)
+            .apply_vars(vars(&[
+                ("LOGOSAURUS_LEVEL", "loud"),
+                ("LOGOSAURUS_FORMAT", "yaml"),
+            ]));
+        assert_eq!(log::LevelFilter::Debug, builder.level);
+        assert_eq!(Format::Json, builder.format);
+        assert_eq!("code: ", builder.prefix);
+    }
+
+    #[test]
+    fn test_parse_env() {
+        // The value is passed in rather than set in the environment, which is shared with the
+        // tests running in parallel.
+        let builder = Logger::builder()
+            .set_out(io::sink())
+            .parse_var(Some(String::from("warn,myapp::db=trace")));
+        assert_eq!(log::LevelFilter::Warn, builder.level);
+        assert_eq!(
+            vec![(String::from("myapp::db"), log::LevelFilter::Trace)],
+            builder.target_levels
+        );
+
+        let builder = Logger::builder()
+            .set_out(io::sink())
+            .set_level(log::LevelFilter::Debug)
+            .parse_var(None);
+        assert_eq!(log::LevelFilter::Debug, builder.level);
+    }
+
+    #[test]
+    fn test_format_record() {
+        // A small xorshift generator produces messages mixing ASCII, whitespace, control
+        // characters, quotes and multi-byte characters.
+        let mut state: u32 = 0x9e37_79b9;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        let format_record = |logger: &Logger<io::Sink>, msg: &str| {
+            logger.format_record(
+                &log::Record::builder()
+                    .args(format_args!("{}", msg))
+                    .level(log::Level::Info)
+                    .target("fuzz")
+                    .file(Some("src/fuzz.rs"))
+                    .line(Some(7))
+                    .build(),
+            )
+        };
+        let alphabet = [
+            'a', 'Z', ' ', '\n', '\t', '\0', '"', '=', '\\', 'é', '世', '\r',
+        ];
+
+        let text = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_STD | L_LEVEL | L_SHORT_FILE)
+            .add_field("k", "v")
+            .build();
+        let json = Logger::builder()
+            .set_out(io::sink())
+            .set_format(Format::Json)
+            .set_flags(L_STD | L_LONG_FILE)
+            .build();
+        for _ in 0..500 {
+            let len = next() as usize % 20;
+            let msg: String = (0..len)
+                .map(|_| alphabet[next() as usize % alphabet.len()])
+                .collect();
+
+            let got = format_record(&text, &msg);
+            assert!(got.ends_with('\n'), "{:?}", got);
+            assert!(got.starts_with("INFO  "), "{:?}", got);
+            let date = &got["INFO  ".len().."INFO  2006/01/02".len()];
+            assert!(chrono::NaiveDate::parse_from_str(date, "%Y/%m/%d").is_ok());
+
+            let got = format_record(&json, &msg);
+            assert!(got.ends_with("}\n"), "{:?}", got);
+            assert!(!got[..got.len() - 1].contains(|c: char| c.is_control()));
+        }
+    }
+
+    #[test]
+    fn test_settings() {
+        let first = Buffer::new();
+        let second = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(first.clone())
+            .set_flags(L_LEVEL)
+            .build();
+        logger.write_output(log::Level::Info, "", None, None, "one");
+
+        logger.set_flags(L_LEVEL | L_MSG_PREFIX);
+        logger.set_prefix("app: ");
+        assert_eq!(L_LEVEL | L_MSG_PREFIX, logger.flags());
+        assert_eq!("app: ", logger.prefix());
+        logger.set_output(second.clone());
+        logger.write_output(log::Level::Info, "", None, None, "two");
+
+        let got = first.contents();
+        assert_eq!("INFO  one\n", got);
+        let got = second.contents();
+        assert_eq!("INFO  app: two\n", got);
+    }
+
+    #[test]
+    fn test_panic_free() {
+        // A writer that panics on its first write, poisoning the logger's lock.
+        struct PanicOnce(bool, Arc<Mutex<Vec<u8>>>);
+
+        impl Write for PanicOnce {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if !self.0 {
+                    self.0 = true;
+                    panic!("first write");
+                }
+                self.1.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        struct Failing;
+
+        impl fmt::Display for Failing {
+            fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+
+        let v = Arc::new(Mutex::new(Vec::new()));
+        let logger = Logger::builder()
+            .set_out(PanicOnce(false, Arc::clone(&v)))
+            .set_flags(L_NONE)
+            .build();
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            logger.write_output(log::Level::Info, "", None, None, "lost")
+        }));
+        assert!(panicked.is_err());
+
+        logger.write_output(log::Level::Info, "", None, None, "after panic");
+        log::Log::log(
+            &logger,
+            &log::Record::builder()
+                .args(format_args!("bad {}", Failing))
+                .level(log::Level::Info)
+                .build(),
+        );
+        log::Log::flush(&logger);
+
+        let got = v.lock().unwrap();
+        assert_eq!(
+            "after panic\nbad <formatting error>\n",
+            std::str::from_utf8(&got).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fatal() {
+        // The test runs itself in a child process, which exits in `fatal`.
+        if env::var_os("LOGOSAURUS_TEST_FATAL").is_some() {
+            let logger = Logger::builder()
+                .set_level(log::LevelFilter::Off)
+                .set_flags(L_LEVEL)
+                .set_buffer(4096, FlushPolicy::EveryNRecords(100))
+                .build();
+            logger.fatal(format_args!("fatal error {}", 42));
+        }
+
+        let output = std::process::Command::new(env::current_exe().unwrap())
+            .args(["tests::test_fatal", "--exact", "--nocapture"])
+            .env("LOGOSAURUS_TEST_FATAL", "1")
+            .output()
+            .unwrap();
+        assert_eq!(Some(1), output.status.code());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("ERROR fatal error 42\n"), "{}", stderr);
+    }
+
+    #[test]
+    fn test_log_panic() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_level(log::LevelFilter::Off)
+            .set_flags(L_LEVEL)
+            .set_buffer(4096, FlushPolicy::EveryNRecords(100))
+            .build();
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            logger.log_panic(format_args!("bad config {}", 42))
+        }))
+        .unwrap_err();
+
+        assert_eq!(Some(&String::from("bad config 42")), payload.downcast_ref());
+        let got = v.contents();
+        assert_eq!("ERROR bad config 42\n", got);
+    }
+
+    #[test]
+    fn test_logo_macros() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_level(log::LevelFilter::Info)
+            .set_flags(L_LEVEL | L_SHORT_FILE)
+            .build();
+        let line = line!() + 1;
+        crate::logo_info!(logger, "hello, {}", "world");
+        crate::logo_debug!(&logger, "suppressed");
+        crate::logo_log!(logger, log::Level::Error, "error");
+
+        let expect = format!(
+            "INFO  lib.rs:{}: hello, world\nERROR lib.rs:{}: error\n",
+            line,
+            line + 2
+        );
+        let got = v.contents();
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_logger_write() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .build();
+        let mut w = &logger;
+        w.write_all(b"one\ntw").unwrap();
+        w.write_all(b"o\nthree").unwrap();
+        assert_eq!("INFO  one\nINFO  two\n", v.contents());
+        w.flush().unwrap();
+        assert_eq!("INFO  one\nINFO  two\nINFO  three\n", v.contents());
+    }
+
+    #[test]
+    fn test_with_writer() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_NONE)
+            .set_buffer(4096, FlushPolicy::EveryNRecords(100))
+            .build();
+        logger.write_output(log::Level::Info, "", None, None, "before");
+        logger.with_writer(|w| w.write_all(b"-----\n")).unwrap();
+        logger.write_output(log::Level::Info, "", None, None, "after");
+        log::Log::flush(&logger);
+        assert_eq!("before\n-----\nafter\n", v.contents());
+    }
+
+    #[cfg(feature = "kv")]
+    #[test]
+    fn test_key_values() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_format(Format::Json)
+            .set_flags(L_LEVEL)
+            .build();
+        let kvs = [("user", "alice"), ("level", "3")];
+        log::Log::log(
+            &logger,
+            &log::Record::builder()
+                .args(format_args!("login"))
+                .level(log::Level::Info)
+                .key_values(&kvs)
+                .build(),
+        );
+
+        let got = v.contents();
+        assert_eq!(
+            "{\"level\":\"info\",\"msg\":\"login\",\"user\":\"alice\",\"fields.level\":\"3\"}\n",
+            got
+        );
+    }
+
+    #[test]
+    fn test_mute_site() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_NONE)
+            .build();
+        let log = |line| {
+            logger.write_output(
+                log::Level::Info,
+                "mute",
+                Some("src/noisy.rs"),
+                Some(line),
+                "hi",
+            )
+        };
+        mute_site("noisy.rs:7");
+        log(7);
+        log(8);
+        assert!(unmute_site("noisy.rs:7"));
+        assert!(!unmute_site("noisy.rs:7"));
+        log(7);
+
+        let got = v.contents();
+        assert_eq!("hi\nhi\n", got);
+    }
+
+    #[cfg(all(feature = "kv", debug_assertions))]
+    #[test]
+    fn test_schema() {
+        schema::register("test_schema", &["user"]);
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .build();
+        let kvs = [("event", "test_schema")];
+        log::Log::log(
+            &logger,
+            &log::Record::builder()
+                .args(format_args!("login"))
+                .level(log::Level::Info)
+                .key_values(&kvs)
+                .build(),
+        );
+
+        let got = v.contents();
+        assert_eq!(
+            "INFO  login event=test_schema\n\
+             WARN  event \"test_schema\" is missing required field \"user\"\n",
+            got
+        );
+    }
+
+    #[test]
+    fn test_target_fields() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_NONE)
+            .add_target_field("tenant_a", "tenant", "a")
+            .add_target_field("tenant_b", "tenant", "b")
+            .build();
+        logger.write_output(log::Level::Info, "tenant_a::billing", None, None, "one");
+        logger.write_output(log::Level::Info, "tenant_b", None, None, "two");
+        logger.write_output(log::Level::Info, "tenant_ab", None, None, "three");
+
+        let got = v.contents();
+        assert_eq!("one tenant=a\ntwo tenant=b\nthree\n", got);
+    }
+
+    #[test]
+    fn test_boxed_out() {
+        let v = Buffer::new();
+        let logger: Logger<Box<dyn Write + Send>> = Logger::builder()
+            .set_flags(L_LEVEL)
+            .set_prefix("app: ")
+            .set_boxed_out(v.clone())
+            .build();
+        logger.write_output(log::Level::Info, "", None, None, "hello");
+
+        let got = v.contents();
+        assert_eq!("app: INFO  hello\n", got);
+    }
+
+    #[test]
+    fn test_error_hook() {
+        struct Full;
+
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("no space left on device"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let logger = Logger::builder()
+            .set_out(Full)
+            .set_error_hook({
+                let errors = Arc::clone(&errors);
+                move |e| errors.lock().unwrap().push(e.to_string())
+            })
+            .build();
+        logger.write_output(log::Level::Info, "", None, None, "one");
+        logger.write_output(log::Level::Info, "", None, None, "two");
+        assert_eq!(2, logger.error_count());
+        assert_eq!(vec!["no space left on device"; 2], *errors.lock().unwrap());
+
+        let logger = Logger::builder().set_out(Full).build();
+        logger.write_output(log::Level::Info, "", None, None, "one");
+        assert_eq!(1, logger.error_count());
+
+        let status = logger.status();
+        assert!(!status.is_healthy());
+        assert_eq!(
+            "no space left on device",
+            status.last_error.map(|(_, e)| e).unwrap()
+        );
+        assert_eq!(
+            (None, 0, None),
+            (status.last_write, status.bytes_written, status.queue_len)
+        );
     }
 
     #[test]
-    fn test_header() {
-        let time = FixedOffset::east(3600 * 5 + 1800)
-            .ymd(2020, 10, 3)
-            .and_hms_micro(1, 2, 3, 9876);
+    fn test_status() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_NONE)
+            .build();
+        assert!(logger.status().is_healthy());
+        logger.write_output(log::Level::Info, "", None, None, "hello");
+        let status = logger.child().status();
+        assert!(status.is_healthy());
+        assert!(status.last_write.is_some());
+        assert_eq!(6, status.bytes_written);
+        assert_eq!(0, status.errors);
 
-        let flags = L_STD | L_MICROSECONDS | L_SHORT_FILE;
-        let expect = "TRACE 2020/10/03 01:02:03.009876 file.rs:9: ";
-        let got = header(
-            "foo",
-            "src/dir/file.rs",
-            9,
-            log::Level::Trace,
-            time,
-            flags,
-            "",
+        // Buffered records count as written once the buffer is flushed.
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_NONE)
+            .set_buffer(4096, FlushPolicy::EveryNRecords(100))
+            .build();
+        logger.write_output(log::Level::Info, "", None, None, "hello");
+        let status = logger.status();
+        assert_eq!((None, 0), (status.last_write, status.bytes_written));
+        log::Log::flush(&logger);
+        let status = logger.status();
+        assert!(status.last_write.is_some());
+        assert_eq!(6, status.bytes_written);
+
+        // The status of an `AsyncWriter` reflects the writes of its background thread.
+        struct Full;
+
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("no space left on device"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let w = AsyncWriter::new(Full, 16);
+        let handle = w.handle();
+        let logger = Logger::builder()
+            .set_out(w)
+            .set_flags(L_NONE)
+            .set_async_handle(handle.clone())
+            .build();
+        logger.write_output(log::Level::Info, "", None, None, "hello");
+        while handle.errors() == 0 {
+            std::thread::yield_now();
+        }
+        let status = logger.status();
+        assert!(!status.is_healthy());
+        assert_eq!((None, 0), (status.last_write, status.bytes_written));
+        assert_eq!(
+            "no space left on device",
+            status.last_error.map(|(_, e)| e).unwrap()
         );
-        assert_eq!(expect, got);
+    }
 
-        let flags = L_DATE | L_TIME | L_UTC | L_LONG_FILE;
-        let expect = "2020/10/02 19:32:03 foo src/dir/file.rs:9: ";
-        let got = header(
-            "foo",
-            "src/dir/file.rs",
-            9,
-            log::Level::Info,
-            time,
-            flags,
-            "",
+    #[test]
+    fn test_staging() {
+        let v = Buffer::new();
+        let logger = Arc::new(
+            // Staging is kept when the output is set afterwards.
+            Logger::builder()
+                .set_staging(time::Duration::from_secs(3600))
+                .unwrap()
+                .set_out(v.clone())
+                .set_flags(L_NONE)
+                .set_flush_level(log::Level::Error)
+                .build(),
+        );
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let logger = Arc::clone(&logger);
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        logger.write_output(log::Level::Info, "", None, None, "x");
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert!(v.is_empty());
+
+        // A record at the flush level writes everything staged before it.
+        logger.write_output(log::Level::Error, "", None, None, "last");
+        let got = v.bytes();
+        assert_eq!(401, got.iter().filter(|b| **b == b'\n').count());
+        assert!(got.ends_with(b"x\nlast\n"));
+    }
+
+    #[test]
+    fn test_staging_sequence() {
+        // A short interval and a small flush level mix drains by the background thread and by
+        // the logging threads.
+        let v = Buffer::new();
+        let logger = Arc::new(
+            Logger::builder()
+                .set_out(v.clone())
+                .set_flags(L_SEQUENCE)
+                .set_staging(time::Duration::from_millis(1))
+                .unwrap()
+                .set_flush_level(log::Level::Warn)
+                .build(),
+        );
+        let threads: Vec<_> = (0..8)
+            .map(|t| {
+                let logger = Arc::clone(&logger);
+                std::thread::spawn(move || {
+                    for i in 0..500 {
+                        let level = if (t + i) % 50 == 0 {
+                            log::Level::Warn
+                        } else {
+                            log::Level::Info
+                        };
+                        logger.write_output(level, "", None, None, "x");
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        log::Log::flush(&*logger);
+
+        // The records are written in the order of their printed sequence numbers.
+        let seqs: Vec<u64> = v
+            .lines()
+            .iter()
+            .map(|l| l[1..l.find(' ').unwrap()].parse().unwrap())
+            .collect();
+        assert_eq!((0..4000).collect::<Vec<_>>(), seqs);
+    }
+
+    #[test]
+    fn test_pause() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_NONE)
+            .build();
+        logger.write_output(log::Level::Info, "", None, None, "one");
+        logger.pause(10);
+        assert!(logger.is_paused());
+        logger.write_output(log::Level::Info, "", None, None, "two");
+        logger.write_output(log::Level::Info, "", None, None, "three");
+        logger.write_output(log::Level::Info, "", None, None, "four");
+        assert_eq!(b"one\n", v.bytes().as_slice());
+
+        assert_eq!(1, logger.resume());
+        assert!(!logger.is_paused());
+        logger.write_output(log::Level::Info, "", None, None, "five");
+        assert_eq!(0, logger.resume());
+        assert_eq!("one\ntwo\nthree\nfive\n", v.contents());
+    }
+
+    #[test]
+    fn test_prefix_route() {
+        let v = Buffer::new();
+        let sensitive = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .set_level(log::LevelFilter::Info)
+            .add_prefix_route(
+                "[sensitive]",
+                Logger::builder()
+                    .set_out(sensitive.clone())
+                    .set_flags(L_NONE)
+                    .build(),
+            )
+            .build();
+        logger.write_output(log::Level::Info, "", None, None, "one");
+        logger.write_output(log::Level::Info, "", None, None, "[sensitive] two");
+        logger.write_output(log::Level::Debug, "", None, None, "[sensitive] three");
+        logger.write_output(log::Level::Warn, "", None, None, "four [sensitive]");
+
+        let got = v.contents();
+        assert_eq!("INFO  one\nWARN  four [sensitive]\n", got);
+        let got = sensitive.contents();
+        assert_eq!("[sensitive] two\n", got);
+    }
+
+    #[test]
+    fn test_scoped_prefix() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_MSG_PREFIX)
+            .set_prefix("app: ")
+            .build();
+        let other = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_MSG_PREFIX)
+            .build();
+        let log = |l: &Logger<Buffer>, s| l.write_output(log::Level::Info, "", None, None, s);
+        {
+            let _outer = logger.scoped_prefix("worker-3: ");
+            log(&logger, "one");
+            {
+                let _inner = logger.scoped_prefix("job-7: ");
+                log(&logger, "two");
+                log(&other, "other");
+            }
+            let prefix = std::thread::scope(|s| {
+                s.spawn(|| {
+                    logger
+                        .format_record(&log::Record::builder().args(format_args!("thread")).build())
+                })
+                .join()
+                .unwrap()
+            });
+            assert_eq!("app: thread\n", prefix);
+        }
+        log(&logger, "three");
+
+        let got = v.contents();
+        assert_eq!(
+            "app: worker-3: one\napp: worker-3: job-7: two\nother\napp: three\n",
+            got
+        );
+    }
+
+    #[test]
+    fn test_child() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .set_prefix("app: ")
+            .add_field("k", "v")
+            .build();
+        let child = logger.with_prefix("db: ");
+        child.set_level(log::LevelFilter::Warn);
+        child.set_flags(L_LEVEL | L_MSG_PREFIX);
+
+        logger.write_output(log::Level::Info, "", None, None, "parent");
+        child.write_output(log::Level::Info, "", None, None, "suppressed");
+        child.write_output(log::Level::Warn, "", None, None, "child");
+
+        let got = v.contents();
+        assert_eq!("app: INFO  parent k=v\nWARN  app: db: child k=v\n", got);
+    }
+
+    #[test]
+    fn test_sinks() {
+        let main = Buffer::new();
+        let side = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(main.clone())
+            .set_flags(L_NONE)
+            .build();
+        let sinks = logger.sinks();
+
+        logger.write_output(log::Level::Info, "foo", None, None, "message0");
+        let id = sinks.attach(
+            Logger::builder()
+                .set_out(side.clone())
+                .set_level(log::LevelFilter::Warn)
+                .set_flags(L_LEVEL)
+                .build(),
         );
+        logger.write_output(log::Level::Info, "foo", None, None, "message1");
+        logger.write_output(log::Level::Warn, "foo", None, None, "message2");
+        assert!(sinks.detach(id).is_some());
+        assert!(sinks.detach(id).is_none());
+        logger.write_output(log::Level::Warn, "foo", None, None, "message3");
+
+        let expect = "message0\nmessage1\nmessage2\nmessage3\n";
+        let got = main.contents();
         assert_eq!(expect, got);
 
-        let flags = L_TIME | L_LEVEL;
-        let prefix = "myprog: ";
-        let expect = "myprog: INFO  01:02:03 ";
-        let got = header(
-            "foo",
-            "src/dir/file.rs",
-            9,
-            log::Level::Info,
-            time,
-            flags,
-            prefix,
+        let expect = "WARN  message2\n";
+        let got = side.contents();
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_sink_precision() {
+        let console = Buffer::new();
+        let file = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(console.clone())
+            .set_flags(L_TIME)
+            .build();
+        logger.sinks().attach(
+            Logger::builder()
+                .set_out(file.clone())
+                .set_flags(L_TIME | L_MICROSECONDS)
+                .build(),
         );
+        logger.write_output(log::Level::Info, "", None, None, "hello");
+
+        // "15:04:05 hello" and "15:04:05.000000 hello"
+        let got = console.bytes();
+        assert_eq!("15:04:05 hello\n".len(), got.len());
+        let got = file.bytes();
+        assert_eq!("15:04:05.000000 hello\n".len(), got.len());
+    }
+
+    #[test]
+    fn test_buffer() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_NONE)
+            .set_buffer(1024, FlushPolicy::EveryNRecords(2))
+            .build();
+
+        logger.write_output(log::Level::Info, "foo", None, None, "message0");
+        assert_eq!("", v.contents());
+        logger.write_output(log::Level::Info, "foo", None, None, "message1");
+        assert_eq!("message0\nmessage1\n", v.contents());
+        logger.write_output(log::Level::Info, "foo", None, None, "message2");
+        log::Log::flush(&logger);
+        assert_eq!("message0\nmessage1\nmessage2\n", v.contents());
+    }
+
+    #[test]
+    fn test_flush_level() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_NONE)
+            .set_buffer(1024, FlushPolicy::EveryNRecords(100))
+            .set_flush_level(log::Level::Warn)
+            .build();
+
+        logger.write_output(log::Level::Info, "foo", None, None, "message0");
+        assert_eq!("", v.contents());
+        logger.write_output(log::Level::Warn, "foo", None, None, "message1");
+        assert_eq!("message0\nmessage1\n", v.contents());
+    }
+
+    #[test]
+    fn test_file_level() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_level(log::LevelFilter::Info)
+            .set_flags(L_SHORT_FILE)
+            .set_file_level("src/net/**", log::LevelFilter::Trace)
+            .set_file_level("src/net/noisy.rs", log::LevelFilter::Off)
+            .build();
+        assert_eq!(log::LevelFilter::Trace, logger.max_level());
+
+        let files = ["src/main.rs", "src/net/conn.rs", "src/net/noisy.rs"];
+        for file in files.iter() {
+            logger.write_output(log::Level::Debug, "foo", Some(file), Some(1), "debug");
+            logger.write_output(log::Level::Error, "foo", Some(file), Some(2), "error");
+        }
+
+        let expect = "main.rs:2: error\nconn.rs:1: debug\nconn.rs:2: error\n";
+        let got = v.contents();
         assert_eq!(expect, got);
+    }
 
-        let flags = L_MSG_PREFIX | L_TIME | L_LEVEL;
-        let expect = "INFO  01:02:03 myprog: ";
-        let got = header(
-            "foo",
-            "src/dir/file.rs",
-            9,
-            log::Level::Info,
-            time,
-            flags,
-            prefix,
+    #[test]
+    fn test_fields() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .add_field("pod", "web-1")
+            .add_field("zone", "us east")
+            .build();
+
+        logger.write_output(log::Level::Info, "foo", None, None, "message0");
+        logger.write_output(log::Level::Info, "foo", None, None, "message1\n");
+
+        let expect = r#"INFO  message0 pod=web-1 zone="us east"
+INFO  message1 pod=web-1 zone="us east"
+"#;
+        let got = v.contents();
+        assert_eq!(expect, got);
+    }
+
+    fn meta() -> Meta<'static> {
+        Meta {
+            level: log::Level::Info,
+            target: "foo",
+            file: "src/a.rs",
+            file_static: None,
+            line: 9,
+            fields: Vec::new(),
+            panic: None,
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let time = NaiveDate::from_ymd_opt(2020, 10, 3)
+            .unwrap()
+            .and_hms_micro_opt(1, 2, 3, 9876)
+            .unwrap()
+            .and_local_timezone(FixedOffset::east_opt(3600 * 5 + 1800).unwrap())
+            .unwrap();
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_format(Format::Json)
+            .set_flags(L_DATE | L_TIME | L_UTC | L_LEVEL)
+            .add_field("pod", "web-1")
+            .build();
+        let expect = r#"{"time":"2020-10-02T19:32:03.009Z","level":"info","msg":"say \"hi\"","pod":"web-1"}
+"#;
+        let got = logger.format_line(&meta(), time, "say \"hi\"\n");
+        assert_eq!(expect, got);
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_format(Format::Json)
+            .set_flags(L_TIME | L_MICROSECONDS | L_LONG_FILE)
+            .set_prefix("myprog")
+            .build();
+        let expect = r#"{"time":"2020-10-03T01:02:03.009876+05:30","prefix":"myprog","target":"foo","file":"src/a.rs","line":9,"msg":"hello"}
+"#;
+        let got = logger.format_line(&meta(), time, "hello");
+        assert_eq!(expect, got);
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_format(Format::Json)
+            .set_flags(L_EPOCH | L_MICROSECONDS)
+            .build();
+        let expect = "{\"time\":1601667123.009876,\"msg\":\"hello\"}\n";
+        let got = logger.format_line(&meta(), time, "hello");
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_timezone() {
+        let time = chrono::Utc
+            .with_ymd_and_hms(2020, 10, 2, 19, 32, 3)
+            .unwrap();
+        let tz = FixedOffset::west_opt(4 * 3600).unwrap();
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_DATE | L_TIME)
+            .set_timezone(tz)
+            .build();
+        assert_eq!(
+            "2020/10/02 15:32:03 hi\n",
+            logger.format_line(&meta(), time, "hi")
+        );
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_format(Format::Json)
+            .set_flags(L_TIME)
+            .set_timezone(tz)
+            .build();
+        let got = logger.format_line(&meta(), time, "hi");
+        assert!(got.starts_with("{\"time\":\"2020-10-02T15:32:03.000-04:00\""));
+
+        // L_UTC takes precedence.
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_TIME | L_UTC)
+            .set_timezone(tz)
+            .build();
+        assert_eq!("19:32:03 hi\n", logger.format_line(&meta(), time, "hi"));
+    }
+
+    #[test]
+    fn test_thread_name() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_THREAD_NAME | L_SHORT_FILE)
+            .build();
+        let got = std::thread::Builder::new()
+            .name(String::from("worker-1"))
+            .spawn(move || logger.format_line(&meta(), chrono::Utc::now(), "hi"))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!("[worker-1] a.rs:9: hi\n", got);
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_format(Format::Json)
+            .set_flags(L_THREAD_NAME)
+            .build();
+        let got = std::thread::spawn(move || logger.format_line(&meta(), chrono::Utc::now(), "hi"))
+            .join()
+            .unwrap();
+        assert!(got.starts_with("{\"thread\":\"ThreadId("), "{}", got);
+    }
+
+    #[test]
+    fn test_sequence() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_SEQUENCE)
+            .build();
+        let clone = logger.clone();
+        let now = chrono::Utc::now();
+        assert_eq!("#0 a\n", logger.format_line(&meta(), now, "a"));
+        assert_eq!("#1 b\n", clone.format_line(&meta(), now, "b"));
+
+        logger.sequence.store(u64::MAX, Ordering::Relaxed);
+        let got = logger.format_line(&meta(), now, "c");
+        assert_eq!(format!("#{} c\n", u64::MAX), got);
+        assert_eq!("#0 d\n", logger.format_line(&meta(), now, "d"));
+
+        // Formatting a record without writing it does not advance the sequence.
+        let record = log::Record::builder().args(format_args!("e")).build();
+        assert_eq!("#1 e\n", logger.format_record(&record));
+        assert_eq!("#1 e\n", logger.format_record(&record));
+        assert_eq!("#1 f\n", logger.format_line(&meta(), now, "f"));
+    }
+
+    #[test]
+    fn test_level_labels() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_LEVEL)
+            .set_level_labels(["ERR", "WARNING", "INF", "DBG", "TRC"])
+            .build();
+        let now = chrono::Utc::now();
+        assert_eq!("INF     hi\n", logger.format_line(&meta(), now, "hi"));
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_format(Format::Json)
+            .set_flags(L_LEVEL)
+            .set_level_labels(["ERR", "WRN", "INF", "DBG", "TRC"])
+            .build();
+        let got = logger.format_line(&meta(), now, "hi");
+        assert_eq!("{\"level\":\"info\",\"msg\":\"hi\"}\n", got);
+    }
+
+    #[test]
+    fn test_level_case_width() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_LEVEL)
+            .set_level_case(LevelCase::Lower)
+            .set_level_width(0)
+            .build();
+        let now = chrono::Utc::now();
+        assert_eq!("info hi\n", logger.format_line(&meta(), now, "hi"));
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_LEVEL)
+            .set_level_case(LevelCase::Title)
+            .set_level_width(7)
+            .build();
+        assert_eq!("Info    hi\n", logger.format_line(&meta(), now, "hi"));
+    }
+
+    #[test]
+    fn test_level_prefix() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .set_prefix("app: ")
+            .set_level_prefix(log::Level::Error, "!! ")
+            .build();
+        logger.write_output(log::Level::Error, "foo", None, None, "failed");
+        logger.write_output(log::Level::Info, "foo", None, None, "ok");
+        logger.set_flags(L_LEVEL | L_MSG_PREFIX);
+        logger.write_output(log::Level::Error, "foo", None, None, "failed");
+
+        let expect = "!! app: ERROR failed\napp: INFO  ok\nERROR !! app: failed\n";
+        assert_eq!(expect, v.contents());
+    }
+
+    #[test]
+    fn test_header_order() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_TIME | L_UTC | L_SHORT_FILE | L_LEVEL)
+            .set_header_order(&[HeaderField::Time, HeaderField::Location])
+            .build();
+        let time = chrono::Utc
+            .with_ymd_and_hms(2020, 10, 2, 19, 32, 3)
+            .unwrap();
+        let got = logger.format_line(&meta(), time, "hi");
+        assert_eq!("19:32:03 a.rs:9: INFO  hi\n", got);
+    }
+
+    #[test]
+    fn test_location_width() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_SHORT_FILE)
+            .set_location_width(10)
+            .build();
+        let now = chrono::Utc::now();
+        assert_eq!("a.rs:9:   hi\n", logger.format_line(&meta(), now, "hi"));
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_TARGET)
+            .set_location_width(2)
+            .build();
+        assert_eq!("foo: hi\n", logger.format_line(&meta(), now, "hi"));
+    }
+
+    #[test]
+    fn test_multiline() {
+        let now = chrono::Utc::now();
+        let cases = [
+            (Multiline::Keep, "INFO  a\nb\n"),
+            (Multiline::RepeatHeader, "INFO  a\nINFO  b\n"),
+            (Multiline::Indent, "INFO  a\n\tb\n"),
+            (Multiline::Escape, "INFO  a\\nb\n"),
+        ];
+        for (multiline, expect) in cases.iter() {
+            let logger = Logger::builder()
+                .set_out(io::sink())
+                .set_flags(L_LEVEL)
+                .set_multiline(*multiline)
+                .build();
+            let got = logger.format_line(&meta(), now, "a\nb\n");
+            assert_eq!(*expect, got, "{:?}", multiline);
+        }
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_NONE)
+            .set_multiline(Multiline::Escape)
+            .build();
+        assert_eq!("a\\r\\nb\n", logger.format_line(&meta(), now, "a\r\nb"));
+    }
+
+    #[test]
+    fn test_redaction() {
+        let builder = || {
+            Logger::builder()
+                .set_out(io::sink())
+                .set_flags(L_LEVEL)
+                .add_redaction(redact::Pattern::new(redact::EMAIL).unwrap())
+                .add_redaction(redact::Pattern::new(r"token=\w+").unwrap())
+        };
+        let now = chrono::Utc::now();
+        let mut m = meta();
+        m.fields
+            .push((String::from("user"), String::from("bob@example.com")));
+        let msg = "login token=abc123 by alice@example.com";
+        assert_eq!(
+            "INFO  login [REDACTED] by [REDACTED] user=[REDACTED]\n",
+            builder().build().format_line(&m, now, msg)
+        );
+        assert_eq!(
+            "{\"level\":\"info\",\"msg\":\"login [REDACTED] by [REDACTED]\",\"user\":\"[REDACTED]\"}\n",
+            builder()
+                .set_format(Format::Json)
+                .build()
+                .format_line(&m, now, msg)
+        );
+    }
+
+    #[test]
+    fn test_escape_control() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_LEVEL)
+            .set_escape_control(true)
+            .build();
+        let now = chrono::Utc::now();
+        let got = logger.format_line(&meta(), now, "user\nINFO  admin\u{1b}[2J\tok\n");
+        assert_eq!("INFO  user\\nINFO  admin\\u{1b}[2J\tok\n", got);
+
+        let mut m = meta();
+        m.fields
+            .push((String::from("name"), String::from("a\u{7}")));
+        assert_eq!(
+            "INFO  hi name=\"a\\u{7}\"\n",
+            logger.format_line(&m, now, "hi")
+        );
+    }
+
+    #[test]
+    fn test_max_message_len() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_LEVEL)
+            .set_max_message_len(5)
+            .build();
+        let now = chrono::Utc::now();
+        assert_eq!("INFO  hello\n", logger.format_line(&meta(), now, "hello\n"));
+        let got = logger.format_line(&meta(), now, "hello, world");
+        assert_eq!("INFO  hello …[truncated 7 bytes]\n", got);
+        // The cut is moved back to the start of a character.
+        let got = logger.format_line(&meta(), now, "hhhhé");
+        assert_eq!("INFO  hhhh …[truncated 2 bytes]\n", got);
+        let got = logger.format_line(&meta(), now, "hé…llo");
+        assert_eq!("INFO  hé …[truncated 6 bytes]\n", got);
+    }
+
+    #[test]
+    fn test_crlf() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_LEVEL)
+            .set_crlf(true)
+            .build();
+        let now = chrono::Utc::now();
+        assert_eq!("INFO  hi\r\n", logger.format_line(&meta(), now, "hi"));
+        let got = logger.format_line(&meta(), now, "a\nb\r\nc\n");
+        assert_eq!("INFO  a\r\nb\r\nc\r\n", got);
+    }
+
+    #[test]
+    fn test_repeat_window() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .set_repeat_window(time::Duration::from_secs(3600))
+            .build();
+        let log = |msg: &str| {
+            log::Log::log(
+                &logger,
+                &log::Record::builder()
+                    .args(format_args!("{}", msg))
+                    .level(log::Level::Warn)
+                    .build(),
+            )
+        };
+        for _ in 0..3 {
+            log("retrying");
+        }
+        log("connected");
+        log("connected");
+        log::Log::flush(&logger);
+
+        let expect = "WARN  retrying\nWARN  last message repeated 2 times\nWARN  connected\n\
+                      WARN  last message repeated 1 times\n";
+        assert_eq!(expect, v.contents());
+    }
+
+    #[test]
+    fn test_site_rate_limit() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .set_site_rate_limit(2, time::Duration::from_secs(60))
+            .build();
+        let log = |i: u32, line: u32| {
+            log::Log::log(
+                &logger,
+                &log::Record::builder()
+                    .args(format_args!("record {}", i))
+                    .level(log::Level::Info)
+                    .file(Some("src/a.rs"))
+                    .line(Some(line))
+                    .build(),
+            )
+        };
+        for i in 0..5 {
+            log(i, 1);
+            log(i, 2);
+        }
+        clock::advance(time::Duration::from_secs(60));
+        log(5, 1);
+
+        let got = v.lines();
+        let expect = [
+            "INFO  record 0",
+            "INFO  record 0",
+            "INFO  record 1",
+            "INFO  record 1",
+            "INFO  suppressed 3 records from src/a.rs:1",
+            "INFO  record 5",
+        ];
+        assert_eq!(expect, got.as_slice());
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_NONE)
+            .set_sample_rate(log::Level::Debug, 1000)
+            .build();
+        for _ in 0..100 {
+            log::Log::log(
+                &logger,
+                &log::Record::builder()
+                    .args(format_args!("x"))
+                    .level(log::Level::Info)
+                    .build(),
+            );
+            log::Log::log(
+                &logger,
+                &log::Record::builder()
+                    .args(format_args!("x"))
+                    .level(log::Level::Debug)
+                    .build(),
+            );
+        }
+        let lines = v.bytes().iter().filter(|b| **b == b'\n').count() as u64;
+        assert!(lines >= 100);
+        assert_eq!(200, lines + logger.sampled_out());
+        assert_eq!(logger.sampled_out(), logger.stats().total_dropped());
+    }
+
+    #[test]
+    fn test_filter() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_NONE)
+            .set_level(log::LevelFilter::Info)
+            .set_filter(|record| {
+                assert!(record.level() <= log::Level::Info);
+                record.target() != "noisy"
+            })
+            .build();
+        for target in ["app", "noisy"].iter() {
+            for level in [log::Level::Info, log::Level::Debug].iter() {
+                log::Log::log(
+                    &logger,
+                    &log::Record::builder()
+                        .args(format_args!("{} {}", target, level))
+                        .level(*level)
+                        .target(target)
+                        .build(),
+                );
+            }
+        }
+        assert_eq!(b"app INFO\n", v.bytes().as_slice());
+
+        let stats = logger.child().stats();
+        assert_eq!(1, stats.emitted(log::Level::Info));
+        assert_eq!(1, stats.dropped(log::Level::Info));
+        assert_eq!((1, 1), (stats.total_emitted(), stats.total_dropped()));
+    }
+
+    #[test]
+    fn test_middleware() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .add_middleware(|r| r.add_field("tenant", "acme"))
+            .add_middleware(|r| {
+                let message = r.message().to_lowercase();
+                r.set_message(&message);
+                r.set_level(log::Level::Warn);
+            })
+            .build();
+        log::Log::log(
+            &logger,
+            &log::Record::builder()
+                .args(format_args!("Timed Out"))
+                .level(log::Level::Info)
+                .build(),
         );
+        let got = v.contents();
+        assert_eq!("WARN  timed out tenant=acme\n", got);
+    }
+
+    #[test]
+    fn test_pid() {
+        let pid = std::process::id();
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_LEVEL | L_PID)
+            .build();
+        let got = logger.format_line(&meta(), chrono::Utc::now(), "hi");
+        assert_eq!(format!("INFO  [{}] hi\n", pid), got);
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_format(Format::Json)
+            .set_flags(L_PID)
+            .build();
+        let got = logger.format_line(&meta(), chrono::Utc::now(), "hi");
+        assert_eq!(format!("{{\"pid\":{},\"msg\":\"hi\"}}\n", pid), got);
+    }
+
+    #[test]
+    fn test_write_raw() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .build();
+
+        logger.write_output(log::Level::Info, "foo", None, None, "message0");
+        logger.write_raw(b"-- raw --").unwrap();
+        logger.write_output(log::Level::Info, "foo", None, None, "message1");
+
+        let expect = "INFO  message0\n-- raw --INFO  message1\n";
+        assert_eq!(expect, v.contents());
+        assert_eq!(expect.len() as u64, logger.status().bytes_written);
+
+        // Raw writes follow the flush policy.
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_buffer(4096, FlushPolicy::EveryNRecords(2))
+            .build();
+        logger.write_raw(b"one\n").unwrap();
+        assert!(v.is_empty());
+        logger.write_raw(b"two\n").unwrap();
+        assert_eq!(b"one\ntwo\n", v.bytes().as_slice());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let v = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_LEVEL)
+            .set_rate_limit(log::Level::Info, 2)
+            .build();
+
+        for _ in 0..5 {
+            logger.write_output(log::Level::Info, "foo", None, None, "info");
+            logger.write_output(log::Level::Error, "foo", None, None, "error");
+        }
+
+        let got = v.contents();
+        assert_eq!(2, got.matches("INFO").count());
+        assert_eq!(5, got.matches("ERROR").count());
+    }
+
+    #[test]
+    fn test_id_generator() {
+        let v = Buffer::new();
+        let next = std::sync::atomic::AtomicU64::new(0);
+        let logger = Logger::builder()
+            .set_out(v.clone())
+            .set_flags(L_NONE)
+            .set_overflow_out(8, io::sink())
+            .set_id_generator(move || {
+                let n = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                format!("id{}", n)
+            })
+            .build();
+
+        logger.write_output(log::Level::Info, "foo", None, None, "message0");
+        logger.write_output(log::Level::Info, "foo", None, None, "much too long");
+
+        let expect = "message0 id=id0\n\
+                      [overflow #1] message of 13 bytes written to overflow output id=id1\n";
+        let got = v.contents();
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let main = Buffer::new();
+        let side = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(main.clone())
+            .set_flags(L_LEVEL)
+            .set_overflow_out(8, side.clone())
+            .build();
+
+        logger.write_output(log::Level::Info, "foo", None, None, "short");
+        logger.write_output(log::Level::Info, "foo", None, None, "much too long");
+        logger.write_output(log::Level::Warn, "foo", None, None, "also too long\n");
+
+        let expect = r"INFO  short
+INFO  [overflow #1] message of 13 bytes written to overflow output
+WARN  [overflow #2] message of 14 bytes written to overflow output
+";
+        let got = main.contents();
+        assert_eq!(expect, got);
+
+        let expect = r"INFO  [overflow #1] much too long
+WARN  [overflow #2] also too long
+";
+        let got = side.contents();
         assert_eq!(expect, got);
     }
 }
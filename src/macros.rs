@@ -0,0 +1,128 @@
+/// Logs a message at the `Info` level with the [standard logger], creating it if needed. This is
+/// the counterpart of Go's `log.Printf`, and does not require [`init`] to be called.
+///
+/// ```
+/// logosaurus::print!("listening on {}", 8080);
+/// ```
+///
+/// [standard logger]: fn.std_logger.html
+/// [`init`]: fn.init.html
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)+) => {
+        $crate::__print(
+            ::std::module_path!(),
+            ::std::file!(),
+            ::std::line!(),
+            ::std::format_args!($($arg)+),
+        )
+    };
+}
+
+/// Logs a message at the `Error` level, flushes the logger, and exits the process with status 1.
+/// This is the counterpart of Go's `log.Fatalf`.
+///
+/// The message is logged with the [standard logger], or with the given `Logger` if the first
+/// argument is `logger: <expr>`. It is written regardless of the logger's level. See
+/// `Logger::fatal`.
+///
+/// ```no_run
+/// use logosaurus::Logger;
+///
+/// let path = "/etc/myprogram.conf";
+/// logosaurus::fatal!("failed to read {}", path);
+///
+/// let logger = Logger::default();
+/// logosaurus::fatal!(logger: logger, "failed to read {}", path);
+/// ```
+///
+/// [standard logger]: fn.std_logger.html
+#[macro_export]
+macro_rules! fatal {
+    (logger: $logger:expr, $($arg:tt)+) => {
+        $logger.__fatal(
+            ::std::module_path!(),
+            ::std::option::Option::Some(::std::file!()),
+            ::std::option::Option::Some(::std::line!()),
+            ::std::format_args!($($arg)+),
+        )
+    };
+    ($($arg:tt)+) => {
+        $crate::fatal!(logger: $crate::std_logger(), $($arg)+)
+    };
+}
+
+/// Logs a message at the given level with a specific `Logger`, rather than the global logger,
+/// recording the module, file and line of the call for the `L_SHORT_FILE` and `L_LONG_FILE`
+/// flags.
+///
+/// ```
+/// use logosaurus::{logo_log, Logger, L_SHORT_FILE, L_STD};
+///
+/// let logger = Logger::builder()
+///     .set_flags(L_STD | L_SHORT_FILE)
+///     .build();
+/// logo_log!(logger, log::Level::Info, "hello, {}", "world"); // INFO  ... main.rs:7: hello, world
+/// ```
+#[macro_export]
+macro_rules! logo_log {
+    ($logger:expr, $level:expr, $($arg:tt)+) => {
+        $logger.__log(
+            $level,
+            ::std::module_path!(),
+            ::std::file!(),
+            ::std::line!(),
+            ::std::format_args!($($arg)+),
+        )
+    };
+}
+
+/// Logs a message at the `Error` level with a specific `Logger`. See [`logo_log!`].
+///
+/// [`logo_log!`]: macro.logo_log.html
+#[macro_export]
+macro_rules! logo_error {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::logo_log!($logger, $crate::__private::Level::Error, $($arg)+)
+    };
+}
+
+/// Logs a message at the `Warn` level with a specific `Logger`. See [`logo_log!`].
+///
+/// [`logo_log!`]: macro.logo_log.html
+#[macro_export]
+macro_rules! logo_warn {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::logo_log!($logger, $crate::__private::Level::Warn, $($arg)+)
+    };
+}
+
+/// Logs a message at the `Info` level with a specific `Logger`. See [`logo_log!`].
+///
+/// [`logo_log!`]: macro.logo_log.html
+#[macro_export]
+macro_rules! logo_info {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::logo_log!($logger, $crate::__private::Level::Info, $($arg)+)
+    };
+}
+
+/// Logs a message at the `Debug` level with a specific `Logger`. See [`logo_log!`].
+///
+/// [`logo_log!`]: macro.logo_log.html
+#[macro_export]
+macro_rules! logo_debug {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::logo_log!($logger, $crate::__private::Level::Debug, $($arg)+)
+    };
+}
+
+/// Logs a message at the `Trace` level with a specific `Logger`. See [`logo_log!`].
+///
+/// [`logo_log!`]: macro.logo_log.html
+#[macro_export]
+macro_rules! logo_trace {
+    ($logger:expr, $($arg:tt)+) => {
+        $crate::logo_log!($logger, $crate::__private::Level::Trace, $($arg)+)
+    };
+}
@@ -0,0 +1,98 @@
+use crate::context::Context;
+use std::fmt;
+use std::sync::Arc;
+
+/// A record being rewritten by the functions added with `LoggerBuilder::add_middleware`.
+///
+/// The target, file and line of the record cannot be changed.
+pub struct RecordMut<'a> {
+    record: &'a log::Record<'a>,
+    level: log::Level,
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl<'a> RecordMut<'a> {
+    /// Returns the original record.
+    pub fn record(&self) -> &log::Record<'a> {
+        self.record
+    }
+
+    /// Returns the level of the record.
+    pub fn level(&self) -> log::Level {
+        self.level
+    }
+
+    /// Set the level of the record.
+    pub fn set_level(&mut self, level: log::Level) {
+        self.level = level;
+    }
+
+    /// Returns the target of the record.
+    pub fn target(&self) -> &str {
+        self.record.target()
+    }
+
+    /// Returns the message of the record.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Set the message of the record.
+    pub fn set_message(&mut self, message: &str) {
+        self.message = String::from(message);
+    }
+
+    /// Add the field `key` with `value` to the record. Fields are written like the fields of
+    /// the current context.
+    pub fn add_field(&mut self, key: &str, value: &str) {
+        self.fields.push((String::from(key), String::from(value)));
+    }
+}
+
+pub(crate) type Middleware = Arc<dyn Fn(&mut RecordMut) + Send + Sync>;
+
+// Runs `record` through `chain`, in order, and passes the rewritten record to `emit`, with the
+// added fields in the current context.
+pub(crate) fn apply<F: FnOnce(&log::Record)>(chain: &[Middleware], record: &log::Record, emit: F) {
+    let mut r = RecordMut {
+        record,
+        level: record.level(),
+        message: crate::format_message(record.args()),
+        fields: Vec::new(),
+    };
+    for f in chain {
+        f(&mut r);
+    }
+
+    let ctx = r
+        .fields
+        .iter()
+        .fold(Context::current(), |ctx, (key, value)| {
+            ctx.with_field(key, value)
+        });
+    ctx.scope(|| rebuild(record, r.level, format_args!("{}", r.message), emit));
+}
+
+// Passes a copy of `record` with the given level and message to `f`.
+fn rebuild<'a, F: FnOnce(&log::Record)>(
+    record: &'a log::Record<'a>,
+    level: log::Level,
+    args: fmt::Arguments<'a>,
+    f: F,
+) {
+    let mut builder = log::Record::builder();
+    builder
+        .args(args)
+        .level(level)
+        .target(record.target())
+        .module_path(record.module_path())
+        .file(record.file())
+        .line(record.line());
+    if let Some(file) = record.file_static() {
+        builder.file_static(Some(file));
+    }
+    #[cfg(feature = "kv")]
+    builder.key_values(record.key_values());
+    f(&builder.build());
+}
@@ -0,0 +1,94 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+static SITES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+// Whether any site is muted, so that the common case does not take the lock.
+static ANY: AtomicBool = AtomicBool::new(false);
+
+/// Mute a call site at runtime, across all loggers, for example to silence a noisy line during
+/// an incident. Records from a muted site are discarded.
+///
+/// `site` is either `file:line`, such as `"src/poller.rs:88"`, or a target, such as
+/// `"myapp::poller"`. A file matches the record's file exactly or as a trailing path: `poller.rs`
+/// matches records from `src/poller.rs`. A target matches the record's target exactly.
+///
+/// ```
+/// logosaurus::mute_site("src/poller.rs:88");
+/// // ...
+/// logosaurus::unmute_site("src/poller.rs:88");
+/// ```
+pub fn mute_site(site: &str) {
+    let mut sites = write();
+    if !sites.iter().any(|s| s == site) {
+        sites.push(String::from(site));
+    }
+    ANY.store(true, Ordering::Relaxed);
+}
+
+/// Unmute a call site muted with [`mute_site`]. Returns whether the site was muted.
+///
+/// [`mute_site`]: fn.mute_site.html
+pub fn unmute_site(site: &str) -> bool {
+    let mut sites = write();
+    let len = sites.len();
+    sites.retain(|s| s != site);
+    ANY.store(!sites.is_empty(), Ordering::Relaxed);
+    sites.len() != len
+}
+
+// Reports whether the record comes from a muted call site.
+pub(crate) fn is_muted(record: &log::Record) -> bool {
+    if !ANY.load(Ordering::Relaxed) {
+        return false;
+    }
+    read().iter().any(|site| matches(site, record))
+}
+
+fn matches(site: &str, record: &log::Record) -> bool {
+    if site == record.target() {
+        return true;
+    }
+    let (file, line) = match site.rsplit_once(':') {
+        Some((file, line)) => match line.parse::<u32>() {
+            Ok(line) => (file, line),
+            Err(_) => return false,
+        },
+        None => return false,
+    };
+    match (record.file(), record.line()) {
+        (Some(f), Some(l)) => {
+            l == line
+                && f.ends_with(file)
+                && (f.len() == file.len() || f[..f.len() - file.len()].ends_with('/'))
+        }
+        _ => false,
+    }
+}
+
+fn read() -> RwLockReadGuard<'static, Vec<String>> {
+    SITES.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write() -> RwLockWriteGuard<'static, Vec<String>> {
+    SITES.write().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let record = log::Record::builder()
+            .target("myapp::poller")
+            .file(Some("src/poller.rs"))
+            .line(Some(88))
+            .build();
+        assert!(matches("src/poller.rs:88", &record));
+        assert!(matches("poller.rs:88", &record));
+        assert!(matches("myapp::poller", &record));
+        assert!(!matches("src/poller.rs:89", &record));
+        assert!(!matches("oller.rs:88", &record));
+        assert!(!matches("myapp", &record));
+    }
+}
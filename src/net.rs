@@ -0,0 +1,346 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// A writer that sends records over TCP, reconnecting with exponential backoff when the
+/// connection drops.
+///
+/// While disconnected, records are kept in a bounded in-memory buffer and sent, in order, once
+/// the connection is re-established. When the buffer is full, the oldest records are dropped.
+/// By default writes never fail, so a collector being unavailable does not disrupt the logger.
+/// With `set_fail_after`, writes fail instead while the collector is unreachable, for example
+/// to switch to a [`FallbackWriter`].
+///
+/// A `Logger` hands each formatted record to its writer in a single call, and `TcpWriter`
+/// treats each call as one record. A record that was partially sent when the connection dropped
+/// is sent again in full after reconnecting.
+///
+/// Connection attempts happen on the thread that writes. Wrap the `TcpWriter` in an
+/// [`AsyncWriter`] to keep them off the logging call sites.
+///
+/// # Example
+///
+/// ```no_run
+/// use logosaurus::{AsyncWriter, Logger, TcpWriter};
+///
+/// let w = TcpWriter::new("logs.example.com:5000").unwrap();
+/// let logger = Logger::builder().set_out(AsyncWriter::new(w, 1024)).build();
+/// ```
+///
+/// [`AsyncWriter`]: struct.AsyncWriter.html
+/// [`FallbackWriter`]: struct.FallbackWriter.html
+pub struct TcpWriter {
+    addrs: Vec<SocketAddr>,
+    stream: Option<TcpStream>,
+    connect_timeout: Duration,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    backoff: Duration,
+    next_attempt: Instant,
+    pending: VecDeque<Vec<u8>>,
+    pending_bytes: usize,
+    buffer_limit: usize,
+    dropped: u64,
+    // The number of failed connection attempts in a row, and the number after which writes fail.
+    failures: u32,
+    fail_after: Option<u32>,
+}
+
+impl TcpWriter {
+    /// Returns a `TcpWriter` that sends records to `addr`. The address is resolved once, here;
+    /// the connection itself is established on the first write.
+    pub fn new<A: ToSocketAddrs>(addr: A) -> io::Result<TcpWriter> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "address resolved to no socket addresses",
+            ));
+        }
+        Ok(TcpWriter {
+            addrs,
+            stream: None,
+            connect_timeout: Duration::from_secs(1),
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            backoff: Duration::from_millis(100),
+            next_attempt: Instant::now(),
+            pending: VecDeque::new(),
+            pending_bytes: 0,
+            buffer_limit: 1 << 20,
+            dropped: 0,
+            failures: 0,
+            fail_after: None,
+        })
+    }
+
+    /// Set the minimum and maximum delay between reconnection attempts. The delay starts at
+    /// `min`, doubles after every failed attempt up to `max`, and is reset once connected.
+    /// Defaults to 100ms and 30s.
+    pub fn set_backoff(mut self, min: Duration, max: Duration) -> TcpWriter {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self.backoff = min;
+        self
+    }
+
+    /// Set the timeout for each connection attempt. Defaults to 1s.
+    pub fn set_connect_timeout(mut self, timeout: Duration) -> TcpWriter {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Set the maximum number of bytes buffered while disconnected. Defaults to 1 MiB.
+    pub fn set_buffer_limit(mut self, limit: usize) -> TcpWriter {
+        self.buffer_limit = limit;
+        self
+    }
+
+    /// Return an error from writes once `attempts` connection attempts in a row have failed,
+    /// instead of buffering the record. Records buffered before that are kept, and sent once a
+    /// later write connects. By default writes never fail.
+    pub fn set_fail_after(mut self, attempts: u32) -> TcpWriter {
+        self.fail_after = Some(attempts);
+        self
+    }
+
+    /// Returns the number of records dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn connect(&mut self) -> bool {
+        if self.stream.is_some() {
+            return true;
+        }
+        if Instant::now() < self.next_attempt {
+            return false;
+        }
+        for addr in &self.addrs {
+            if let Ok(stream) = TcpStream::connect_timeout(addr, self.connect_timeout) {
+                self.stream = Some(stream);
+                self.backoff = self.min_backoff;
+                self.failures = 0;
+                return true;
+            }
+        }
+        self.next_attempt = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(self.max_backoff);
+        self.failures = self.failures.saturating_add(1);
+        false
+    }
+
+    // Sends pending records, oldest first. Returns false if the connection failed.
+    fn send_pending(&mut self) -> bool {
+        while let Some(record) = self.pending.front() {
+            let stream = match &mut self.stream {
+                Some(stream) => stream,
+                None => return false,
+            };
+            if stream.write_all(record).is_err() {
+                self.disconnect();
+                return false;
+            }
+            self.pending_bytes -= record.len();
+            self.pending.pop_front();
+        }
+        true
+    }
+
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.next_attempt = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(self.max_backoff);
+    }
+
+    fn buffer(&mut self, buf: &[u8]) {
+        self.pending.push_back(buf.to_vec());
+        self.pending_bytes += buf.len();
+        while self.pending_bytes > self.buffer_limit {
+            match self.pending.pop_front() {
+                Some(record) => {
+                    self.pending_bytes -= record.len();
+                    self.dropped += 1;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl Write for TcpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let connected = self.connect();
+        if !connected && self.fail_after.is_some_and(|n| self.failures >= n) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "failed to connect to the collector",
+            ));
+        }
+        self.buffer(buf);
+        if connected {
+            self.send_pending();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.connect() && self.send_pending() {
+            if let Some(stream) = &mut self.stream {
+                let _ = stream.flush();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A writer that sends one UDP datagram per record, for fire-and-forget shipping to collectors.
+///
+/// A `Logger` hands each formatted record to its writer in a single call, and `UdpWriter`
+/// sends each call as one datagram. Records larger than the maximum size (by default 65507
+/// bytes, the largest UDP payload over IPv4) are rejected with an error, or, if truncation is
+/// enabled, cut down to the maximum size.
+///
+/// # Example
+///
+/// ```no_run
+/// use logosaurus::{Logger, UdpWriter};
+///
+/// let w = UdpWriter::new("127.0.0.1:5140").unwrap().set_max_size(1400).set_truncate(true);
+/// let logger = Logger::builder().set_out(w).build();
+/// ```
+pub struct UdpWriter {
+    socket: UdpSocket,
+    max_size: usize,
+    truncate: bool,
+}
+
+impl UdpWriter {
+    /// Returns a `UdpWriter` that sends datagrams to `addr`.
+    pub fn new<A: ToSocketAddrs>(addr: A) -> io::Result<UdpWriter> {
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "address resolved to no socket addresses",
+            )
+        })?;
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        Ok(UdpWriter {
+            socket,
+            max_size: 65507,
+            truncate: false,
+        })
+    }
+
+    /// Set the maximum size of a datagram, in bytes.
+    pub fn set_max_size(mut self, max_size: usize) -> UdpWriter {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Set whether records larger than the maximum size are truncated rather than rejected.
+    /// Truncation happens at a UTF-8 character boundary where possible.
+    pub fn set_truncate(mut self, truncate: bool) -> UdpWriter {
+        self.truncate = truncate;
+        self
+    }
+}
+
+impl Write for UdpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut datagram = buf;
+        if buf.len() > self.max_size {
+            if !self.truncate {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "record exceeds the maximum datagram size",
+                ));
+            }
+            let mut end = self.max_size;
+            if let Ok(s) = std::str::from_utf8(buf) {
+                while !s.is_char_boundary(end) {
+                    end -= 1;
+                }
+            }
+            datagram = &buf[..end];
+        }
+        self.socket.send(datagram)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_tcp_writer_reconnect() {
+        // Find a free port, and leave it unbound so that the first attempt fails.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut w = TcpWriter::new(addr)
+            .unwrap()
+            .set_backoff(Duration::from_millis(0), Duration::from_millis(0));
+        w.write_all(b"a\n").unwrap();
+        assert!(w.stream.is_none());
+        assert_eq!(1, w.pending.len());
+
+        let listener = TcpListener::bind(addr).unwrap();
+        w.write_all(b"b\n").unwrap();
+        assert!(w.pending.is_empty());
+        drop(w);
+
+        let mut got = String::new();
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.read_to_string(&mut got).unwrap();
+        assert_eq!("a\nb\n", got);
+    }
+
+    #[test]
+    fn test_udp_writer() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut w = UdpWriter::new(server.local_addr().unwrap())
+            .unwrap()
+            .set_max_size(6);
+        let mut buf = [0; 64];
+
+        w.write_all(b"hello\n").unwrap();
+        let n = server.recv(&mut buf).unwrap();
+        assert_eq!(b"hello\n", &buf[..n]);
+
+        assert!(w.write_all("hééé\n".as_bytes()).is_err());
+        let mut w = w.set_truncate(true);
+        w.write_all("hééé\n".as_bytes()).unwrap();
+        let n = server.recv(&mut buf).unwrap();
+        assert_eq!("héé".as_bytes(), &buf[..n]);
+    }
+
+    #[test]
+    fn test_tcp_writer_buffer_limit() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut w = TcpWriter::new(addr).unwrap().set_buffer_limit(4);
+        for _ in 0..3 {
+            w.write_all(b"ab").unwrap();
+        }
+        assert_eq!(1, w.dropped());
+        assert_eq!(4, w.pending_bytes);
+    }
+}
@@ -0,0 +1,94 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::panic;
+use std::thread;
+
+// The location of the panic being logged on this thread, if any.
+#[derive(Clone)]
+pub(crate) struct Location {
+    pub(crate) file: String,
+    pub(crate) line: u32,
+    pub(crate) column: u32,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Location>> = const { RefCell::new(None) };
+}
+
+// Returns the location of the panic being logged on this thread. Set only while the panic hook
+// installed by `log_panics` logs a panic.
+pub(crate) fn current() -> Option<Location> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+/// Install a panic hook that logs panics at the `Error` level with the global logger, then
+/// calls the previously installed hook.
+///
+/// The message has the form `thread 'main' panicked at src/main.rs:10:5: boom`, and the record's
+/// file and line are those of the panic. With `Format::Json`, the record also has a
+/// `"panic":true` field and a `"location"` object with the file, line and column, so that
+/// monitors can alert on panics specifically.
+///
+/// ```
+/// logosaurus::init(logosaurus::Logger::default()).unwrap();
+/// logosaurus::log_panics();
+/// ```
+pub fn log_panics() {
+    let prev = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let location = info.location().map(|l| Location {
+            file: String::from(l.file()),
+            line: l.line(),
+            column: l.column(),
+        });
+        let thread = thread::current();
+        let mut msg = format!("thread '{}' panicked", thread.name().unwrap_or("<unnamed>"));
+        if let Some(l) = &location {
+            msg.push_str(&format!(" at {}:{}:{}", l.file, l.line, l.column));
+        }
+        msg.push_str(": ");
+        msg.push_str(payload_str(info.payload()));
+
+        CURRENT.with(|c| *c.borrow_mut() = location.clone());
+        log::logger().log(
+            &log::Record::builder()
+                .args(format_args!("{}", msg))
+                .level(log::Level::Error)
+                .target("panic")
+                .file(location.as_ref().map(|l| l.file.as_str()))
+                .line(location.as_ref().map(|l| l.line))
+                .build(),
+        );
+        log::logger().flush();
+        CURRENT.with(|c| *c.borrow_mut() = None);
+
+        prev(info);
+    }));
+}
+
+// Returns the message of a panic payload, which is a `&str` or a `String` for panics raised by
+// the `panic!` macro.
+fn payload_str(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_str() {
+        let payload: Box<dyn Any + Send> = Box::new("boom");
+        assert_eq!("boom", payload_str(&*payload));
+        let payload: Box<dyn Any + Send> = Box::new(String::from("boom"));
+        assert_eq!("boom", payload_str(&*payload));
+        let payload: Box<dyn Any + Send> = Box::new(42);
+        assert_eq!("Box<dyn Any>", payload_str(&*payload));
+    }
+}
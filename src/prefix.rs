@@ -0,0 +1,51 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+
+thread_local! {
+    // Prefixes added with `Logger::scoped_prefix` on this thread: the logger, the guard, and the
+    // prefix, in the order they were added.
+    static SCOPED: RefCell<Vec<(usize, u64, String)>> = const { RefCell::new(Vec::new()) };
+    static NEXT_GUARD: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Removes a prefix added with `Logger::scoped_prefix` when dropped.
+#[must_use = "the prefix is removed when the guard is dropped"]
+pub struct PrefixGuard {
+    guard: u64,
+    // The guard must be dropped on the thread that created it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for PrefixGuard {
+    fn drop(&mut self) {
+        SCOPED.with(|s| s.borrow_mut().retain(|(_, guard, _)| *guard != self.guard));
+    }
+}
+
+// Adds `prefix` for the logger identified by `logger` on the current thread.
+pub(crate) fn push(logger: usize, prefix: &str) -> PrefixGuard {
+    let guard = NEXT_GUARD.with(|n| n.replace(n.get() + 1));
+    SCOPED.with(|s| s.borrow_mut().push((logger, guard, String::from(prefix))));
+    PrefixGuard {
+        guard,
+        _not_send: PhantomData,
+    }
+}
+
+// Returns `base` followed by the scoped prefixes of the logger identified by `logger` on the
+// current thread.
+pub(crate) fn scoped(logger: usize, base: &str) -> Cow<'_, str> {
+    SCOPED.with(|s| {
+        let s = s.borrow();
+        let mut prefixes = s.iter().filter(|(l, _, _)| *l == logger).peekable();
+        if prefixes.peek().is_none() {
+            return Cow::Borrowed(base);
+        }
+        let mut prefix = String::from(base);
+        for (_, _, p) in prefixes {
+            prefix.push_str(p);
+        }
+        Cow::Owned(prefix)
+    })
+}
@@ -0,0 +1,123 @@
+use crate::clock;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// A token bucket allowing `rate` events per `interval`, with bursts of up to `rate` events.
+pub(crate) struct Bucket {
+    rate: f64,
+    interval: Duration,
+    state: Mutex<(f64, Instant)>, // available tokens, time of last refill
+}
+
+impl Bucket {
+    pub(crate) fn new(rate: u32, interval: Duration) -> Bucket {
+        Bucket {
+            rate: f64::from(rate),
+            interval,
+            state: Mutex::new((f64::from(rate), clock::instant())),
+        }
+    }
+
+    // Takes a token, returning whether one was available.
+    pub(crate) fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, last) = &mut *state;
+        let now = clock::instant();
+        let elapsed = now.duration_since(*last).as_secs_f64();
+        *tokens = (*tokens + elapsed / self.interval.as_secs_f64() * self.rate).min(self.rate);
+        *last = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Limits the records of each call site to `limit` per `interval`, in fixed windows.
+pub(crate) struct SiteLimits {
+    limit: u32,
+    interval: Duration,
+    sites: Mutex<HashMap<(String, u32), Window>>,
+}
+
+struct Window {
+    start: Instant,
+    count: u32,
+    suppressed: u64,
+}
+
+impl SiteLimits {
+    pub(crate) fn new(limit: u32, interval: Duration) -> SiteLimits {
+        SiteLimits {
+            limit,
+            interval,
+            sites: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Counts a record of the call site at `file` and `line`, returning whether it is allowed,
+    // and the number of records suppressed in the call site's previous window, if any.
+    pub(crate) fn allow(&self, file: &str, line: u32) -> (bool, Option<u64>) {
+        let mut sites = self.sites.lock().unwrap_or_else(|e| e.into_inner());
+        let now = clock::instant();
+        let window = match sites.get_mut(&(String::from(file), line)) {
+            Some(window) => window,
+            None => sites.entry((String::from(file), line)).or_insert(Window {
+                start: now,
+                count: 0,
+                suppressed: 0,
+            }),
+        };
+        let mut suppressed = None;
+        if now.duration_since(window.start) >= self.interval {
+            suppressed = Some(window.suppressed).filter(|n| *n > 0);
+            *window = Window {
+                start: now,
+                count: 0,
+                suppressed: 0,
+            };
+        }
+        if window.count < self.limit {
+            window.count += 1;
+            (true, suppressed)
+        } else {
+            window.suppressed += 1;
+            (false, suppressed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket() {
+        let bucket = Bucket::new(2, Duration::from_secs(3600));
+        assert!(bucket.allow());
+        assert!(bucket.allow());
+        assert!(!bucket.allow());
+        assert!(!bucket.allow());
+
+        let bucket = Bucket::new(1, Duration::from_secs(60));
+        assert!(bucket.allow());
+        assert!(!bucket.allow());
+        clock::advance(Duration::from_secs(60));
+        assert!(bucket.allow());
+    }
+
+    #[test]
+    fn test_site_limits() {
+        let limits = SiteLimits::new(1, Duration::from_secs(60));
+        assert_eq!((true, None), limits.allow("a.rs", 1));
+        assert_eq!((false, None), limits.allow("a.rs", 1));
+        assert_eq!((false, None), limits.allow("a.rs", 1));
+        assert_eq!((true, None), limits.allow("a.rs", 2));
+        clock::advance(Duration::from_secs(60));
+        assert_eq!((true, Some(2)), limits.allow("a.rs", 1));
+        assert_eq!((true, None), limits.allow("a.rs", 2));
+    }
+}
@@ -0,0 +1,662 @@
+// A small regular expression engine for redaction patterns, so that logosaurus does not
+// depend on a regex crate.
+//
+// Supported syntax: literals, `.`, character classes like `[a-z_]` and `[^0-9]`, the escapes
+// `\d`, `\w`, `\s` and their negations, `\b`, `\B`, `\n`, `\r`, `\t` and escaped punctuation,
+// the anchors `^` and `$`, groups `(...)` and `(?:...)`, alternation `|`, the quantifiers `*`,
+// `+`, `?`, `{n}`, `{n,}` and `{n,m}` with lazy variants, and a leading `(?i)` for case
+// insensitive matching.
+//
+// Patterns are compiled to a program for a Pike VM, which runs in time linear in the length of
+// the text, so that a pattern cannot stall logging on a long message. Matches are leftmost
+// first, as in Perl.
+use std::fmt;
+
+// The maximum number of instructions in a compiled pattern. Counted repetitions are expanded,
+// so this limits patterns like `(a{100}){100}`.
+const MAX_PROGRAM: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Error {
+    msg: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+
+fn error<T>(msg: &str) -> Result<T, Error> {
+    Err(Error {
+        msg: String::from(msg),
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool), // negated
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+impl Class {
+    fn matches(&self, c: char, icase: bool) -> bool {
+        let hit = |c: char| {
+            self.items.iter().any(|item| match *item {
+                ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+                ClassItem::Perl(p, negated) => p.matches(c) != negated,
+            })
+        };
+        let found = hit(c) || (icase && (c.to_lowercase().any(&hit) || c.to_uppercase().any(&hit)));
+        found != self.negated
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Look {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Empty,
+    Literal(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn eat(&mut self, c: char) -> bool {
+        if self.chars.peek() == Some(&c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn alt(&mut self) -> Result<Node, Error> {
+        let mut alts = vec![self.concat()?];
+        while self.eat('|') {
+            alts.push(self.concat()?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.pop().unwrap()
+        } else {
+            Node::Alt(alts)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, Error> {
+        let mut nodes = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn atom(&mut self) -> Result<Node, Error> {
+        match self.chars.next() {
+            Some('(') => {
+                if self.eat('?') && !self.eat(':') {
+                    return error("unsupported group flag");
+                }
+                let node = self.alt()?;
+                if !self.eat(')') {
+                    return error("missing )");
+                }
+                Ok(node)
+            }
+            Some('[') => self.class(),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Look(Look::Start)),
+            Some('$') => Ok(Node::Look(Look::End)),
+            Some('\\') => match self.escape()? {
+                Escape::Char(c) => Ok(Node::Literal(c)),
+                Escape::Perl(p, negated) => Ok(Node::Class(Class {
+                    negated: false,
+                    items: vec![ClassItem::Perl(p, negated)],
+                })),
+                Escape::Look(look) => Ok(Node::Look(look)),
+            },
+            Some(c @ ('*' | '+' | '?')) => {
+                error(&format!("quantifier {} without an expression", c))
+            }
+            Some(c) => Ok(Node::Literal(c)),
+            None => error("unexpected end of pattern"),
+        }
+    }
+
+    fn escape(&mut self) -> Result<Escape, Error> {
+        Ok(match self.chars.next() {
+            Some('d') => Escape::Perl(Perl::Digit, false),
+            Some('D') => Escape::Perl(Perl::Digit, true),
+            Some('w') => Escape::Perl(Perl::Word, false),
+            Some('W') => Escape::Perl(Perl::Word, true),
+            Some('s') => Escape::Perl(Perl::Space, false),
+            Some('S') => Escape::Perl(Perl::Space, true),
+            Some('b') => Escape::Look(Look::WordBoundary),
+            Some('B') => Escape::Look(Look::NotWordBoundary),
+            Some('n') => Escape::Char('\n'),
+            Some('r') => Escape::Char('\r'),
+            Some('t') => Escape::Char('\t'),
+            Some(c) if !c.is_alphanumeric() => Escape::Char(c),
+            Some(c) => return error(&format!("unsupported escape \\{}", c)),
+            None => return error("unexpected end of pattern"),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, Error> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = match self.chars.next() {
+                Some(']') if !first => break,
+                Some(c) => c,
+                None => return error("missing ]"),
+            };
+            first = false;
+            let lo = if c == '\\' {
+                match self.escape()? {
+                    Escape::Char(c) => c,
+                    Escape::Perl(p, negated) => {
+                        items.push(ClassItem::Perl(p, negated));
+                        continue;
+                    }
+                    Escape::Look(_) => return error("assertion in character class"),
+                }
+            } else {
+                c
+            };
+            let mut ahead = self.chars.clone();
+            if ahead.next() == Some('-') && ahead.peek().is_some_and(|c| *c != ']') {
+                self.chars.next();
+                let hi = match self.chars.next() {
+                    Some('\\') => match self.escape()? {
+                        Escape::Char(c) => c,
+                        _ => return error("invalid range in character class"),
+                    },
+                    Some(c) => c,
+                    None => return error("missing ]"),
+                };
+                if hi < lo {
+                    return error("invalid range in character class");
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+        Ok(Node::Class(Class { negated, items }))
+    }
+
+    fn quantifier(&mut self, node: Node) -> Result<Node, Error> {
+        let (min, max) = match self.chars.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                // A `{` that does not start a valid count is a literal, as in most engines.
+                let mut ahead = self.chars.clone();
+                ahead.next();
+                match counts(&mut ahead) {
+                    Some((min, max)) => {
+                        self.chars = ahead;
+                        return self.repeat(node, min, max);
+                    }
+                    None => return Ok(node),
+                }
+            }
+            _ => return Ok(node),
+        };
+        self.chars.next();
+        self.repeat(node, min, max)
+    }
+
+    fn repeat(&mut self, node: Node, min: u32, max: Option<u32>) -> Result<Node, Error> {
+        if let Node::Look(_) | Node::Empty = node {
+            return error("quantifier without an expression");
+        }
+        if max.is_some_and(|max| max < min) {
+            return error("invalid repetition count");
+        }
+        let greedy = !self.eat('?');
+        Ok(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        })
+    }
+}
+
+enum Escape {
+    Char(char),
+    Perl(Perl, bool),
+    Look(Look),
+}
+
+// Parses the `n}`, `n,}` or `n,m}` following a `{`.
+fn counts(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<(u32, Option<u32>)> {
+    let number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        let mut n: Option<u32> = None;
+        while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+            chars.next();
+            n = Some(n.unwrap_or(0).checked_mul(10)?.checked_add(d)?);
+        }
+        n
+    };
+    let min = number(chars)?;
+    let max = if chars.peek() == Some(&',') {
+        chars.next();
+        number(chars)
+    } else {
+        Some(min)
+    };
+    if chars.next() == Some('}') {
+        Some((min, max))
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Split(usize, usize), // the first branch has priority
+    Jmp(usize),
+    Match,
+}
+
+struct Compiler {
+    prog: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, Error> {
+        if self.prog.len() >= MAX_PROGRAM {
+            return error("pattern too large");
+        }
+        self.prog.push(inst);
+        Ok(self.prog.len() - 1)
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), Error> {
+        match node {
+            Node::Empty => {}
+            Node::Literal(c) => {
+                self.push(Inst::Char(*c))?;
+            }
+            Node::Any => {
+                self.push(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()))?;
+            }
+            Node::Look(look) => {
+                self.push(Inst::Look(*look))?;
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alt(nodes) => {
+                let mut jumps = Vec::new();
+                for (i, node) in nodes.iter().enumerate() {
+                    if i + 1 == nodes.len() {
+                        self.compile(node)?;
+                    } else {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.compile(node)?;
+                        jumps.push(self.push(Inst::Jmp(0))?);
+                        self.prog[split] = Inst::Split(split + 1, self.prog.len());
+                    }
+                }
+                let end = self.prog.len();
+                for jump in jumps {
+                    self.prog[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.compile(node)?;
+                        self.push(Inst::Jmp(split))?;
+                        self.prog[split] = self.split(split + 1, self.prog.len(), *greedy);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.compile(node)?;
+                        }
+                        let end = self.prog.len();
+                        for split in splits {
+                            self.prog[split] = self.split(split + 1, end, *greedy);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn split(&self, body: usize, skip: usize, greedy: bool) -> Inst {
+        if greedy {
+            Inst::Split(body, skip)
+        } else {
+            Inst::Split(skip, body)
+        }
+    }
+}
+
+// A compiled regular expression.
+#[derive(Clone, Debug)]
+pub(crate) struct Regex {
+    prog: Vec<Inst>,
+    icase: bool,
+}
+
+impl Regex {
+    pub(crate) fn new(pattern: &str) -> Result<Regex, Error> {
+        let (icase, pattern) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut parser = Parser {
+            chars: pattern.chars().peekable(),
+        };
+        let node = parser.alt()?;
+        if parser.chars.next().is_some() {
+            return error("unmatched )");
+        }
+        let mut compiler = Compiler { prog: Vec::new() };
+        compiler.compile(&node)?;
+        compiler.push(Inst::Match)?;
+        Ok(Regex {
+            prog: compiler.prog,
+            icase,
+        })
+    }
+
+    // Returns the byte range of the leftmost match in `text` starting at or after `start`.
+    pub(crate) fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let mut clist = Threads::new(self.prog.len());
+        let mut nlist = Threads::new(self.prog.len());
+        let mut matched = None;
+        let mut pos = start;
+        loop {
+            let prev = text[..pos].chars().next_back();
+            let next = text[pos..].chars().next();
+            if matched.is_none() {
+                self.add_at(&mut clist, 0, pos, prev, next);
+            }
+            if clist.is_empty() {
+                break;
+            }
+            for i in 0..clist.len() {
+                let (pc, thread_start) = clist.get(i);
+                let step = match &self.prog[pc] {
+                    Inst::Char(c) => next.filter(|n| self.eq(*c, *n)),
+                    Inst::Any => next.filter(|n| *n != '\n'),
+                    Inst::Class(class) => next.filter(|n| class.matches(*n, self.icase)),
+                    Inst::Match => {
+                        matched = Some((thread_start, pos));
+                        // Threads after this one have lower priority.
+                        break;
+                    }
+                    _ => None,
+                };
+                if let Some(c) = step {
+                    let after = pos + c.len_utf8();
+                    let next_next = text[after..].chars().next();
+                    self.add_at(&mut nlist, pc + 1, thread_start, Some(c), next_next);
+                }
+            }
+            std::mem::swap(&mut clist, &mut nlist);
+            nlist.clear();
+            match next {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+        }
+        matched
+    }
+
+    // Adds the thread at `pc`, following jumps, splits and assertions, which are evaluated
+    // between the characters `prev` and `next`.
+    fn add_at(
+        &self,
+        list: &mut Threads,
+        pc: usize,
+        start: usize,
+        prev: Option<char>,
+        next: Option<char>,
+    ) {
+        if !list.insert(pc, start) {
+            return;
+        }
+        match &self.prog[pc] {
+            Inst::Jmp(to) => self.add_at(list, *to, start, prev, next),
+            Inst::Split(a, b) => {
+                self.add_at(list, *a, start, prev, next);
+                self.add_at(list, *b, start, prev, next);
+            }
+            Inst::Look(look) => {
+                let ok = match look {
+                    Look::Start => prev.is_none(),
+                    Look::End => next.is_none(),
+                    Look::WordBoundary => prev.is_some_and(is_word) != next.is_some_and(is_word),
+                    Look::NotWordBoundary => prev.is_some_and(is_word) == next.is_some_and(is_word),
+                };
+                if ok {
+                    self.add_at(list, pc + 1, start, prev, next);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn eq(&self, a: char, b: char) -> bool {
+        a == b || (self.icase && a.to_lowercase().eq(b.to_lowercase()))
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// An ordered set of threads, each a program counter and the start of its match. Jumps, splits
+// and assertions are kept in the set to stop loops, but are skipped when stepping.
+struct Threads {
+    dense: Vec<(usize, usize)>,
+    sparse: Vec<usize>,
+}
+
+impl Threads {
+    fn new(n: usize) -> Threads {
+        Threads {
+            dense: Vec::with_capacity(n),
+            sparse: vec![0; n],
+        }
+    }
+
+    fn insert(&mut self, pc: usize, start: usize) -> bool {
+        let i = self.sparse[pc];
+        if i < self.dense.len() && self.dense[i].0 == pc {
+            return false;
+        }
+        self.sparse[pc] = self.dense.len();
+        self.dense.push((pc, start));
+        true
+    }
+
+    fn get(&self, i: usize) -> (usize, usize) {
+        self.dense[i]
+    }
+
+    fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<String> {
+        let re = Regex::new(pattern).unwrap();
+        re.find_at(text, 0).map(|(s, e)| String::from(&text[s..e]))
+    }
+
+    #[test]
+    fn test_find() {
+        let cases: &[(&str, &str, Option<&str>)] = &[
+            ("abc", "xxabcxx", Some("abc")),
+            ("a.c", "abc", Some("abc")),
+            ("a.c", "a\nc", None),
+            ("a+", "baaa", Some("aaa")),
+            ("a+?", "baaa", Some("a")),
+            ("ab*", "abbbc", Some("abbb")),
+            ("colou?r", "color", Some("color")),
+            ("[a-c]+", "xxcabz", Some("cab")),
+            ("[^a-c ]+", "abc xyz", Some("xyz")),
+            ("[-a]+", "b-a-", Some("-a-")),
+            (r"\d{3}-\d{4}", "call 555-1234 now", Some("555-1234")),
+            (r"\d{2,3}", "1 12345", Some("123")),
+            (r"\d{2,}", "1 12345", Some("12345")),
+            ("a{,2}", "a{,2}", Some("a{,2}")),
+            ("cat|dog", "hotdog", Some("dog")),
+            ("(?:ab)+", "xababx", Some("abab")),
+            ("(a|ab)c", "abc", Some("abc")),
+            ("^abc", "xabc", None),
+            ("abc$", "abcx", None),
+            ("^$", "", Some("")),
+            (r"\bcat\b", "concat cat", Some("cat")),
+            (r"\Bcat", "concat", Some("cat")),
+            (
+                r"\w+@\w+\.com",
+                "mail bob@example.com!",
+                Some("bob@example.com"),
+            ),
+            (r"\s+", "a \t b", Some(" \t ")),
+            (r"\.", "a.b", Some(".")),
+            ("(?i)bearer", "BeArEr x", Some("BeArEr")),
+            ("(?i)[a-c]+", "xABCx", Some("ABC")),
+            ("é+", "caféé", Some("éé")),
+            ("x*", "abc", Some("")),
+        ];
+        for (pattern, text, expect) in cases {
+            assert_eq!(
+                expect.map(String::from),
+                find(pattern, text),
+                "{:?} in {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_at() {
+        let re = Regex::new(r"\d+").unwrap();
+        assert_eq!(Some((0, 2)), re.find_at("12 345", 0));
+        assert_eq!(Some((3, 6)), re.find_at("12 345", 2));
+        assert_eq!(None, re.find_at("12 345", 6));
+    }
+
+    #[test]
+    fn test_errors() {
+        for pattern in [
+            "(ab",
+            "ab)",
+            "[ab",
+            "*a",
+            "a{3,2}",
+            r"\q",
+            "(?x)",
+            "(a{100}){200}",
+        ]
+        .iter()
+        {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_linear() {
+        // Catastrophic for backtracking engines.
+        let re = Regex::new("(a*)*b").unwrap();
+        let text = "a".repeat(10_000);
+        assert_eq!(None, re.find_at(&text, 0));
+    }
+}
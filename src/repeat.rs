@@ -0,0 +1,106 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// A record that was suppressed as a repetition: its level, target and the number of times it
+// was repeated.
+pub(crate) type Summary = (log::Level, String, u64);
+
+// Collapses consecutive identical records, like the "last message repeated N times" lines of
+// syslogd.
+pub(crate) struct Repeats {
+    window: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    last: Option<(log::Level, String, String)>, // level, target, message
+    count: u64,                                 // repetitions not yet summarized
+    since: Instant,                             // when the repetitions started to be counted
+}
+
+impl Repeats {
+    pub(crate) fn new(window: Duration) -> Repeats {
+        Repeats {
+            window,
+            state: Mutex::new(State {
+                last: None,
+                count: 0,
+                since: Instant::now(),
+            }),
+        }
+    }
+
+    // Records a record, returning whether it is a repetition that should be suppressed, and
+    // the summary of earlier repetitions that should be written first, if any.
+    //
+    // Repetitions are summarized when a different record arrives, and when a repetition
+    // arrives after the window has passed.
+    pub(crate) fn check(
+        &self,
+        level: log::Level,
+        target: &str,
+        msg: &str,
+    ) -> (bool, Option<Summary>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if let Some((l, t, m)) = &state.last {
+            if *l == level && t == target && m == msg {
+                if state.count == 0 {
+                    state.since = now;
+                }
+                state.count += 1;
+                if now.duration_since(state.since) < self.window {
+                    return (true, None);
+                }
+                let count = std::mem::take(&mut state.count);
+                return (true, Some((level, String::from(target), count)));
+            }
+        }
+        let summary = state.take();
+        state.last = Some((level, String::from(target), String::from(msg)));
+        (false, summary)
+    }
+
+    // Returns the summary of the repetitions that have not been summarized yet, if any.
+    pub(crate) fn take(&self) -> Option<Summary> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+impl State {
+    fn take(&mut self) -> Option<Summary> {
+        let count = std::mem::take(&mut self.count);
+        match &self.last {
+            Some((level, target, _)) if count > 0 => Some((*level, target.clone(), count)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_repeats() {
+        let repeats = Repeats::new(Duration::from_secs(3600));
+        assert_eq!((false, None), repeats.check(Level::Warn, "a", "retrying"));
+        assert_eq!((true, None), repeats.check(Level::Warn, "a", "retrying"));
+        assert_eq!((true, None), repeats.check(Level::Warn, "a", "retrying"));
+        assert_eq!(
+            (false, Some((Level::Warn, String::from("a"), 2))),
+            repeats.check(Level::Warn, "b", "retrying")
+        );
+        assert_eq!((true, None), repeats.check(Level::Warn, "b", "retrying"));
+        assert_eq!(Some((Level::Warn, String::from("b"), 1)), repeats.take());
+        assert_eq!(None, repeats.take());
+
+        let repeats = Repeats::new(Duration::ZERO);
+        assert_eq!((false, None), repeats.check(Level::Info, "a", "x"));
+        assert_eq!(
+            (true, Some((Level::Info, String::from("a"), 1))),
+            repeats.check(Level::Info, "a", "x")
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Keeps a random 1 in N records of each level, for `LoggerBuilder::set_sample_rate`.
+pub(crate) struct Sampler {
+    rates: [u32; 5], // indexed by level, Error first
+    state: RandomState,
+    counter: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Sampler {
+    pub(crate) fn new(rates: [u32; 5]) -> Sampler {
+        Sampler {
+            rates,
+            state: RandomState::new(),
+            counter: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    // Reports whether a record of `level` is kept, counting it if it is not.
+    pub(crate) fn keep(&self, level: log::Level) -> bool {
+        let rate = u64::from(self.rates[level as usize - 1]);
+        if rate <= 1 {
+            return true;
+        }
+        // The standard library's randomly seeded hasher is a good enough source of randomness
+        // for sampling.
+        let mut h = self.state.build_hasher();
+        h.write_u64(self.counter.fetch_add(1, Ordering::Relaxed));
+        if h.finish().is_multiple_of(rate) {
+            true
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    // Returns the number of records that were not kept.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_sampler() {
+        let sampler = Sampler::new([1, 1, 1, 10, 0]);
+        let kept = (0..10_000).filter(|_| sampler.keep(Level::Debug)).count();
+        assert!((500..1500).contains(&kept), "kept {}", kept);
+        assert_eq!(10_000 - kept as u64, sampler.dropped());
+
+        assert!((0..100).all(|_| sampler.keep(Level::Info)));
+        assert!((0..100).all(|_| sampler.keep(Level::Trace)));
+    }
+}
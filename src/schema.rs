@@ -0,0 +1,98 @@
+//! A registry of structured event types and the fields they require.
+//!
+//! A record is an event of a registered type if it has an `event` key-value pair naming the
+//! type. In debug builds, the logger checks such records against the registered schema and, for
+//! every required field that is missing, logs a warning with the target `logosaurus::schema`
+//! after the record. This keeps structured logs consistent across a code base without costing
+//! anything in release builds.
+//!
+//! ```
+//! use logosaurus::schema;
+//!
+//! schema::register("login", &["user", "method"]);
+//!
+//! // Logs a schema violation in debug builds, because `method` is missing:
+//! log::info!(event = "login", user = "alice"; "user logged in");
+//! ```
+//!
+//! This module requires the `kv` feature.
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+/// The target of the warnings logged for schema violations.
+pub const TARGET: &str = "logosaurus::schema";
+
+fn registry() -> &'static RwLock<HashMap<String, Vec<String>>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, Vec<String>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Register the event type `event` with its required fields, replacing any previous schema for
+/// the type.
+pub fn register(event: &str, required: &[&str]) {
+    let required = required.iter().map(|f| String::from(*f)).collect();
+    registry()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(String::from(event), required);
+}
+
+/// Remove the schema of the event type `event`. Returns whether it was registered.
+pub fn unregister(event: &str) -> bool {
+    registry()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(event)
+        .is_some()
+}
+
+// Returns a description of each violation of the record's schema, given its fields. Records
+// without an `event` field, or of an unregistered type, have no violations.
+pub(crate) fn violations(fields: &[(String, String)]) -> Vec<String> {
+    let event = match fields.iter().find(|(k, _)| k == "event") {
+        Some((_, event)) => event,
+        None => return Vec::new(),
+    };
+    let registry = registry().read().unwrap_or_else(|e| e.into_inner());
+    let required = match registry.get(event) {
+        Some(required) => required,
+        None => return Vec::new(),
+    };
+    required
+        .iter()
+        .filter(|r| !fields.iter().any(|(k, _)| k == *r))
+        .map(|r| format!("event {:?} is missing required field {:?}", event, r))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (String::from(*k), String::from(*v)))
+            .collect()
+    }
+
+    #[test]
+    fn test_violations() {
+        register("test_violations", &["user", "method"]);
+        assert_eq!(
+            vec![String::from(
+                "event \"test_violations\" is missing required field \"method\""
+            )],
+            violations(&fields(&[("event", "test_violations"), ("user", "alice")]))
+        );
+        assert!(violations(&fields(&[
+            ("event", "test_violations"),
+            ("user", "alice"),
+            ("method", "password"),
+        ]))
+        .is_empty());
+        assert!(violations(&fields(&[("event", "unknown")])).is_empty());
+        assert!(violations(&fields(&[("user", "alice")])).is_empty());
+        assert!(unregister("test_violations"));
+    }
+}
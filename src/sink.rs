@@ -0,0 +1,119 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Identifies a sink attached through [`Sinks::attach`].
+///
+/// [`Sinks::attach`]: struct.Sinks.html#method.attach
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SinkId(u64);
+
+#[derive(Default)]
+struct Registry {
+    next_id: AtomicU64,
+    sinks: RwLock<Vec<(SinkId, Box<dyn log::Log>)>>,
+}
+
+/// A handle for attaching and detaching additional sinks on a [`Logger`], including after the
+/// logger has been installed with [`init`].
+///
+/// A sink is any [`log::Log`] implementation, for example another `Logger` with different flags
+/// and output. Every record that passes the `Logger`'s level filter is written to the
+/// `Logger`'s own output and then passed to each attached sink whose `enabled` method accepts
+/// it.
+///
+/// Each sink formats records independently. For example, a `Logger` writing to the console can
+/// use second precision timestamps for readability, while a sink writing to a file sets
+/// `L_MICROSECONDS` for correlating records across machines:
+///
+/// ```
+/// use logosaurus::{Logger, L_MICROSECONDS, L_STD};
+/// use std::fs::File;
+/// use std::io;
+///
+/// # fn main() -> io::Result<()> {
+/// # let dir = std::env::temp_dir();
+/// let logger = Logger::builder().set_flags(L_STD).build();
+/// let file = File::create(dir.join("app.log"))?;
+/// logger
+///     .sinks()
+///     .attach(Logger::builder().set_out(file).set_flags(L_STD | L_MICROSECONDS).build());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Attaching and detaching is synchronized with in-flight records: once `detach` returns, the
+/// detached sink does not receive any further records.
+///
+/// Obtain a `Sinks` handle using `Logger::sinks()`.
+///
+/// # Example
+///
+/// ```
+/// use logosaurus::{Logger, L_NONE};
+/// use std::io;
+///
+/// let logger = Logger::default();
+/// let sinks = logger.sinks();
+/// logosaurus::init(logger).unwrap();
+///
+/// // While investigating an incident:
+/// let id = sinks.attach(Logger::builder().set_out(io::stdout()).set_flags(L_NONE).build());
+/// // ...
+/// sinks.detach(id);
+/// ```
+///
+/// [`Logger`]: struct.Logger.html
+/// [`init`]: fn.init.html
+/// [`log::Log`]: https://docs.rs/log/0.4/log/trait.Log.html
+#[derive(Clone, Default)]
+pub struct Sinks {
+    registry: Arc<Registry>,
+}
+
+impl Sinks {
+    /// Attach a sink. Returns an id that can be used to detach it.
+    pub fn attach<L: log::Log + 'static>(&self, sink: L) -> SinkId {
+        let id = SinkId(self.registry.next_id.fetch_add(1, Ordering::Relaxed));
+        self.write().push((id, Box::new(sink)));
+        id
+    }
+
+    /// Detach the sink with the given id, returning it. Returns `None` if no such sink is
+    /// attached.
+    pub fn detach(&self, id: SinkId) -> Option<Box<dyn log::Log>> {
+        let mut sinks = self.write();
+        let i = sinks.iter().position(|(sink_id, _)| *sink_id == id)?;
+        Some(sinks.remove(i).1)
+    }
+
+    pub(crate) fn log(&self, record: &log::Record) {
+        let sinks = self
+            .registry
+            .sinks
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        for (_, sink) in sinks.iter() {
+            if sink.enabled(record.metadata()) {
+                sink.log(record);
+            }
+        }
+    }
+
+    pub(crate) fn flush(&self) {
+        let sinks = self
+            .registry
+            .sinks
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        for (_, sink) in sinks.iter() {
+            sink.flush();
+        }
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Vec<(SinkId, Box<dyn log::Log>)>> {
+        self.registry
+            .sinks
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
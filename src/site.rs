@@ -0,0 +1,75 @@
+use crate::{format_location, Flag, L_LONG_FILE, L_SHORT_FILE, L_TARGET};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// The number of call sites cached per thread. The cache is cleared when it is full, which only
+// happens in programs that log from an unusually large number of places.
+const MAX_SITES: usize = 1024;
+
+// A call site: the address and length of its static file name, its line, and the location
+// flags it was formatted with.
+type Key = (usize, usize, u32, Flag);
+
+thread_local! {
+    // The formatted location of each call site, along with the target it was formatted for.
+    static CACHE: RefCell<HashMap<Key, (String, String)>> = RefCell::new(HashMap::new());
+}
+
+// Writes the location part of the header, like `format_location`, reusing the formatted
+// location from an earlier record of the same call site.
+//
+// A static file name has a fixed address, so the address identifies the call site together
+// with the line. The target is not static, so it is compared on every use.
+pub(crate) fn push_location(
+    buf: &mut String,
+    target: &str,
+    file: &'static str,
+    line: u32,
+    flag: Flag,
+) {
+    let flag = flag & (L_LONG_FILE | L_SHORT_FILE | L_TARGET);
+    let key = (file.as_ptr() as usize, file.len(), line, flag);
+    let cached = CACHE.try_with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if let Some((t, location)) = cache.get(&key) {
+            if t == target {
+                buf.push_str(location);
+                return;
+            }
+        }
+        if cache.len() >= MAX_SITES {
+            cache.clear();
+        }
+        let mut location = String::new();
+        format_location(&mut location, target, file, line, flag);
+        buf.push_str(&location);
+        cache.insert(key, (String::from(target), location));
+    });
+    // The cache is gone while the thread is exiting.
+    if cached.is_err() {
+        format_location(buf, target, file, line, flag);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_location() {
+        let mut buf = String::new();
+        push_location(&mut buf, "foo", "src/dir/file.rs", 9, L_SHORT_FILE);
+        push_location(&mut buf, "foo", "src/dir/file.rs", 9, L_SHORT_FILE);
+        assert_eq!("file.rs:9: file.rs:9: ", buf);
+
+        // The same site with different flags or another target is formatted again.
+        let mut buf = String::new();
+        push_location(&mut buf, "foo", "src/dir/file.rs", 9, L_LONG_FILE);
+        push_location(&mut buf, "bar", "src/dir/file.rs", 9, L_LONG_FILE);
+        push_location(&mut buf, "bar", "src/dir/file.rs", 10, L_LONG_FILE);
+        assert_eq!(
+            "foo src/dir/file.rs:9: bar src/dir/file.rs:9: bar src/dir/file.rs:10: ",
+            buf
+        );
+    }
+}
@@ -0,0 +1,478 @@
+use std::cell::RefCell;
+use std::io;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::Duration;
+
+// A formatted record: its sequence number, its bytes, and whether the output should be flushed
+// after writing it.
+pub(crate) type Staged = (u64, Vec<u8>, bool);
+
+type Write = Box<dyn Fn(&[Staged]) -> io::Result<()> + Send + Sync>;
+type Report = Box<dyn Fn(io::Error) + Send + Sync>;
+
+type StageRef = Arc<Mutex<Stage>>;
+
+// The records staged by one thread for one `Stager`.
+#[derive(Default)]
+struct Stage {
+    records: Vec<Staged>,
+    bytes: usize,
+}
+
+struct Shared {
+    // The logger's sequence, which numbers the staged records.
+    next_seq: Arc<AtomicU64>,
+    stages: Mutex<Vec<StageRef>>,
+    // Writes a batch of records to the logger's output. Called with `draining` held, so that
+    // batches are written in order, and with the fork gate held.
+    write: Write,
+    // Reports a failed write, after `draining` has been released.
+    report: Report,
+    draining: Mutex<Order>,
+    state: Mutex<State>,
+    wake: Condvar,
+    idle: Condvar,
+    thread: Mutex<Option<thread::JoinHandle<()>>>,
+    // Set when the background thread could not be restarted after a fork. Records are then
+    // written by the threads that log them.
+    synchronous: AtomicBool,
+    interval: Duration,
+    // The staged bytes above which a thread drains its stage itself.
+    limit: usize,
+}
+
+// The records collected from the stages but not written yet, because a record numbered before
+// them is still being formatted, and the sequence number of the next record to write.
+struct Order {
+    held: Vec<Staged>,
+    next: u64,
+}
+
+struct State {
+    closed: bool,
+    // Set while the process prepares to fork. The background thread sets `idle` once it has
+    // stopped draining.
+    paused: bool,
+    idle: bool,
+}
+
+// All live stagers, so that their background threads can be paused and restarted around a fork.
+static STAGERS: Mutex<Vec<Weak<Shared>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // The current thread's stage for each `Stager` it has logged to.
+    static STAGES: RefCell<Vec<(Weak<Shared>, StageRef)>> = const { RefCell::new(Vec::new()) };
+}
+
+// A background thread spawned ahead of its `Stager` by `LoggerBuilder::set_staging`, so that a
+// failure to spawn it is returned there. The thread exits if it is dropped unused.
+pub(crate) struct Worker {
+    start: mpsc::Sender<Arc<Shared>>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Worker {
+    pub(crate) fn spawn() -> io::Result<Worker> {
+        let (start, started) = mpsc::channel::<Arc<Shared>>();
+        let thread = thread::Builder::new()
+            .name(String::from("logosaurus-stager"))
+            .spawn(move || {
+                if let Ok(shared) = started.recv() {
+                    run(&shared);
+                }
+            })?;
+        Ok(Worker { start, thread })
+    }
+}
+
+// Per-thread staging of formatted records, written to the logger's output in order by a
+// background thread. See `LoggerBuilder::set_staging`.
+pub(crate) struct Stager {
+    shared: Arc<Shared>,
+}
+
+// The sequence number of a record being formatted. Records are written in sequence order, so
+// the record must be staged with `push`; if the ticket is dropped instead, for example because
+// formatting panicked, an empty record takes its place.
+pub(crate) struct Ticket<'a> {
+    stager: &'a Stager,
+    seq: u64,
+    pushed: bool,
+}
+
+impl Ticket<'_> {
+    pub(crate) fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    // Stages the formatted record on the current thread.
+    pub(crate) fn push(mut self, buf: &[u8], force_flush: bool) {
+        self.pushed = true;
+        self.stager.stage(self.seq, buf, force_flush);
+    }
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        if !self.pushed {
+            self.stager.stage(self.seq, &[], false);
+        }
+    }
+}
+
+impl Stager {
+    pub(crate) fn new<F, R>(
+        interval: Duration,
+        limit: usize,
+        sequence: Arc<AtomicU64>,
+        write: F,
+        report: R,
+        worker: Worker,
+    ) -> Stager
+    where
+        F: Fn(&[Staged]) -> io::Result<()> + Send + Sync + 'static,
+        R: Fn(io::Error) + Send + Sync + 'static,
+    {
+        let next = sequence.load(Ordering::Relaxed);
+        let shared = Arc::new(Shared {
+            next_seq: sequence,
+            stages: Mutex::new(Vec::new()),
+            write: Box::new(write),
+            report: Box::new(report),
+            draining: Mutex::new(Order {
+                held: Vec::new(),
+                next,
+            }),
+            state: Mutex::new(State {
+                closed: false,
+                paused: false,
+                idle: false,
+            }),
+            wake: Condvar::new(),
+            idle: Condvar::new(),
+            thread: Mutex::new(Some(worker.thread)),
+            synchronous: AtomicBool::new(false),
+            interval,
+            limit,
+        });
+        // The thread only exits early if the receiver is gone, which it is not.
+        let _ = worker.start.send(Arc::clone(&shared));
+        let mut stagers = lock(&STAGERS);
+        stagers.retain(|s| s.strong_count() > 0);
+        stagers.push(Arc::downgrade(&shared));
+        Stager { shared }
+    }
+
+    // Takes the sequence number of the next record. `fetch_add` wraps around on overflow.
+    pub(crate) fn ticket(&self) -> Ticket<'_> {
+        Ticket {
+            stager: self,
+            seq: self.shared.next_seq.fetch_add(1, Ordering::Relaxed),
+            pushed: false,
+        }
+    }
+
+    fn stage(&self, seq: u64, buf: &[u8], force_flush: bool) {
+        let stage = self.stage_ref();
+        let full = {
+            let mut stage = lock(&stage);
+            stage.records.push((seq, buf.to_vec(), force_flush));
+            stage.bytes += buf.len();
+            stage.bytes >= self.shared.limit
+        };
+        // A record that must be flushed is written right away, and so is a full stage, which
+        // bounds the memory used by each thread.
+        if full || force_flush || self.shared.synchronous.load(Ordering::Relaxed) {
+            self.shared.drain();
+        }
+    }
+
+    // Writes all staged records to the output.
+    pub(crate) fn drain(&self) {
+        self.shared.drain();
+    }
+
+    fn stage_ref(&self) -> StageRef {
+        let find = |stages: &mut Vec<(Weak<Shared>, StageRef)>| {
+            if let Some((_, stage)) = stages
+                .iter()
+                .find(|(shared, _)| Weak::as_ptr(shared) == Arc::as_ptr(&self.shared))
+            {
+                return Arc::clone(stage);
+            }
+            stages.retain(|(shared, _)| shared.strong_count() > 0);
+            let stage = Arc::new(Mutex::new(Stage::default()));
+            lock(&self.shared.stages).push(Arc::clone(&stage));
+            stages.push((Arc::downgrade(&self.shared), Arc::clone(&stage)));
+            stage
+        };
+        STAGES
+            .try_with(|stages| find(&mut stages.borrow_mut()))
+            .unwrap_or_else(|_| {
+                // The thread is exiting; stage the record without remembering the stage.
+                let stage = Arc::new(Mutex::new(Stage::default()));
+                lock(&self.shared.stages).push(Arc::clone(&stage));
+                stage
+            })
+    }
+}
+
+impl Shared {
+    // Collects the staged records of all threads and writes them in sequence order.
+    //
+    // Records are written up to the first missing sequence number, which belongs to a record
+    // that another thread is still formatting. The rest are held until that record is staged.
+    fn drain(&self) {
+        if let Err(e) = self.write_staged(false) {
+            (self.report)(e);
+        }
+    }
+
+    // Writes all staged and held records, regardless of missing sequence numbers.
+    fn drain_all(&self) {
+        if let Err(e) = self.write_staged(true) {
+            (self.report)(e);
+        }
+    }
+
+    fn write_staged(&self, all: bool) -> io::Result<()> {
+        // The fork gate is taken before `draining`, in the same order as a logging thread that
+        // drains from within `Logger::emit`. Taking it inside `draining` would deadlock with a
+        // pending fork, which blocks new readers of the gate.
+        let _gate = crate::fork::gate();
+        let mut order = lock(&self.draining);
+        let mut batch = mem::take(&mut order.held);
+        lock(&self.stages).retain(|stage| {
+            let mut s = lock(stage);
+            batch.append(&mut s.records);
+            s.bytes = 0;
+            drop(s);
+            // Stages of threads that have exited are removed.
+            Arc::strong_count(stage) > 1
+        });
+        let next = order.next;
+        batch.sort_unstable_by_key(|(seq, _, _)| seq.wrapping_sub(next));
+        let ready = if all {
+            batch.len()
+        } else {
+            batch
+                .iter()
+                .zip(0..)
+                .take_while(|((seq, _, _), i)| *seq == next.wrapping_add(*i))
+                .count()
+        };
+        order.held = batch.split_off(ready);
+        if let Some((seq, _, _)) = batch.last() {
+            order.next = seq.wrapping_add(1);
+        }
+        // Records in place of dropped tickets are empty.
+        batch.retain(|(_, buf, _)| !buf.is_empty());
+        if batch.is_empty() {
+            return Ok(());
+        }
+        (self.write)(&batch)
+    }
+}
+
+fn spawn(shared: Arc<Shared>) -> io::Result<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("logosaurus-stager"))
+        .spawn(move || run(&shared))
+}
+
+fn run(shared: &Shared) {
+    let mut state = lock(&shared.state);
+    while !state.closed {
+        if state.paused {
+            state.idle = true;
+            shared.idle.notify_all();
+            state = shared.wake.wait(state).unwrap_or_else(|e| e.into_inner());
+            continue;
+        }
+        state = shared
+            .wake
+            .wait_timeout(state, shared.interval)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+        if state.closed || state.paused {
+            continue;
+        }
+        drop(state);
+        shared.drain();
+        state = lock(&shared.state);
+    }
+}
+
+fn stagers() -> Vec<Arc<Shared>> {
+    lock(&STAGERS).iter().filter_map(Weak::upgrade).collect()
+}
+
+// Pauses the background thread of every stager and waits until none of them is draining. This
+// must be called before the fork gate is taken for writing, since a draining thread waits for
+// the gate.
+pub(crate) fn pause_all() {
+    for shared in stagers() {
+        let mut state = lock(&shared.state);
+        if state.closed || shared.synchronous.load(Ordering::Relaxed) {
+            continue;
+        }
+        state.paused = true;
+        shared.wake.notify_one();
+        while !state.idle {
+            state = shared.idle.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+// Resumes the background threads paused by `pause_all`. In a forked child the threads do not
+// exist, so `restart` starts new ones.
+pub(crate) fn resume_all(restart: bool) {
+    for shared in stagers() {
+        let mut state = lock(&shared.state);
+        if restart && !state.closed && !shared.synchronous.load(Ordering::Relaxed) {
+            // The old thread cannot be joined in the child, so its handle is leaked. If no
+            // thread can be started, the stager falls back to writing records synchronously.
+            let new = spawn(Arc::clone(&shared)).ok();
+            if new.is_none() {
+                shared.synchronous.store(true, Ordering::Relaxed);
+            }
+            std::mem::forget(std::mem::replace(&mut *lock(&shared.thread), new));
+        }
+        state.paused = false;
+        state.idle = false;
+        shared.wake.notify_one();
+    }
+}
+
+impl Drop for Stager {
+    fn drop(&mut self) {
+        lock(&self.shared.state).closed = true;
+        self.shared.wake.notify_one();
+        let thread = lock(&self.shared.thread).take();
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+        self.shared.drain_all();
+    }
+}
+
+fn lock<T: ?Sized>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stager() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let stager = {
+            let written = Arc::clone(&written);
+            Arc::new(Stager::new(
+                Duration::from_millis(1),
+                1024,
+                Arc::new(AtomicU64::new(0)),
+                move |batch| {
+                    let mut written = lock(&written);
+                    for (seq, buf, _) in batch {
+                        written.push((*seq, String::from_utf8(buf.clone()).unwrap()));
+                    }
+                    Ok(())
+                },
+                |e| panic!("{}", e),
+                Worker::spawn().unwrap(),
+            ))
+        };
+
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let stager = Arc::clone(&stager);
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        stager
+                            .ticket()
+                            .push(format!("{} {}", t, i).as_bytes(), false);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        stager.drain();
+
+        // Every record is written once, in sequence order, and each thread's records are in the
+        // order they were logged.
+        let written = lock(&written);
+        assert_eq!(2000, written.len());
+        assert!(written.windows(2).all(|w| w[0].0 < w[1].0));
+        for t in 0..4 {
+            let prefix = format!("{} ", t);
+            let got: Vec<usize> = written
+                .iter()
+                .filter_map(|(_, s)| s.strip_prefix(&prefix))
+                .map(|i| i.parse().unwrap())
+                .collect();
+            assert_eq!((0..500).collect::<Vec<_>>(), got);
+        }
+        // The stages of the exited threads are removed.
+        assert!(lock(&stager.shared.stages).is_empty());
+    }
+
+    #[test]
+    fn test_ticket() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let stager = {
+            let written = Arc::clone(&written);
+            Stager::new(
+                Duration::from_secs(3600),
+                1024,
+                Arc::new(AtomicU64::new(0)),
+                move |batch| {
+                    lock(&written).extend(batch.iter().map(|(seq, _, _)| *seq));
+                    Ok(())
+                },
+                |e| panic!("{}", e),
+                Worker::spawn().unwrap(),
+            )
+        };
+        // A record is held until the records numbered before it are staged, and a dropped
+        // ticket writes nothing.
+        let first = stager.ticket();
+        let second = stager.ticket();
+        stager.ticket().push(b"2", false);
+        stager.drain();
+        assert!(lock(&written).is_empty());
+        drop(second);
+        first.push(b"0", false);
+        stager.drain();
+        assert_eq!(vec![0, 2], *lock(&written));
+    }
+
+    #[test]
+    fn test_synchronous() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let stager = {
+            let written = Arc::clone(&written);
+            Stager::new(
+                Duration::from_secs(3600),
+                1024,
+                Arc::new(AtomicU64::new(0)),
+                move |batch| {
+                    lock(&written).extend(batch.iter().map(|(_, buf, _)| buf.clone()));
+                    Ok(())
+                },
+                |e| panic!("{}", e),
+                Worker::spawn().unwrap(),
+            )
+        };
+        // As in a forked child where the background thread could not be restarted.
+        stager.shared.synchronous.store(true, Ordering::Relaxed);
+        stager.ticket().push(b"one", false);
+        assert_eq!(vec![b"one".to_vec()], *lock(&written));
+    }
+}
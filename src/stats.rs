@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counts of records by level, returned by `Logger::stats`.
+///
+/// A record is emitted when it is written to the output, a sink, or a prefix route. It is
+/// dropped when it passes the level filters but is then discarded by a mute, a filter, sampling,
+/// a rate limit, or repeat suppression. Records below the level filters, or excluded by the
+/// target allowlist or denylist, are not counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    emitted: [u64; 5], // indexed by level, Error first
+    dropped: [u64; 5],
+}
+
+impl Stats {
+    /// Returns the number of records emitted at `level`.
+    pub fn emitted(&self, level: log::Level) -> u64 {
+        self.emitted[level as usize - 1]
+    }
+
+    /// Returns the number of records dropped at `level`.
+    pub fn dropped(&self, level: log::Level) -> u64 {
+        self.dropped[level as usize - 1]
+    }
+
+    /// Returns the number of records emitted at all levels.
+    pub fn total_emitted(&self) -> u64 {
+        self.emitted.iter().sum()
+    }
+
+    /// Returns the number of records dropped at all levels.
+    pub fn total_dropped(&self) -> u64 {
+        self.dropped.iter().sum()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Counters {
+    emitted: [AtomicU64; 5],
+    dropped: [AtomicU64; 5],
+}
+
+impl Counters {
+    pub(crate) fn emitted(&self, level: log::Level) {
+        self.emitted[level as usize - 1].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dropped(&self, level: log::Level) {
+        self.dropped[level as usize - 1].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Stats {
+        let load = |counts: &[AtomicU64; 5]| {
+            let mut out = [0; 5];
+            for (n, count) in out.iter_mut().zip(counts) {
+                *n = count.load(Ordering::Relaxed);
+            }
+            out
+        };
+        Stats {
+            emitted: load(&self.emitted),
+            dropped: load(&self.dropped),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The state of a logger's output, returned by `Logger::status`, for example for a health
+/// check that reports whether logging is working.
+#[derive(Clone, Debug)]
+pub struct Status {
+    /// The number of failed writes and flushes of the output.
+    ///
+    /// With an `AsyncWriter` set with `LoggerBuilder::set_async_handle`, this includes the
+    /// failed writes of its background thread. Such an error is counted again when it is
+    /// returned by the next write to the `AsyncWriter`.
+    pub errors: u64,
+    /// The most recent failed write or flush, and when it happened.
+    pub last_error: Option<(SystemTime, String)>,
+    /// When a record was last written to the output successfully. With `set_buffer`, records
+    /// count as written once the buffer is flushed, and with an `AsyncWriter`, once its
+    /// background thread has written them.
+    pub last_write: Option<SystemTime>,
+    /// The number of bytes of records written to the output, counted as for `last_write`.
+    pub bytes_written: u64,
+    /// The number of writes waiting in the queue of the `AsyncWriter` set with
+    /// `LoggerBuilder::set_async_handle`, if any.
+    pub queue_len: Option<usize>,
+}
+
+impl Status {
+    /// Reports whether the last write or flush of the output succeeded. This is also true if
+    /// nothing has been written yet.
+    pub fn is_healthy(&self) -> bool {
+        match (&self.last_error, self.last_write) {
+            (Some((failed, _)), Some(wrote)) => wrote >= *failed,
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+}
+
+// The counters behind `Status`, shared by a logger and its clones.
+#[derive(Default)]
+pub(crate) struct Health {
+    errors: AtomicU64,
+    last_error: Mutex<Option<(SystemTime, String)>>,
+    last_write: AtomicU64, // nanoseconds since the Unix epoch, or 0 if never
+    bytes_written: AtomicU64,
+}
+
+impl Health {
+    pub(crate) fn wrote(&self, n: usize) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        self.last_write.store(now, Ordering::Relaxed);
+        self.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn failed(&self, e: &io::Error) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some((SystemTime::now(), e.to_string()));
+    }
+
+    pub(crate) fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn status(&self, queue_len: Option<usize>) -> Status {
+        let last_write = match self.last_write.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(UNIX_EPOCH + Duration::from_nanos(nanos)),
+        };
+        Status {
+            errors: self.errors(),
+            last_error: self
+                .last_error
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+            last_write,
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            queue_len,
+        }
+    }
+}
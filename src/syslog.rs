@@ -0,0 +1,256 @@
+//! A sink that sends records to syslog, formatted per [RFC 5424]. Requires the `syslog` feature.
+//!
+//! A [`SyslogLogger`] can be installed on its own with `log::set_boxed_logger`, or attached to a
+//! [`Logger`] as an additional sink using [`Sinks`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use logosaurus::syslog::{Facility, SyslogLogger};
+//! use logosaurus::Logger;
+//!
+//! let logger = Logger::default();
+//! let syslog = SyslogLogger::udp("127.0.0.1:514")
+//!     .unwrap()
+//!     .set_facility(Facility::Local0)
+//!     .set_app_name("myprogram");
+//! logger.sinks().attach(syslog);
+//! logosaurus::init(logger).unwrap();
+//! ```
+//!
+//! [RFC 5424]: https://tools.ietf.org/html/rfc5424
+//! [`SyslogLogger`]: struct.SyslogLogger.html
+//! [`Logger`]: ../struct.Logger.html
+//! [`Sinks`]: ../struct.Sinks.html
+use std::env;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+
+/// Syslog facility codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Facility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    Authpriv = 10,
+    Ftp = 11,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+enum Transport {
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+/// Sends records to a syslog daemon. See the [module documentation](index.html).
+///
+/// Each record is sent as one RFC 5424 message, with the log level mapped to the syslog
+/// severity. Over TCP, messages are framed using octet counting ([RFC 6587]).
+///
+/// [RFC 6587]: https://tools.ietf.org/html/rfc6587
+pub struct SyslogLogger {
+    transport: Mutex<Transport>,
+    level: log::LevelFilter,
+    facility: Facility,
+    hostname: String,
+    app_name: String,
+    pid: u32,
+}
+
+impl SyslogLogger {
+    /// Connects to the local syslog daemon through the Unix datagram socket at `path`, usually
+    /// `/dev/log`.
+    #[cfg(unix)]
+    pub fn unix<P: AsRef<Path>>(path: P) -> io::Result<SyslogLogger> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(SyslogLogger::new(Transport::Unix(socket)))
+    }
+
+    /// Sends messages to a syslog server over UDP.
+    pub fn udp<A: ToSocketAddrs>(addr: A) -> io::Result<SyslogLogger> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        Ok(SyslogLogger::new(Transport::Udp(socket)))
+    }
+
+    /// Sends messages to a syslog server over TCP.
+    pub fn tcp<A: ToSocketAddrs>(addr: A) -> io::Result<SyslogLogger> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(SyslogLogger::new(Transport::Tcp(stream)))
+    }
+
+    fn new(transport: Transport) -> SyslogLogger {
+        SyslogLogger {
+            transport: Mutex::new(transport),
+            level: log::LevelFilter::Trace,
+            facility: Facility::User,
+            hostname: crate::host::hostname().unwrap_or_else(|| String::from("-")),
+            app_name: app_name().unwrap_or_else(|| String::from("-")),
+            pid: process::id(),
+        }
+    }
+
+    /// Set the allowed log level.
+    pub fn set_level(mut self, level: log::LevelFilter) -> SyslogLogger {
+        self.level = level;
+        self
+    }
+
+    /// Set the facility. Defaults to `Facility::User`.
+    pub fn set_facility(mut self, facility: Facility) -> SyslogLogger {
+        self.facility = facility;
+        self
+    }
+
+    /// Set the APP-NAME field. Defaults to the name of the executable.
+    pub fn set_app_name(mut self, app_name: &str) -> SyslogLogger {
+        self.app_name = String::from(app_name);
+        self
+    }
+
+    /// Set the HOSTNAME field. Defaults to the machine's hostname.
+    pub fn set_hostname(mut self, hostname: &str) -> SyslogLogger {
+        self.hostname = String::from(hostname);
+        self
+    }
+
+    fn format(&self, record: &log::Record, now: chrono::DateTime<chrono::Local>) -> String {
+        let pri = self.facility as u8 * 8 + severity(record.level());
+        let msg = record.args().to_string();
+        format!(
+            "<{}>1 {} {} {} {} - - {}",
+            pri,
+            now.to_rfc3339_opts(chrono::SecondsFormat::Micros, false),
+            header_field(&self.hostname, 255),
+            header_field(&self.app_name, 48),
+            self.pid,
+            msg.strip_suffix('\n').unwrap_or(&msg)
+        )
+    }
+
+    fn send(&self, msg: &str) -> io::Result<()> {
+        let mut transport = self.transport.lock().unwrap_or_else(|e| e.into_inner());
+        match &mut *transport {
+            #[cfg(unix)]
+            Transport::Unix(socket) => socket.send(msg.as_bytes()).map(|_| ()),
+            Transport::Udp(socket) => socket.send(msg.as_bytes()).map(|_| ()),
+            Transport::Tcp(stream) => stream.write_all(format!("{} {}", msg.len(), msg).as_bytes()),
+        }
+    }
+}
+
+impl log::Log for SyslogLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let _ = self.send(&self.format(record, chrono::Local::now()));
+    }
+
+    fn flush(&self) {
+        if let Transport::Tcp(stream) =
+            &mut *self.transport.lock().unwrap_or_else(|e| e.into_inner())
+        {
+            let _ = stream.flush();
+        }
+    }
+}
+
+// Maps a log level to a syslog severity.
+fn severity(level: log::Level) -> u8 {
+    match level {
+        log::Level::Error => 3,
+        log::Level::Warn => 4,
+        log::Level::Info => 6,
+        log::Level::Debug | log::Level::Trace => 7,
+    }
+}
+
+// Header fields must be non-empty printable ASCII without spaces, up to a maximum length.
+fn header_field(s: &str, max: usize) -> String {
+    let s: String = s
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max)
+        .collect();
+    if s.is_empty() {
+        String::from("-")
+    } else {
+        s
+    }
+}
+
+fn app_name() -> Option<String> {
+    let exe = env::current_exe().ok()?;
+    Some(exe.file_name()?.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_format() {
+        let syslog = SyslogLogger::new(Transport::Udp(UdpSocket::bind("127.0.0.1:0").unwrap()))
+            .set_facility(Facility::Local0)
+            .set_app_name("my prog")
+            .set_hostname("host1");
+        let now = chrono::Local.timestamp_opt(0, 0).unwrap();
+        let record = log::Record::builder()
+            .args(format_args!("hello\n"))
+            .level(log::Level::Warn)
+            .build();
+        let expect = format!(
+            "<132>1 {} host1 myprog {} - - hello",
+            now.to_rfc3339_opts(chrono::SecondsFormat::Micros, false),
+            process::id()
+        );
+        assert_eq!(expect, syslog.format(&record, now));
+    }
+
+    #[test]
+    fn test_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let syslog = SyslogLogger::udp(server.local_addr().unwrap()).unwrap();
+        log::Log::log(
+            &syslog,
+            &log::Record::builder()
+                .args(format_args!("hello"))
+                .level(log::Level::Error)
+                .build(),
+        );
+        let mut buf = [0; 1024];
+        let n = server.recv(&mut buf).unwrap();
+        let got = std::str::from_utf8(&buf[..n]).unwrap();
+        assert!(got.starts_with("<11>1 "));
+        assert!(got.ends_with(" - - hello"));
+    }
+}
@@ -0,0 +1,428 @@
+//! Utilities for testing log output.
+//!
+//! [`Buffer`] captures the output of a logger in memory, to check the records written by the
+//! code under test:
+//!
+//! ```
+//! use logosaurus::{testing, L_LEVEL};
+//!
+//! let (logger, buf) = testing::capture(|b| b.set_flags(L_LEVEL));
+//! logger.write_output(log::Level::Warn, "myapp", None, None, "low disk space");
+//! assert_eq!(vec!["WARN  low disk space"], buf.lines());
+//! buf.clear();
+//! ```
+//!
+//! [`golden`] renders a fixed set of records with a logger configuration and compares the
+//! output against a snapshot ("golden") file. This locks down an output format against
+//! accidental changes:
+//!
+//! ```no_run
+//! use logosaurus::{testing, L_SHORT_FILE, L_STD};
+//!
+//! #[test]
+//! fn test_log_format() {
+//!     testing::golden("tests/golden/format.txt", |b| {
+//!         b.set_flags(L_STD | L_SHORT_FILE).set_prefix("myapp: ")
+//!     });
+//! }
+//! ```
+//!
+//! Set the `LOGOSAURUS_UPDATE_GOLDEN` environment variable to write the current output to the
+//! snapshot file instead of comparing against it, for example to create the snapshot file.
+//!
+//! `Logger::bench_write` measures the cost of formatting and writing records with a logger's
+//! configuration and output, to compare flag, format and writer choices.
+//!
+//! [`Buffer`]: struct.Buffer.html
+//! [`golden`]: fn.golden.html
+use crate::context::Context;
+use crate::{fork, Logger, LoggerBuilder};
+use chrono::TimeZone;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// An in-memory output that can be inspected while a logger writes to it.
+///
+/// Clones share the same contents, so a clone can be kept for inspection after the original
+/// is moved into a `Logger`, including one installed with [`init`]. Writes never fail.
+///
+/// ```
+/// use logosaurus::testing::Buffer;
+/// use logosaurus::{Logger, L_NONE};
+///
+/// let buf = Buffer::new();
+/// let logger = Logger::builder().set_out(buf.clone()).set_flags(L_NONE).build();
+/// logger.write_output(log::Level::Info, "myapp", None, None, "hello");
+/// assert_eq!("hello\n", buf.contents());
+/// ```
+///
+/// [`init`]: ../fn.init.html
+#[derive(Clone, Debug, Default)]
+pub struct Buffer {
+    inner: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Buffer {
+    /// Returns an empty `Buffer`.
+    pub fn new() -> Buffer {
+        Buffer::default()
+    }
+
+    /// Returns the bytes written so far.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.lock().clone()
+    }
+
+    /// Returns the text written so far. Invalid UTF-8 is replaced with U+FFFD.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.lock()).into_owned()
+    }
+
+    /// Returns the lines written so far, without their line endings.
+    pub fn lines(&self) -> Vec<String> {
+        self.contents().lines().map(String::from).collect()
+    }
+
+    /// Reports whether nothing has been written since the buffer was created or cleared.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Discards the contents, for example between test cases that share a logger.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A `Logger` that writes to a [`Buffer`], as returned by [`capture`].
+///
+/// [`Buffer`]: struct.Buffer.html
+/// [`capture`]: fn.capture.html
+pub type CaptureLogger = Logger<Buffer>;
+
+/// Returns a logger configured by `configure` that writes to a new [`Buffer`], and the buffer.
+///
+/// [`Buffer`]: struct.Buffer.html
+pub fn capture<F>(configure: F) -> (CaptureLogger, Buffer)
+where
+    F: FnOnce(LoggerBuilder<Buffer>) -> LoggerBuilder<Buffer>,
+{
+    let buf = Buffer::new();
+    let logger = configure(Logger::builder().set_out(buf.clone())).build();
+    (logger, buf)
+}
+
+/// The environment variable that causes [`golden`] to update snapshot files.
+///
+/// [`golden`]: fn.golden.html
+pub const UPDATE_GOLDEN_ENV: &str = "LOGOSAURUS_UPDATE_GOLDEN";
+
+// A canned record: level, target, file, line, and message.
+type Record = (
+    log::Level,
+    &'static str,
+    Option<&'static str>,
+    Option<u32>,
+    &'static str,
+);
+
+// The canned records rendered by `golden`. They cover the levels, long and short file names,
+// missing locations, and messages that need escaping or newline handling.
+const RECORDS: &[Record] = &[
+    (
+        log::Level::Error,
+        "myapp",
+        Some("src/main.rs"),
+        Some(12),
+        "hello, world",
+    ),
+    (
+        log::Level::Warn,
+        "myapp::db",
+        Some("src/db/mod.rs"),
+        Some(340),
+        "retrying in 5s",
+    ),
+    (
+        log::Level::Info,
+        "hyper::client",
+        Some("/home/user/.cargo/registry/hyper/src/client.rs"),
+        Some(7),
+        "connected to \"example.com\"",
+    ),
+    (log::Level::Debug, "myapp", None, None, "no location"),
+    (
+        log::Level::Trace,
+        "myapp",
+        Some("src/main.rs"),
+        Some(1),
+        "ends with newline\n",
+    ),
+    (
+        log::Level::Info,
+        "myapp",
+        Some("src/main.rs"),
+        Some(2),
+        "multi\nline\tmessage",
+    ),
+    (log::Level::Info, "myapp", Some("src/main.rs"), Some(3), ""),
+    (
+        log::Level::Info,
+        "myapp",
+        Some("src/main.rs"),
+        Some(4),
+        "unicode: héllo, 世界",
+    ),
+];
+
+/// Renders the canned records with the logger configured by `configure` and compares the
+/// output against the snapshot file at `path`. Panics if the output differs from the snapshot.
+///
+/// The records are rendered at the fixed time 2020-10-02 21:27:03.123123 UTC, regardless of
+/// `L_UTC`, so that the output does not depend on the clock or the local time zone. They are
+/// rendered outside of the calling thread's context, so that its fields do not appear in the
+/// output. Output from a configured ID generator is not deterministic and should not be used in
+/// snapshots.
+///
+/// If the `LOGOSAURUS_UPDATE_GOLDEN` environment variable is set, the output is written to
+/// `path` instead, creating parent directories as needed. Otherwise a missing snapshot file is
+/// an error, so that a snapshot that was not checked in does not make the test pass.
+pub fn golden<P, F>(path: P, configure: F)
+where
+    P: AsRef<Path>,
+    F: FnOnce(LoggerBuilder<io::Sink>) -> LoggerBuilder<io::Sink>,
+{
+    let path = path.as_ref();
+    let got = render(&configure(Logger::builder().set_out(io::sink())).build());
+
+    if env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(path, &got)
+            .unwrap_or_else(|e| panic!("failed to write {}: {}", path.display(), e));
+        return;
+    }
+
+    let expect = fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!(
+            "failed to read {}: {} (set {} to create it)",
+            path.display(),
+            e,
+            UPDATE_GOLDEN_ENV
+        )
+    });
+    if got != expect {
+        let line = got
+            .lines()
+            .zip(expect.lines())
+            .position(|(g, e)| g != e)
+            .unwrap_or_else(|| got.lines().count().min(expect.lines().count()));
+        panic!(
+            "output does not match {} at line {} (set {} to update)\n\
+             --- expected ---\n{}--- got ---\n{}",
+            path.display(),
+            line + 1,
+            UPDATE_GOLDEN_ENV,
+            expect,
+            got
+        );
+    }
+}
+
+/// The per-record cost of logging, measured by `Logger::bench_write`.
+#[derive(Clone, Copy, Debug)]
+pub struct BenchReport {
+    /// The number of records measured.
+    pub records: usize,
+    /// The average time to format a record, including the header and fields.
+    pub format: Duration,
+    /// The average time to write a formatted record to the output.
+    pub write: Duration,
+}
+
+impl BenchReport {
+    /// Returns the average total time per record.
+    pub fn total(&self) -> Duration {
+        self.format + self.write
+    }
+}
+
+impl<W: io::Write + Send> Logger<W> {
+    /// Formats `n` records with the logger's configuration and writes them to its output, and
+    /// returns the average time spent per record in each step. The output is flushed at the
+    /// end, and the flush is included in the write time.
+    ///
+    /// The records are really written, so that the cost of the logger's writer and buffering
+    /// is measured: run this against a copy of the production configuration with a scratch
+    /// destination, not against a logger in use. The records are counted by `status` like
+    /// others. With `L_SEQUENCE` they are numbered from 0, without advancing the logger's
+    /// sequence. Staging, sinks, filters and rate limits are not involved.
+    ///
+    /// ```no_run
+    /// use logosaurus::{FileWriter, Logger, L_STD, L_SHORT_FILE};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let w = FileWriter::builder("/tmp/bench.log").build()?;
+    /// let logger = Logger::builder().set_out(w).set_flags(L_STD | L_SHORT_FILE).build();
+    /// let report = logger.bench_write(1000);
+    /// println!("{:?} per record", report.total());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn bench_write(&self, n: usize) -> BenchReport {
+        let msg = "benchmark record with a typical message length, value=42";
+        let mut format = Duration::ZERO;
+        let mut write = Duration::ZERO;
+        {
+            // Records staged before the benchmark are written first.
+            let _gate = fork::gate();
+            self.drain_staged();
+        }
+        for i in 0..n {
+            let start = Instant::now();
+            let meta = self.meta(
+                log::Level::Info,
+                "logosaurus::bench",
+                Some("src/bench.rs"),
+                Some(i as u32),
+            );
+            let mut buf = String::new();
+            self.push_line(&mut buf, &meta, crate::clock::now(), i as u64, msg);
+            let formatted = Instant::now();
+            let result = {
+                let _gate = fork::gate();
+                crate::lock(&self.out).write_record(buf.as_bytes(), false)
+            };
+            write += formatted.elapsed();
+            format += formatted - start;
+            self.report(result);
+        }
+        let start = Instant::now();
+        let result = {
+            let _gate = fork::gate();
+            crate::lock(&self.out).flush()
+        };
+        write += start.elapsed();
+        self.report(result);
+
+        let records = n.max(1) as u32;
+        BenchReport {
+            records: n,
+            format: format / records,
+            write: write / records,
+        }
+    }
+}
+
+// Renders the canned records with `logger` at a fixed time, in an empty context.
+fn render<W: io::Write + Send>(logger: &Logger<W>) -> String {
+    let now = chrono::Utc
+        .with_ymd_and_hms(2020, 10, 2, 21, 27, 3)
+        .unwrap()
+        + chrono::Duration::microseconds(123_123);
+    Context::default().scope(|| {
+        RECORDS
+            .iter()
+            .map(|&(level, target, file, line, msg)| {
+                logger.format_line(&logger.meta(level, target, file, line), now, msg)
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Format, L_LEVEL, L_STD};
+
+    #[test]
+    fn test_render() {
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_flags(L_STD | L_LEVEL)
+            .build();
+        let got = render(&logger);
+        assert_eq!(RECORDS.len(), got.lines().count() - 1); // one message has an inner newline
+        assert!(got.starts_with("ERROR 2020/10/02 21:27:03 hello, world\n"));
+
+        let logger = Logger::builder()
+            .set_out(io::sink())
+            .set_format(Format::Json)
+            .build();
+        let got = render(&logger);
+        assert!(got.starts_with("{\"time\":\"2020-10-02T21:27:03.123Z\""));
+
+        // The fields of the caller's context are left out.
+        let _field = crate::context::field("request", "42");
+        assert_eq!(got, render(&logger));
+    }
+
+    #[test]
+    fn test_golden_missing() {
+        // With the variable set, the snapshot would be written instead.
+        if env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+            return;
+        }
+        let path = "tests/golden/missing.txt";
+        assert!(std::panic::catch_unwind(|| golden(path, |b| b)).is_err());
+        assert!(!Path::new(path).exists());
+    }
+
+    #[test]
+    fn test_capture() {
+        let (logger, buf) = capture(|b| b.set_flags(L_LEVEL));
+        assert!(buf.is_empty());
+        logger.write_output(log::Level::Info, "", None, None, "one");
+        logger.write_output(log::Level::Warn, "", None, None, "two\nlines");
+        assert_eq!(vec!["INFO  one", "WARN  two", "lines"], buf.lines());
+        assert_eq!(b"INFO  one\nWARN  two\nlines\n".to_vec(), buf.bytes());
+
+        buf.clear();
+        assert!(buf.is_empty());
+        logger.write_output(log::Level::Error, "", None, None, "three");
+        assert_eq!("ERROR three\n", buf.contents());
+    }
+
+    #[test]
+    fn test_bench_write() {
+        let buf = Buffer::new();
+        let logger = Logger::builder()
+            .set_out(buf.clone())
+            .set_flags(L_LEVEL | crate::L_SEQUENCE)
+            .set_buffer(4096, crate::FlushPolicy::EveryNRecords(4))
+            .build();
+        let report = logger.bench_write(10);
+        assert_eq!(10, report.records);
+        assert_eq!(report.format + report.write, report.total());
+
+        // The records are written to the logger's output, without advancing its sequence.
+        let lines = buf.lines();
+        assert_eq!(10, lines.len());
+        assert!(lines[9].starts_with("#9 INFO  "), "{}", lines[9]);
+        assert_eq!(buf.bytes().len() as u64, logger.status().bytes_written);
+        buf.clear();
+        logger.write_output(log::Level::Info, "", None, None, "hi");
+        log::Log::flush(&logger);
+        assert_eq!("#0 INFO  hi\n", buf.contents());
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{Logger, TcpWriter, UdpWriter};
+use std::io;
+
+/// Returns a sink for the network destination described by `url`, so that the choice of sink
+/// can live in a configuration file as a single string. The sink can be attached to a
+/// `Logger` with `Sinks::attach`.
+///
+/// The supported URLs are:
+///
+///   * `tcp://host:port`: a `Logger` with the default configuration writing to a [`TcpWriter`].
+///   * `udp://host:port`: a `Logger` with the default configuration writing to a [`UdpWriter`].
+///   * `syslog+udp://host:port`, `syslog+tcp://host:port` and `syslog+unix:///path`: a
+///     `SyslogLogger`. These require the `syslog` feature.
+///
+/// Other schemes, such as `syslog+tls` or `gelf+udp`, return an error of kind `InvalidInput`.
+///
+/// ```no_run
+/// use logosaurus::Logger;
+///
+/// let logger = Logger::default();
+/// logger.sinks().attach(logosaurus::sink_from_url("tcp://logs.example.com:5000").unwrap());
+/// ```
+///
+/// [`TcpWriter`]: struct.TcpWriter.html
+/// [`UdpWriter`]: struct.UdpWriter.html
+pub fn sink_from_url(url: &str) -> io::Result<Box<dyn log::Log>> {
+    let (scheme, rest) = url
+        .split_once("://")
+        .ok_or_else(|| invalid(format!("missing scheme in {:?}", url)))?;
+    let scheme = scheme.to_ascii_lowercase();
+
+    if scheme == "syslog+unix" {
+        return syslog_unix(rest);
+    }
+
+    let addr = rest.strip_suffix('/').unwrap_or(rest);
+    if addr.is_empty() || addr.contains('/') {
+        return Err(invalid(format!("expected host:port in {:?}", url)));
+    }
+    match scheme.as_str() {
+        "tcp" => Ok(Box::new(
+            Logger::builder().set_out(TcpWriter::new(addr)?).build(),
+        )),
+        "udp" => Ok(Box::new(
+            Logger::builder().set_out(UdpWriter::new(addr)?).build(),
+        )),
+        "syslog+udp" | "syslog+tcp" => syslog(&scheme, addr),
+        _ => Err(invalid(format!("unsupported scheme {:?}", scheme))),
+    }
+}
+
+#[cfg(feature = "syslog")]
+fn syslog(scheme: &str, addr: &str) -> io::Result<Box<dyn log::Log>> {
+    use crate::syslog::SyslogLogger;
+
+    if scheme == "syslog+udp" {
+        Ok(Box::new(SyslogLogger::udp(addr)?))
+    } else {
+        Ok(Box::new(SyslogLogger::tcp(addr)?))
+    }
+}
+
+#[cfg(not(feature = "syslog"))]
+fn syslog(scheme: &str, _: &str) -> io::Result<Box<dyn log::Log>> {
+    Err(invalid(format!(
+        "scheme {:?} requires the syslog feature",
+        scheme
+    )))
+}
+
+#[cfg(all(unix, feature = "syslog"))]
+fn syslog_unix(path: &str) -> io::Result<Box<dyn log::Log>> {
+    Ok(Box::new(crate::syslog::SyslogLogger::unix(path)?))
+}
+
+#[cfg(not(all(unix, feature = "syslog")))]
+fn syslog_unix(_: &str) -> io::Result<Box<dyn log::Log>> {
+    Err(invalid(String::from(
+        "scheme \"syslog+unix\" requires the syslog feature on unix",
+    )))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+
+    #[test]
+    fn test_sink_from_url() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let url = format!("udp://{}/", socket.local_addr().unwrap());
+        let sink = sink_from_url(&url).unwrap();
+        sink.log(
+            &log::Record::builder()
+                .args(format_args!("hello"))
+                .level(log::Level::Info)
+                .build(),
+        );
+        let mut buf = [0; 128];
+        let n = socket.recv(&mut buf).unwrap();
+        assert!(buf[..n].ends_with(b" hello\n"));
+
+        for url in [
+            "127.0.0.1:5000",
+            "tcp://",
+            "tcp://127.0.0.1:5000/path",
+            "gelf+udp://127.0.0.1:12201",
+            "syslog+tls://127.0.0.1:6514",
+        ] {
+            let err = sink_from_url(url).err().unwrap();
+            assert_eq!(io::ErrorKind::InvalidInput, err.kind(), "{}", url);
+        }
+    }
+}
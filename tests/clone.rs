@@ -0,0 +1,27 @@
+use log::*;
+use logosaurus::testing::Buffer;
+use logosaurus::*;
+
+#[test]
+fn test_clone() {
+    let buf = Buffer::new();
+
+    let logger = Logger::builder()
+        .set_out(buf.clone())
+        .set_flags(L_LEVEL)
+        .build();
+    let handle = init(logger.clone()).unwrap();
+
+    info!("from the global logger");
+    logger.set_prefix("myprogram: ");
+    logger.write_output(Level::Warn, "", None, None, "from the handle");
+    handle.set_level(LevelFilter::Error);
+    info!("suppressed");
+    error!("after");
+
+    let expect = r"INFO  from the global logger
+myprogram: WARN  from the handle
+myprogram: ERROR after
+";
+    assert_eq!(expect, buf.contents());
+}
@@ -0,0 +1,21 @@
+use log::*;
+use logosaurus::testing::Buffer;
+use logosaurus::*;
+
+#[test]
+fn test_flush_guard() {
+    let buf = Buffer::new();
+    let w = AsyncWriter::new(buf.clone(), 16);
+
+    let logger = Logger::builder().set_out(w).set_flags(L_LEVEL).build();
+    let guard = init_with_guard(logger).unwrap();
+
+    info!("message0");
+    error!("message1");
+    drop(guard);
+
+    let expect = r"INFO  message0
+ERROR message1
+";
+    assert_eq!(expect, buf.contents());
+}
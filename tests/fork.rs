@@ -0,0 +1,78 @@
+use log::*;
+use logosaurus::testing::Buffer;
+use logosaurus::*;
+use std::sync::mpsc;
+use std::thread;
+#[cfg(unix)]
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+extern "C" {
+    fn fork() -> i32;
+    fn waitpid(pid: i32, status: *mut i32, options: i32) -> i32;
+    fn _exit(status: i32) -> !;
+}
+
+#[test]
+fn test_fork() {
+    let buf = Buffer::new();
+    let w = AsyncWriter::new(buf.clone(), 16);
+
+    let logger = Logger::builder().set_out(w).set_flags(L_LEVEL).build();
+    init(logger).unwrap();
+
+    info!("before");
+    fork::prepare_fork();
+    // The record logged before preparing is written out, and logging blocks until the fork is
+    // done.
+    assert_eq!("INFO  before\n", buf.contents());
+    let (tx, rx) = mpsc::channel();
+    let t = thread::spawn(move || {
+        tx.send("logging").unwrap();
+        info!("during");
+        tx.send("logged").unwrap();
+    });
+    assert_eq!("logging", rx.recv().unwrap());
+    assert!(rx.try_recv().is_err());
+    assert_eq!("INFO  before\n", buf.contents());
+    fork::parent_after_fork();
+    assert_eq!("logged", rx.recv().unwrap());
+    t.join().unwrap();
+
+    info!("after");
+    log::logger().flush();
+    let expect = r"INFO  before
+INFO  during
+INFO  after
+";
+    assert_eq!(expect, buf.contents());
+
+    // The background thread of a logger with staging is restarted in a forked child, so that
+    // records staged there are written without an explicit flush.
+    #[cfg(unix)]
+    {
+        let buf = Buffer::new();
+        let staged = Logger::builder()
+            .set_out(buf.clone())
+            .set_flags(L_NONE)
+            .set_staging(Duration::from_millis(1))
+            .unwrap()
+            .build();
+        fork::prepare_fork();
+        let pid = unsafe { fork() };
+        if pid == 0 {
+            fork::child_after_fork();
+            staged.write_output(Level::Info, "", None, None, "in child");
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while buf.is_empty() && Instant::now() < deadline {
+                thread::yield_now();
+            }
+            let code = if buf.contents() == "in child\n" { 0 } else { 1 };
+            unsafe { _exit(code) }
+        }
+        fork::parent_after_fork();
+        let mut status = -1;
+        assert_eq!(pid, unsafe { waitpid(pid, &mut status, 0) });
+        assert_eq!(0, status, "the child did not write its staged record");
+    }
+}
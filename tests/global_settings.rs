@@ -0,0 +1,27 @@
+use log::*;
+use logosaurus::testing::Buffer;
+use logosaurus::*;
+
+#[test]
+fn test_global_settings() {
+    let buf = Buffer::new();
+
+    assert_eq!(L_NONE, flags());
+    let logger = Logger::builder()
+        .set_out(buf.clone())
+        .set_flags(L_LEVEL)
+        .build();
+    init(logger).unwrap();
+
+    info!("before");
+    set_flags(L_LEVEL | L_MSG_PREFIX);
+    set_prefix("myprogram: ");
+    assert_eq!(L_LEVEL | L_MSG_PREFIX, flags());
+    assert_eq!("myprogram: ", prefix());
+    info!("after");
+
+    let expect = r"INFO  before
+INFO  myprogram: after
+";
+    assert_eq!(expect, buf.contents());
+}
@@ -0,0 +1,24 @@
+use logosaurus::testing::golden;
+use logosaurus::*;
+
+#[test]
+fn test_golden_text() {
+    golden("tests/golden/text.txt", |b| {
+        b.set_flags(L_STD | L_MICROSECONDS | L_SHORT_FILE | L_MSG_PREFIX)
+            .set_prefix("myapp: ")
+            .add_field("env", "prod")
+    });
+    golden("tests/golden/long_file.txt", |b| {
+        b.set_flags(L_LEVEL | L_LONG_FILE)
+    });
+}
+
+#[test]
+fn test_golden_json() {
+    golden("tests/golden/json.txt", |b| {
+        b.set_format(Format::Json)
+            .set_flags(L_STD | L_LONG_FILE)
+            .set_prefix("myapp")
+            .add_field("env", "prod")
+    });
+}
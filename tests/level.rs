@@ -1,16 +1,13 @@
 use log::*;
-use logosaurus::test_util::SyncWriter;
+use logosaurus::testing::Buffer;
 use logosaurus::*;
-use std::str;
-use std::sync::{Arc, Mutex};
 
 #[test]
 fn test_level_filter() {
-    let v = Mutex::new(Vec::new());
-    let arc = Arc::new(v);
-    let w = SyncWriter::new(Arc::clone(&arc));
+    let buf = Buffer::new();
 
-    let logger = Logger::builder(w)
+    let logger = Logger::builder()
+        .set_out(buf.clone())
         .set_level(log::LevelFilter::Warn)
         .set_flags(L_LEVEL)
         .build();
@@ -25,7 +22,5 @@ fn test_level_filter() {
     let expect = r"WARN  warn message
 ERROR error message
 ";
-    let got = arc.lock().unwrap();
-    let got = str::from_utf8(got.as_slice()).unwrap();
-    assert_eq!(expect, got);
+    assert_eq!(expect, buf.contents());
 }
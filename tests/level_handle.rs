@@ -0,0 +1,30 @@
+use log::*;
+use logosaurus::testing::Buffer;
+use logosaurus::*;
+
+#[test]
+fn test_level_handle() {
+    let buf = Buffer::new();
+
+    let logger = Logger::builder()
+        .set_out(buf.clone())
+        .set_level(log::LevelFilter::Info)
+        .set_flags(L_LEVEL)
+        .build();
+    let handle = init(logger).unwrap();
+    assert_eq!(log::LevelFilter::Info, log::max_level());
+
+    debug!("suppressed debug message");
+    handle.set_level(log::LevelFilter::Trace);
+    assert_eq!(log::LevelFilter::Trace, handle.level());
+    assert_eq!(log::LevelFilter::Trace, log::max_level());
+    debug!("debug message");
+    handle.set_level(log::LevelFilter::Warn);
+    info!("suppressed info message");
+    warn!("warn message");
+
+    let expect = r"DEBUG debug message
+WARN  warn message
+";
+    assert_eq!(expect, buf.contents());
+}
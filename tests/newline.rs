@@ -1,16 +1,15 @@
 use log::*;
-use logosaurus::test_util::SyncWriter;
+use logosaurus::testing::Buffer;
 use logosaurus::*;
-use std::str;
-use std::sync::{Arc, Mutex};
 
 #[test]
 fn test_newline() {
-    let v = Mutex::new(Vec::new());
-    let arc = Arc::new(v);
-    let w = SyncWriter::new(Arc::clone(&arc));
+    let buf = Buffer::new();
 
-    let logger = Logger::builder(w).set_flags(L_NONE).build();
+    let logger = Logger::builder()
+        .set_out(buf.clone())
+        .set_flags(L_NONE)
+        .build();
     init(logger).unwrap();
 
     warn!("message0");
@@ -22,7 +21,5 @@ message1
 
 message2
 ";
-    let got = arc.lock().unwrap();
-    let got = str::from_utf8(got.as_slice()).unwrap();
-    assert_eq!(expect, got);
+    assert_eq!(expect, buf.contents());
 }
@@ -0,0 +1,22 @@
+use log::*;
+use logosaurus::testing::Buffer;
+use logosaurus::*;
+
+#[test]
+fn test_no_newline() {
+    let buf = Buffer::new();
+
+    let logger = Logger::builder()
+        .set_out(buf.clone())
+        .set_flags(L_NONE)
+        .set_newline(false)
+        .build();
+    init(logger).unwrap();
+
+    warn!("message0");
+    warn!("message1\n\n");
+    warn!("message2\n");
+
+    let expect = "message0message1\n\nmessage2\n";
+    assert_eq!(expect, buf.contents());
+}
@@ -0,0 +1,27 @@
+use logosaurus::testing::Buffer;
+use logosaurus::*;
+use std::panic;
+
+#[test]
+fn test_log_panics() {
+    let buf = Buffer::new();
+
+    let logger = Logger::builder()
+        .set_out(buf.clone())
+        .set_format(Format::Json)
+        .set_flags(L_LEVEL)
+        .build();
+    init(logger).unwrap();
+    log_panics();
+
+    let line = line!() + 1;
+    let result = panic::catch_unwind(|| panic!("boom {}", 42));
+    assert!(result.is_err());
+
+    let expect = format!(
+        "{{\"level\":\"error\",\"msg\":\"thread 'test_log_panics' panicked at tests/panic_hook.rs:{}:41: boom 42\",\
+         \"panic\":true,\"location\":{{\"file\":\"tests/panic_hook.rs\",\"line\":{},\"column\":41}}}}\n",
+        line, line
+    );
+    assert_eq!(expect, buf.contents());
+}
@@ -0,0 +1,29 @@
+use logosaurus::testing::Buffer;
+use logosaurus::*;
+use std::io;
+use std::process::ExitCode;
+
+#[test]
+fn test_run() {
+    let buf = Buffer::new();
+
+    let logger = Logger::builder()
+        .set_out(buf.clone())
+        .set_flags(L_LEVEL)
+        .build();
+    init(logger).unwrap();
+
+    let code = run("ok", || -> Result<(), io::Error> { Ok(()) });
+    assert_eq!(ExitCode::SUCCESS, code);
+    let code = run("fail", || Err("disk full"));
+    assert_eq!(ExitCode::FAILURE, code);
+
+    let got = buf.lines();
+    assert_eq!(4, got.len());
+    assert_eq!("INFO  ok: started", got[0]);
+    assert!(got[1].starts_with("INFO  ok: finished in "));
+    assert!(got[1].ends_with(", exit code 0"));
+    assert_eq!("INFO  fail: started", got[2]);
+    assert!(got[3].starts_with("ERROR fail: failed in "));
+    assert!(got[3].ends_with(", exit code 1: disk full"));
+}
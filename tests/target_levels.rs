@@ -0,0 +1,31 @@
+use log::*;
+use logosaurus::testing::Buffer;
+use logosaurus::*;
+
+#[test]
+fn test_target_levels() {
+    let buf = Buffer::new();
+
+    let logger = Logger::builder()
+        .set_out(buf.clone())
+        .set_flags(L_LEVEL | L_LONG_FILE)
+        .set_target_levels("info,hyper=warn,myapp::db=trace")
+        .build();
+    init(logger).unwrap();
+
+    assert!(!log_enabled!(target: "hyper::client", Level::Info));
+    assert!(log_enabled!(target: "myapp::db", Level::Trace));
+
+    debug!(target: "myapp", "suppressed");
+    info!(target: "myapp", "info");
+    info!(target: "hyper::client", "suppressed");
+    warn!(target: "hyper::client", "warn");
+    trace!(target: "myapp::db::pool", "trace");
+
+    let got = buf.lines();
+    let got: Vec<&str> = got
+        .iter()
+        .map(|l| l.split(' ').next().unwrap_or(""))
+        .collect();
+    assert_eq!(vec!["INFO", "WARN", "TRACE"], got);
+}